@@ -0,0 +1,170 @@
+use std::{error::Error, fmt, fs, io::{self, Cursor, Read}, path::Path};
+
+use flate2::read::GzDecoder;
+use sevenz_rust::{Password, SevenZReader};
+use zip::ZipArchive;
+
+const ROM_EXTENSIONS: [&str; 2] = ["gb", "gbc"];
+
+// The largest Game Boy ROMs are 8 MiB, an archive can't
+// make the emulator decompress more than that
+const MAX_ROM_SIZE: u64 = 8 << 20;
+
+enum ArchiveFormat {
+    Zip,
+    SevenZip,
+    Gzip,
+}
+
+impl ArchiveFormat {
+    // Detected from the first bytes of the file, whatever its extension
+    fn detect(data: &[u8]) -> Option<ArchiveFormat> {
+        if data.starts_with(b"PK\x03\x04") || data.starts_with(b"PK\x05\x06") {
+            Some(ArchiveFormat::Zip)
+        } else if data.starts_with(&[b'7', b'z', 0xBC, 0xAF, 0x27, 0x1C]) {
+            Some(ArchiveFormat::SevenZip)
+        } else if data.starts_with(&[0x1F, 0x8B]) {
+            Some(ArchiveFormat::Gzip)
+        } else {
+            None
+        }
+    }
+}
+
+/// The names of the ROMs in a zip, 7z or gzip archive, in the order they
+/// are stored. A file that isn't an archive is its own only ROM
+pub fn archived_roms(path: &Path) -> Result<Vec<String>, Box<dyn Error>> {
+    let data = fs::read(path)?;
+
+    let names = match ArchiveFormat::detect(&data) {
+        None => vec![file_name(path)],
+        Some(ArchiveFormat::Zip) => {
+            let archive = ZipArchive::new(Cursor::new(data.as_slice()))?;
+            (0..archive.len())
+                .filter_map(|index| archive.name_for_index(index))
+                .filter(|name| is_rom(name))
+                .map(str::to_string)
+                .collect()
+        }
+        Some(ArchiveFormat::SevenZip) => {
+            let reader = SevenZReader::new(Cursor::new(data.as_slice()), data.len() as u64, Password::empty())?;
+            reader.archive().files.iter()
+                .filter(|entry| !entry.is_directory() && is_rom(entry.name()))
+                .map(|entry| entry.name().to_string())
+                .collect()
+        }
+        Some(ArchiveFormat::Gzip) => vec![gzip_name(&data, path)],
+    };
+    Ok(names)
+}
+
+/// Reads the ROM with this name in an archive, or its first ROM. A file
+/// that isn't an archive is read as is. The name of the ROM is returned
+/// with its data
+pub(crate) fn read_rom(path: &Path, name: Option<&str>) -> Result<(String, Vec<u8>), Box<dyn Error>> {
+    let data = fs::read(path)?;
+
+    let wanted = |entry: &str| name.map_or_else(|| is_rom(entry), |name| entry == name);
+    let not_found = || InvalidArchive::new("no Game Boy ROM found in the archive");
+
+    match ArchiveFormat::detect(&data) {
+        None => Ok((file_name(path), data)),
+        Some(ArchiveFormat::Zip) => {
+            let mut archive = ZipArchive::new(Cursor::new(data.as_slice()))?;
+            let name = (0..archive.len())
+                .filter_map(|index| archive.name_for_index(index))
+                .find(|name| wanted(name))
+                .ok_or_else(not_found)?
+                .to_string();
+            let rom = decompress(archive.by_name(&name)?, "corrupted zip data")?;
+            Ok((name, rom))
+        }
+        // The files can be compressed together, the ones
+        // before the ROM are decompressed to be skipped
+        Some(ArchiveFormat::SevenZip) => {
+            let mut reader = SevenZReader::new(Cursor::new(data.as_slice()), data.len() as u64, Password::empty())?;
+            let mut rom = None;
+            reader.for_each_entries(|entry, entry_reader| {
+                if entry.is_directory() || !wanted(entry.name()) {
+                    io::copy(entry_reader, &mut io::sink())?;
+                    return Ok(true)
+                }
+                rom = Some((entry.name().to_string(), decompress(entry_reader, "corrupted 7z data")));
+                Ok(false)
+            })?;
+
+            let (name, rom) = rom.ok_or_else(not_found)?;
+            Ok((name, rom?))
+        }
+        // A gzip file only holds one file
+        Some(ArchiveFormat::Gzip) => {
+            let rom = decompress(GzDecoder::new(data.as_slice()), "corrupted gzip data")?;
+            Ok((gzip_name(&data, path), rom))
+        }
+    }
+}
+
+fn decompress(reader: impl Read, corrupted: &'static str) -> Result<Vec<u8>, InvalidArchive> {
+    let mut rom = Vec::new();
+    reader.take(MAX_ROM_SIZE + 1)
+        .read_to_end(&mut rom)
+        .map_err(|_| InvalidArchive::new(corrupted))?;
+
+    if rom.len() as u64 > MAX_ROM_SIZE {
+        return Err(InvalidArchive::new("the ROM is larger than 8 MiB"))
+    }
+    Ok(rom)
+}
+
+fn is_rom(name: &str) -> bool {
+    Path::new(name).extension()
+        .is_some_and(|extension| ROM_EXTENSIONS.iter().any(|rom| extension.eq_ignore_ascii_case(rom)))
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().unwrap_or_default().to_string_lossy().to_string()
+}
+
+// The original name is optional in the header, the
+// archive is named after it with .gz otherwise
+fn gzip_name(data: &[u8], path: &Path) -> String {
+    const EXTRA: u8 = 0x04;
+    const NAME: u8 = 0x08;
+
+    let flags = data.get(3).copied().unwrap_or(0);
+    let mut offset = 10;
+
+    if flags & EXTRA != 0
+        && let Some(len) = data.get(offset..offset + 2) {
+        offset += 2 + u16::from_le_bytes([len[0], len[1]]) as usize;
+    }
+
+    if flags & NAME != 0
+        && let Some(name) = data.get(offset..)
+        && let Some(len) = name.iter().position(|&c| c == 0) {
+        // The name is in Latin-1
+        return name[..len].iter().map(|&c| c as char).collect()
+    }
+
+    path.file_stem().unwrap_or_default().to_string_lossy().to_string()
+}
+
+/// This error is returned when an archive can't be read
+#[derive(Debug)]
+pub struct InvalidArchive {
+    reason: &'static str,
+}
+
+impl InvalidArchive {
+    fn new(reason: &'static str) -> InvalidArchive {
+        InvalidArchive { reason }
+    }
+}
+
+impl fmt::Display for InvalidArchive {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid archive loaded: {}", self.reason)
+    }
+}
+
+impl Error for InvalidArchive {}
@@ -0,0 +1,66 @@
+use crate::{ColorMode, ppu::{XRES, YRES}, settings::FrameBlending};
+
+/// Blends each frame with the previous ones, after it's drawn. The bytes of
+/// the pixels are blended separately, so the formats with a 16-bit color or
+/// an index (`RGB565` and `Indexed`) are left untouched
+pub struct FrameBlender {
+    previous: Vec<u32>,
+}
+
+impl Default for FrameBlender {
+    fn default() -> FrameBlender {
+        FrameBlender::new()
+    }
+}
+
+impl FrameBlender {
+    pub fn new() -> FrameBlender {
+        FrameBlender { previous: Vec::new() }
+    }
+
+    /// The next frame isn't blended with the previous ones
+    pub fn reset(&mut self) {
+        self.previous.clear();
+    }
+
+    pub fn apply(&mut self, blending: FrameBlending, color_mode: ColorMode, framebuffer: &mut [u32]) {
+        if blending == FrameBlending::Off || matches!(color_mode, ColorMode::RGB565 | ColorMode::Indexed) {
+            self.reset();
+            return
+        }
+
+        // The packed formats fill whole words too
+        let words = XRES * YRES * color_mode.bytes_per_pixel() / 4;
+        let frame = &mut framebuffer[..words];
+
+        if self.previous.len() != words {
+            self.previous = frame.to_vec();
+            return
+        }
+
+        for (pixel, previous) in frame.iter_mut().zip(&mut self.previous) {
+            let current = pixel.to_ne_bytes();
+            let mut output = previous.to_ne_bytes();
+
+            for (output, current) in output.iter_mut().zip(current) {
+                *output = match blending {
+                    FrameBlending::Mix => (*output as u16 + current as u16).div_ceil(2) as u8,
+                    // The difference always shrinks, so that the pixel reaches its color
+                    FrameBlending::Ghosting(persistence) => {
+                        let weight = (persistence.clamp(0.0, 1.0) * 255.0) as i32;
+                        let difference = (*output as i32 - current as i32) * weight / 256;
+                        (current as i32 + difference) as u8
+                    }
+                    FrameBlending::Off => unreachable!(),
+                };
+            }
+
+            // Mixing only uses the last frame, ghosting accumulates the output
+            *previous = match blending {
+                FrameBlending::Mix => *pixel,
+                _ => u32::from_ne_bytes(output),
+            };
+            *pixel = u32::from_ne_bytes(output);
+        }
+    }
+}
@@ -1,56 +1,93 @@
 #![allow(non_contiguous_range_endpoints)]
 
-use std::{error::Error, fs, path::PathBuf};
+use std::{error::Error, fmt, fs, path::{Path, PathBuf}};
+
+use crate::{archive, state::{InvalidState, SaveState, StateReader, StateWriter}};
 
 mod header;
-use header::CartridgeHeader;
+use header::{CartridgeHeader, UnsupportedMbc};
 
 mod rom;
 mod mbc1;
 mod mbc2;
 mod mbc3;
+mod mbc5;
+mod gbs;
 
 use self::{
-    rom::ROM, mbc1::MBC1, mbc2::MBC2, mbc3::MBC3
+    rom::ROM, mbc1::MBC1, mbc2::MBC2, mbc3::MBC3, mbc5::MBC5, gbs::GbsRom
 };
 
+pub use gbs::GbsInfo;
+
 
 trait CartridgeInternals {
     fn read(&self, address: u16) -> u8;
     fn write(&mut self, address: u16, value: u8);
     fn need_save(&mut self) -> bool;
     fn save(&self, save_path: &PathBuf);
-    fn load_save(&mut self, save_path: &PathBuf);
+    fn load_save(&mut self, save_path: &PathBuf) -> Result<(), Box<dyn Error>>;
+
+    // Only GBS files have several tracks to choose from
+    fn select_track(&mut self, _track: u8) {}
+
+    // The banking registers are cleared on power on, the RAM is kept
+    fn reset(&mut self) {}
+
+    // The registers and the RAM, a ROM without MBC has nothing to save
+    fn save_state(&self, _state: &mut StateWriter) {}
+    fn load_state(&mut self, _state: &mut StateReader) -> Result<(), InvalidState> { Ok(()) }
 }
 
 pub struct Cartridge {
     _rom_size: u32,
     pub(crate) header: CartridgeHeader,
+    pub(crate) gbs_info: Option<GbsInfo>,
+    pub(crate) rom_name: String, // The file name of the ROM, inside its archive if any
     cart_internals: Box<dyn CartridgeInternals + Send>,
 }
 
 impl Cartridge {
-    pub fn load(path: &PathBuf) -> Result<Cartridge, Box<dyn Error>> {
-        let rom_data = fs::read(path)?;
+    /// Loads a ROM, or the ROM with this name in an archive, or its first one
+    pub fn load(path: &Path, rom_name: Option<&str>) -> Result<Cartridge, Box<dyn Error>> {
+        let (rom_name, rom_data) = archive::read_rom(path, rom_name)?;
         let rom_size = (rom_data.len() * 8) as u32;
 
         let header = CartridgeHeader::from_bytes(&rom_data)?;
 
         let cart_internals: Box<dyn CartridgeInternals + Send> = match header.cart_type {
             0 => Box::new(ROM::new(rom_data)),
-            0x1..0x4 => Box::new(MBC1::new(&header, rom_data)),
+            0x1..0x4 => Box::new(MBC1::new(&header, rom_data)?),
             0x5..0x7 => Box::new(MBC2::new(&header, rom_data)),
-            0x0F..=0x13 => Box::new(MBC3::new(&header, rom_data)),
-            _ => panic!("Incompatible MBC detected: {:X}", header.cart_type)
+            0x0F..=0x13 => Box::new(MBC3::new(&header, rom_data)?),
+            0x19..=0x1E => Box::new(MBC5::new(&header, rom_data)?),
+            cart_type => return Err(UnsupportedMbc::new(cart_type).into()),
         };
 
         Ok(Cartridge {
             _rom_size: rom_size,
             header,
+            gbs_info: None,
+            rom_name,
             cart_internals,
         })
     }
 
+    pub fn load_gbs(path: &Path) -> Result<Cartridge, Box<dyn Error>> {
+        let gbs_data = fs::read(path)?;
+
+        let (gbs, info) = GbsRom::new(&gbs_data)?;
+        let header = CartridgeHeader::for_gbs(&info, gbs.rom_bank_nb());
+
+        Ok(Cartridge {
+            _rom_size: (gbs_data.len() * 8) as u32,
+            header,
+            gbs_info: Some(info),
+            rom_name: path.file_name().unwrap_or_default().to_string_lossy().to_string(),
+            cart_internals: Box::new(gbs),
+        })
+    }
+
     pub fn read(&self, address: u16) -> u8 {
         self.cart_internals.read(address)
     }
@@ -63,11 +100,52 @@ impl Cartridge {
         self.cart_internals.save(save_path);
     }
 
-    pub fn load_save(&mut self, save_path: &PathBuf) {
-        self.cart_internals.load_save(save_path);
+    /// A missing save is left to be created, an invalid one is an error
+    pub fn load_save(&mut self, save_path: &PathBuf) -> Result<(), Box<dyn Error>> {
+        self.cart_internals.load_save(save_path)
     }
 
     pub fn need_save(&mut self) -> bool {
         self.cart_internals.need_save()
     }
+
+    pub fn reset(&mut self) {
+        self.cart_internals.reset();
+    }
+
+    /// The track is started by the driver of the GBS file
+    pub fn select_track(&mut self, track: u8) {
+        self.cart_internals.select_track(track);
+    }
+}
+
+impl SaveState for Cartridge {
+    fn save_state(&self, state: &mut StateWriter) {
+        self.cart_internals.save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), InvalidState> {
+        self.cart_internals.load_state(state)
+    }
+}
+
+/// This error is returned when the header or the save of
+/// a cartridge doesn't match its memory bank controller
+#[derive(Debug)]
+pub struct CartridgeError {
+    reason: &'static str,
 }
+
+impl CartridgeError {
+    fn new(reason: &'static str) -> CartridgeError {
+        CartridgeError { reason }
+    }
+}
+
+impl fmt::Display for CartridgeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid cartridge data: {}", self.reason)
+    }
+}
+
+impl Error for CartridgeError {}
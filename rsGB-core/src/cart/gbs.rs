@@ -0,0 +1,252 @@
+use std::{error::Error, fmt, path::PathBuf};
+
+use crate::{cart::CartridgeInternals, state::{InvalidState, StateReader, StateWriter}};
+
+const HEADER_SIZE: usize = 0x70;
+const TEXT_LEN: usize = 32;
+
+const BANK_SIZE: usize = 0x4000;
+
+// The driver is placed below the music data, which can't start earlier
+const MIN_LOAD_ADDRESS: u16 = 0x400;
+
+const VBLANK_VECTOR: usize = 0x40;
+const TIMER_VECTOR: usize = 0x50;
+const ENTRY_POINT: usize = 0x100;
+
+/// The information in the header of a GBS file
+#[derive(Debug, Clone)]
+pub struct GbsInfo {
+    pub title: String,
+    pub author: String,
+    pub copyright: String,
+
+    pub track_count: u8,
+    pub first_track: u8, // Starting at 0
+}
+
+struct GbsHeader {
+    info: GbsInfo,
+
+    load_address: u16,
+    init_address: u16,
+    play_address: u16,
+    stack_pointer: u16,
+
+    timer_modulo: u8,
+    timer_control: u8,
+}
+
+impl GbsHeader {
+    fn from_bytes(data: &[u8]) -> Result<GbsHeader, InvalidGbs> {
+        if data.len() < HEADER_SIZE || &data[0..3] != b"GBS" || data[3] != 1 {
+            return Err(InvalidGbs::new());
+        }
+
+        let read_u16 = |offset: usize| u16::from_le_bytes([data[offset], data[offset + 1]]);
+        let read_text = |offset: usize| {
+            let text = &data[offset..offset + TEXT_LEN];
+            let len = text.iter().position(|&c| c == 0).unwrap_or(TEXT_LEN);
+            String::from_utf8_lossy(&text[..len]).trim().to_string()
+        };
+
+        let track_count = data[0x04];
+        let first_track = data[0x05].max(1) - 1;
+        let load_address = read_u16(0x06);
+
+        if track_count == 0 || !(MIN_LOAD_ADDRESS..0x8000).contains(&load_address) {
+            return Err(InvalidGbs::new());
+        }
+
+        Ok(GbsHeader {
+            info: GbsInfo {
+                title: read_text(0x10),
+                author: read_text(0x30),
+                copyright: read_text(0x50),
+
+                track_count,
+                first_track: first_track.min(track_count - 1),
+            },
+
+            load_address,
+            init_address: read_u16(0x08),
+            play_address: read_u16(0x0A),
+            stack_pointer: read_u16(0x0C),
+
+            timer_modulo: data[0x0E],
+            timer_control: data[0x0F],
+        })
+    }
+
+    // Bit 2 of TAC selects the timer instead of the VBlank to call PLAY
+    fn timer_driven(&self) -> bool {
+        self.timer_control & 0b100 != 0
+    }
+}
+
+/// A GBS file is only the music code and data of a game. It's mapped like
+/// a ROM with a small driver program, which calls INIT with the selected
+/// track, then PLAY on each VBlank or timer interrupt
+pub struct GbsRom {
+    rom_data: Vec<u8>,
+    rom_bank_nb: usize,
+    rom_bank: usize,
+
+    ram: [u8; 0x2000],
+
+    track_offset: usize, // Where the driver loads the track number
+}
+
+impl GbsRom {
+    pub fn new(data: &[u8]) -> Result<(GbsRom, GbsInfo), InvalidGbs> {
+        let header = GbsHeader::from_bytes(data)?;
+
+        // The music data is at the load address, in a ROM
+        // with a power of two number of banks, like an MBC1
+        let load_address = header.load_address as usize;
+        let music = &data[HEADER_SIZE..];
+
+        let rom_bank_nb = (load_address + music.len()).div_ceil(BANK_SIZE).next_power_of_two().max(2);
+        let mut rom_data = vec![0; rom_bank_nb * BANK_SIZE];
+        rom_data[load_address..load_address + music.len()].copy_from_slice(music);
+
+        let track_offset = write_driver(&mut rom_data, &header);
+
+        let mut gbs = GbsRom {
+            rom_data,
+            rom_bank_nb,
+            rom_bank: 1,
+
+            ram: [0; 0x2000],
+
+            track_offset,
+        };
+        gbs.select_track(header.info.first_track);
+
+        Ok((gbs, header.info))
+    }
+
+    pub fn rom_bank_nb(&self) -> usize {
+        self.rom_bank_nb
+    }
+}
+
+impl CartridgeInternals for GbsRom {
+    fn read(&self, address: u16) -> u8 {
+        match address {
+            0x0000..0x4000 => self.rom_data[address as usize],
+            0x4000..0x8000 => self.rom_data[self.rom_bank << 14 | address as usize & 0x3FFF],
+            0xA000..0xC000 => self.ram[address as usize & 0x1FFF],
+            _ => 0xFF,
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        match address {
+            // The bank is selected like with an MBC1
+            0x2000..0x4000 => self.rom_bank = (value as usize).max(1) % self.rom_bank_nb,
+            0xA000..0xC000 => self.ram[address as usize & 0x1FFF] = value,
+            _ => (),
+        }
+    }
+
+    fn need_save(&mut self) -> bool { false }
+
+    fn save(&self, _save_path: &PathBuf) {}
+
+    fn load_save(&mut self, _save_path: &PathBuf) -> Result<(), Box<dyn Error>> { Ok(()) }
+
+    fn select_track(&mut self, track: u8) {
+        self.rom_data[self.track_offset] = track;
+    }
+
+    // The selected track is kept
+    fn reset(&mut self) {
+        self.rom_bank = 1;
+        self.ram = [0; 0x2000];
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.u8(self.rom_data[self.track_offset]);
+        state.u16(self.rom_bank as u16);
+        state.bytes(&self.ram);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), InvalidState> {
+        self.select_track(state.u8()?);
+        self.rom_bank = (state.u16()? as usize).max(1) % self.rom_bank_nb;
+        state.bytes(&mut self.ram)
+    }
+}
+
+// Writes the driver in the first bytes of the ROM, and returns the
+// offset of the track number in it.
+// The RST vectors jump to the same offset from the load address, the
+// interrupt vectors call PLAY, and the entry point sets up the APU,
+// timer and LCD before calling INIT and waiting for the interrupts
+fn write_driver(rom_data: &mut [u8], header: &GbsHeader) -> usize {
+    for vector in (0x00..VBLANK_VECTOR).step_by(8) {
+        let [low, high] = (header.load_address + vector as u16).to_le_bytes();
+        rom_data[vector..vector + 3].copy_from_slice(&[0xC3, low, high]); // JP load+n
+    }
+
+    let [play_low, play_high] = header.play_address.to_le_bytes();
+    for vector in (VBLANK_VECTOR..0x68).step_by(8) {
+        rom_data[vector] = 0xD9; // RETI
+    }
+    let play_vector = if header.timer_driven() { TIMER_VECTOR } else { VBLANK_VECTOR };
+    rom_data[play_vector..play_vector + 4].copy_from_slice(&[0xCD, play_low, play_high, 0xD9]); // CALL play, RETI
+
+    // Without a VBlank to wait for, the LCD is left off
+    let (interrupt, lcdc) = if header.timer_driven() { (0x04, 0x00) } else { (0x01, 0x80) };
+
+    let [sp_low, sp_high] = header.stack_pointer.to_le_bytes();
+    let [init_low, init_high] = header.init_address.to_le_bytes();
+
+    let mut code = vec![
+        0xF3,                  // DI
+        0x31, sp_low, sp_high, // LD SP,sp
+    ];
+
+    // LD A,value then LDH (register),A
+    for (register, value) in [
+        (0x26, 0x00), (0x26, 0x80), // Reset the APU
+        (0x24, 0x77), (0x25, 0xFF), // Full volume on both outputs
+        (0x05, header.timer_modulo), (0x06, header.timer_modulo), (0x07, header.timer_control & 0b111),
+        (0x40, lcdc),
+        (0xFF, interrupt),
+    ] {
+        code.extend([0x3E, value, 0xE0, register]);
+    }
+
+    let track_offset = ENTRY_POINT + code.len() + 1;
+    code.extend([
+        0x3E, 0x00,                 // LD A,track
+        0xCD, init_low, init_high,  // CALL init
+        0xAF, 0xE0, 0x0F,           // XOR A, LDH (IF),A
+        0xFB,                       // EI
+        0x76,                       // HALT
+        0x18, 0xFD,                 // JR back to the HALT
+    ]);
+
+    rom_data[ENTRY_POINT..ENTRY_POINT + code.len()].copy_from_slice(&code);
+    track_offset
+}
+
+/// This error is returned when the file isn't a valid GBS file
+#[derive(Debug)]
+pub struct InvalidGbs {}
+
+impl InvalidGbs {
+    fn new() -> InvalidGbs {
+        InvalidGbs {}
+    }
+}
+
+impl fmt::Display for InvalidGbs {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid GBS file loaded")
+    }
+}
+
+impl Error for InvalidGbs {}
@@ -1,5 +1,7 @@
 use std::{error::Error, fmt};
 
+use super::GbsInfo;
+
 static ROM_TYPES: [&str; 0x23] = [
     "ROM ONLY",
     "MBC1",
@@ -189,7 +191,7 @@ pub struct CartridgeHeader {
     pub(crate) lic_code: u8,
     _version: u8,
     _checksum: u8,
-    _global_checksum: u16,
+    pub(crate) global_checksum: u16,
 }
 
 impl CartridgeHeader {
@@ -238,10 +240,31 @@ impl CartridgeHeader {
             lic_code: data[0x14B],
             _version: data[0x14C],
             _checksum: checksum,
-            _global_checksum: u16::from_be_bytes(data[0x14E..0x150].try_into().unwrap()),
+            global_checksum: u16::from_be_bytes(data[0x14E..0x150].try_into().unwrap()),
         })
     }
 
+    /// GBS files have no cartridge header, they are
+    /// described as an MBC1 with 8 KiB of RAM
+    pub(crate) fn for_gbs(info: &GbsInfo, rom_bank_nb: usize) -> CartridgeHeader {
+        Self {
+            _entry: [0; 4],
+            _logo: [0; 0x30],
+            cgb_flag: 0,
+            title: info.title.clone(),
+            new_lic_code: [0; 2],
+            sgb_flag: 0,
+            cart_type: 0x02,
+            rom_size: rom_bank_nb.trailing_zeros() as u8 - 1,
+            ram_size: 0x02,
+            _dest_code: 0,
+            lic_code: 0,
+            _version: 0,
+            _checksum: 0,
+            global_checksum: 0,
+        }
+    }
+
     pub fn get_lic_name(&self) -> &str {
         let lic_code = self.lic_code as usize;
         if lic_code >= LIC_CODES.len() {
@@ -285,4 +308,25 @@ impl fmt::Display for InvalidCartridge {
     }
 }
 
-impl Error for InvalidCartridge {}
\ No newline at end of file
+impl Error for InvalidCartridge {}
+
+/// This error is returned when the cartridge uses
+/// a memory bank controller that isn't emulated
+#[derive(Debug)]
+pub struct UnsupportedMbc {
+    cart_type: u8,
+}
+
+impl UnsupportedMbc {
+    pub(super) fn new(cart_type: u8) -> UnsupportedMbc {
+        UnsupportedMbc { cart_type }
+    }
+}
+
+impl fmt::Display for UnsupportedMbc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Incompatible MBC detected: {:02X}", self.cart_type)
+    }
+}
+
+impl Error for UnsupportedMbc {}
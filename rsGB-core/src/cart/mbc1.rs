@@ -1,6 +1,6 @@
-use std::{fs::File, io::{Read, Write}, path::PathBuf};
+use std::{error::Error, fs::File, io::{Read, Write}, path::PathBuf};
 
-use crate::cart::{CartridgeInternals};
+use crate::{cart::{CartridgeError, CartridgeInternals}, state::{InvalidState, StateReader, StateWriter}};
 
 use super::CartridgeHeader;
 
@@ -44,7 +44,7 @@ pub struct MBC1 {
 }
 
 impl MBC1 {
-    pub fn new(header: &CartridgeHeader, rom_data: Vec<u8>) -> MBC1 {
+    pub fn new(header: &CartridgeHeader, rom_data: Vec<u8>) -> Result<MBC1, CartridgeError> {
         let battery = header.cart_type == 3;
         let need_save = false;
 
@@ -58,7 +58,7 @@ impl MBC1 {
             0x03 => 4,
             0x04 => 16,
             0x05 => 8,
-            _ => unreachable!(),
+            _ => return Err(CartridgeError::new("unknown RAM size")),
         };
 
         let mut ram_banks = Vec::with_capacity(ram_bank_nb as usize);
@@ -66,7 +66,7 @@ impl MBC1 {
             ram_banks.push([0; 0x2000]);
         }
 
-        MBC1 {
+        Ok(MBC1 {
             rom_data,
             rom_bank_nb,
             is_multicart,
@@ -83,7 +83,7 @@ impl MBC1 {
             
             battery,
             need_save,
-        }
+        })
     }
 }
 
@@ -128,7 +128,7 @@ impl CartridgeInternals for MBC1 {
                 } else {
                     self.bank2.min(self.ram_bank_nb as usize - 1)
                 };
-                self.ram_banks[ram_bank][address as usize % (1 << 12)]
+                self.ram_banks[ram_bank][address as usize % (1 << 13)]
             }
             _ => 0xFF,
         }
@@ -146,7 +146,7 @@ impl CartridgeInternals for MBC1 {
                 } else {
                     self.bank2.min(self.ram_bank_nb as usize - 1)
                 };
-                self.ram_banks[ram_bank][address as usize % (1 << 12)] = value;
+                self.ram_banks[ram_bank][address as usize % (1 << 13)] = value;
 
                 if self.battery {
                     self.need_save = true;
@@ -156,6 +156,37 @@ impl CartridgeInternals for MBC1 {
         }
     }
 
+    fn reset(&mut self) {
+        self.ram_enabled = false;
+        self.bank1 = 1;
+        self.bank2 = 0;
+        self.banking_mode = 0;
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.bool(self.ram_enabled);
+        state.u8(self.bank1 as u8);
+        state.u8(self.bank2 as u8);
+        state.u8(self.banking_mode);
+        for bank in &self.ram_banks {
+            state.bytes(bank);
+        }
+    }
+
+    // The registers are masked like when they are written
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), InvalidState> {
+        self.ram_enabled = state.bool()?;
+        self.bank1 = (state.u8()? as usize & 0x1F).max(1);
+        self.bank2 = state.u8()? as usize & 0b11;
+        self.banking_mode = state.u8()? & 1;
+        for bank in &mut self.ram_banks {
+            state.bytes(bank)?;
+        }
+
+        self.need_save = self.battery;
+        Ok(())
+    }
+
     fn need_save(&mut self) -> bool {
         let need_save = self.need_save;
         if need_save { self.need_save = false };
@@ -172,14 +203,14 @@ impl CartridgeInternals for MBC1 {
         file.write_all(&buffer).expect("Failed to write save file");
     }
 
-    fn load_save(&mut self, save_path: &PathBuf) {
+    fn load_save(&mut self, save_path: &PathBuf) -> Result<(), Box<dyn Error>> {
         // If the save file doesn't exist 
         // it will be created on next frame anyway
         if let Ok(mut file) = File::open(save_path) {
             let expected_len = self.ram_bank_nb as usize * 0x2000;
 
             let mut buffer = Vec::with_capacity(expected_len);
-            file.read_to_end(&mut buffer).unwrap();
+            file.read_to_end(&mut buffer)?;
             
             if buffer.len() == expected_len {
                 for i in 0..self.ram_bank_nb as usize {
@@ -191,9 +222,10 @@ impl CartridgeInternals for MBC1 {
                     self.ram_banks[i].copy_from_slice(slice);
                 }
             } else {
-                panic!("Failed to load the save data: incorrect size")
+                return Err(CartridgeError::new("the save doesn't match the RAM size").into())
             }
         }
+        Ok(())
     }
 }
 
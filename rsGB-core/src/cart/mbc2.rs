@@ -1,6 +1,6 @@
-use std::{fs::File, io::{Read, Write}};
+use std::{error::Error, fs::File, io::{Read, Write}};
 
-use crate::cart::{CartridgeInternals, header::CartridgeHeader};
+use crate::{cart::{CartridgeError, CartridgeInternals, header::CartridgeHeader}, state::{InvalidState, StateReader, StateWriter}};
 
 pub struct MBC2 {
     rom_data: Vec<u8>,
@@ -66,6 +66,26 @@ impl CartridgeInternals for MBC2 {
         }
     }
 
+    fn reset(&mut self) {
+        self.rom_bank_nb = 1;
+        self.ram_enabled = false;
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.u8(self.rom_bank_nb);
+        state.bool(self.ram_enabled);
+        state.bytes(&self.internal_ram);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), InvalidState> {
+        self.rom_bank_nb = (state.u8()? & 0xF).max(1);
+        self.ram_enabled = state.bool()?;
+        state.bytes(&mut self.internal_ram)?;
+
+        self.need_save = self.has_battery;
+        Ok(())
+    }
+
     fn need_save(&mut self) -> bool {
         let need_save = self.need_save;
         if need_save { self.need_save = false };
@@ -77,18 +97,19 @@ impl CartridgeInternals for MBC2 {
         file.write_all(&self.internal_ram).expect("Failed to write save file");
     }
 
-    fn load_save(&mut self, save_path: &std::path::PathBuf) {
+    fn load_save(&mut self, save_path: &std::path::PathBuf) -> Result<(), Box<dyn Error>> {
         if let Ok(mut file) = File::open(save_path) {
             let expected_len = self.internal_ram.len();
 
             let mut buffer = Vec::with_capacity(expected_len);
-            file.read_to_end(&mut buffer).unwrap();
+            file.read_to_end(&mut buffer)?;
             
             if buffer.len() == expected_len {
                 self.internal_ram.copy_from_slice(&buffer);
             } else {
-                panic!("Failed to load the save data: incorrect size")
+                return Err(CartridgeError::new("the save doesn't match the RAM size").into())
             }
         }
+        Ok(())
     }
 }
\ No newline at end of file
@@ -1,6 +1,6 @@
-use std::{fs::File, io::{Read, Write}};
+use std::{error::Error, fs::File, io::{Read, Write}};
 
-use crate::cart::{CartridgeInternals, header::CartridgeHeader};
+use crate::{cart::{CartridgeError, CartridgeInternals, header::CartridgeHeader}, state::{InvalidState, SaveState, StateReader, StateWriter}};
 
 mod rtc;
 use rtc::RTC;
@@ -25,7 +25,7 @@ pub struct MBC3 {
 }
 
 impl MBC3 {
-    pub fn new(header: &CartridgeHeader, rom_data: Vec<u8>) -> MBC3 {
+    pub fn new(header: &CartridgeHeader, rom_data: Vec<u8>) -> Result<MBC3, CartridgeError> {
         let rom_bank_nb = rom_data.len() / 0x4000; // A ROM bank is 32 KiB
         
         let ram_bank_nb: u8 = match header.ram_size {
@@ -34,7 +34,7 @@ impl MBC3 {
             0x03 => 4,
             0x04 => 16,
             0x05 => 8,
-            _ => unreachable!(),
+            _ => return Err(CartridgeError::new("unknown RAM size")),
         };
 
         let mut ram_banks = Vec::with_capacity(ram_bank_nb as usize);
@@ -45,7 +45,7 @@ impl MBC3 {
         let battery_present = header.cart_type == 0xF || header.cart_type == 0x10 || header.cart_type == 0x13;
         let timer_present = header.cart_type == 0x0F || header.cart_type == 0x10;
 
-        MBC3 {
+        Ok(MBC3 {
             rom_data,
             active_rom_bank: 1,
             rom_bank_nb,
@@ -60,7 +60,7 @@ impl MBC3 {
             battery_present,
             timer_present,
             need_save: false,
-        }
+        })
     }
 }
 
@@ -124,20 +124,63 @@ impl CartridgeInternals for MBC3 {
         }
     }
 
+    // The clock keeps running
+    fn reset(&mut self) {
+        self.active_rom_bank = 1;
+        self.ram_rtc_enabled = false;
+        self.mapped_memory = MappedMemory::RamBank(0);
+        self.previous_latch = 0xFF;
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.u8(self.active_rom_bank as u8);
+        state.bool(self.ram_rtc_enabled);
+        state.u8(match self.mapped_memory {
+            MappedMemory::RamBank(bank) => bank,
+            MappedMemory::RtcRegister(register) => register,
+        });
+        state.u8(self.previous_latch);
+
+        for bank in &self.ram_banks {
+            state.bytes(bank);
+        }
+        self.rtc.save_state(state);
+    }
+
+    // The registers are checked like when they are written
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), InvalidState> {
+        self.active_rom_bank = (state.u8()? & 0x7F).max(1) as usize;
+        self.ram_rtc_enabled = state.bool()?;
+        self.mapped_memory = match state.u8()? {
+            register @ 0x08..=0x0C if self.timer_present => MappedMemory::RtcRegister(register),
+            bank if bank < self.ram_banks.len() as u8 => MappedMemory::RamBank(bank),
+            _ => MappedMemory::RamBank(0),
+        };
+        self.previous_latch = state.u8()?;
+
+        for bank in &mut self.ram_banks {
+            state.bytes(bank)?;
+        }
+        self.rtc.load_state(state)?;
+
+        self.need_save = self.battery_present;
+        Ok(())
+    }
+
     fn need_save(&mut self) -> bool {
         let need_save = self.need_save;
         if need_save { self.need_save = false };
         need_save
     }
 
-    fn load_save(&mut self, save_path: &std::path::PathBuf) {
+    fn load_save(&mut self, save_path: &std::path::PathBuf) -> Result<(), Box<dyn Error>> {
         // If the save file doesn't exist 
         // it will be created on next frame anyway
         if let Ok(mut file) = File::open(save_path) {
             let expected_len = self.ram_banks.len() * 0x2000;
 
             let mut buffer = Vec::with_capacity(expected_len);
-            file.read_to_end(&mut buffer).unwrap();
+            file.read_to_end(&mut buffer)?;
             
             if buffer.len() == expected_len {
                 for i in 0..self.ram_banks.len() {
@@ -149,11 +192,12 @@ impl CartridgeInternals for MBC3 {
                     self.ram_banks[i].copy_from_slice(slice);
                 }
             } else {
-                panic!("Failed to load the save data: incorrect size")
+                return Err(CartridgeError::new("the save doesn't match the RAM size").into())
             }
         }
 
-        if self.timer_present { self.rtc.load(save_path); }
+        if self.timer_present { self.rtc.load(save_path)?; }
+        Ok(())
     }
 
     fn save(&self, save_path: &std::path::PathBuf) {
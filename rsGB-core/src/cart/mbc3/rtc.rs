@@ -1,4 +1,6 @@
-use std::{cell::RefCell, fs::File, io::{Read, Write}, path::PathBuf, time::{SystemTime, UNIX_EPOCH}};
+use std::{cell::RefCell, fs::File, io::{self, Read, Write}, path::PathBuf, time::{SystemTime, UNIX_EPOCH}};
+
+use crate::state::{InvalidState, SaveState, StateReader, StateWriter};
 
 pub struct RTC {
     live: RefCell<RtcState>,
@@ -75,22 +77,23 @@ impl RTC {
 
     }
 
-    pub fn load(&mut self, save_path: &PathBuf) {
+    pub fn load(&mut self, save_path: &PathBuf) -> io::Result<()> {
         let mut rtc_path = save_path.clone();
         rtc_path.set_extension("rtc");
         
         if let Ok(mut file) = File::open(&rtc_path) {
             let mut rtc_values = [0; 5];
-            file.read_exact(&mut rtc_values).unwrap();
+            file.read_exact(&mut rtc_values)?;
 
             let mut timestamp_buffer = [0; 8];
-            file.read_exact(&mut timestamp_buffer).unwrap();
+            file.read_exact(&mut timestamp_buffer)?;
 
             self.live.replace(RtcState::new(&rtc_values));
             self.last_update.replace(u64::from_le_bytes(timestamp_buffer));
         }
 
         self.update();
+        Ok(())
     }
 }
 
@@ -144,6 +147,30 @@ impl RTC {
     }
 }
 
+// The clock catches up on the time elapsed since the state was saved
+impl SaveState for RTC {
+    fn save_state(&self, state: &mut StateWriter) {
+        self.update();
+        state.bytes(&self.live.borrow().values());
+        state.bytes(&self.latched.values());
+        state.bool(self.latched_valid);
+        state.u64(*self.last_update.borrow());
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), InvalidState> {
+        let mut values = [0; 5];
+        state.bytes(&mut values)?;
+        self.live.replace(RtcState::new(&values));
+        state.bytes(&mut values)?;
+        self.latched = RtcState::new(&values);
+        self.latched_valid = state.bool()?;
+        self.last_update.replace(state.u64()?);
+
+        self.update();
+        Ok(())
+    }
+}
+
 #[derive(Debug, Default, Clone, Copy)]
 struct RtcState {
     s: u8,
@@ -0,0 +1,163 @@
+use std::{error::Error, fs::File, io::{Read, Write}, path::PathBuf};
+
+use crate::{cart::{CartridgeError, CartridgeInternals}, state::{InvalidState, StateReader, StateWriter}};
+
+use super::CartridgeHeader;
+
+const RAM_BANK_SIZE: usize = 0x2000;
+
+pub struct MBC5 {
+    rom_data: Vec<u8>,
+    rom_bank_nb: usize,
+
+    ram_enabled: bool,
+
+    rom_bank: usize, // 9 bits, written in two registers: bank 0 can be mapped
+    ram_bank: usize,
+
+    ram_banks: Vec<[u8; RAM_BANK_SIZE]>,
+
+    // for battery
+    battery: bool,
+    need_save: bool,
+}
+
+impl MBC5 {
+    pub fn new(header: &CartridgeHeader, rom_data: Vec<u8>) -> Result<MBC5, CartridgeError> {
+        let battery = header.cart_type == 0x1B || header.cart_type == 0x1E;
+
+        let rom_bank_nb = rom_data.len() / 0x4000; // A ROM bank is 16 KiB
+
+        let ram_bank_nb: u8 = match header.ram_size {
+            0x00 => 0,
+            0x02 => 1,
+            0x03 => 4,
+            0x04 => 16,
+            0x05 => 8,
+            _ => return Err(CartridgeError::new("unknown RAM size")),
+        };
+
+        let mut ram_banks = Vec::with_capacity(ram_bank_nb as usize);
+        for _ in 0..ram_bank_nb {
+            ram_banks.push([0; RAM_BANK_SIZE]);
+        }
+
+        Ok(MBC5 {
+            rom_data,
+            rom_bank_nb,
+
+            ram_enabled: false,
+
+            rom_bank: 1,
+            ram_bank: 0,
+
+            ram_banks,
+
+            battery,
+            need_save: false,
+        })
+    }
+}
+
+impl CartridgeInternals for MBC5 {
+    fn read(&self, address: u16) -> u8 {
+        match address {
+            ..=0x3FFF => self.rom_data[address as usize],
+            0x4000..=0x7FFF => {
+                let bank = self.rom_bank % self.rom_bank_nb;
+                self.rom_data[bank << 14 | address as usize & 0x3FFF]
+            }
+            0xA000..=0xBFFF => {
+                if !self.ram_enabled || self.ram_banks.is_empty() {
+                    return 0xFF;
+                }
+
+                let ram_bank = self.ram_bank % self.ram_banks.len();
+                self.ram_banks[ram_bank][address as usize & 0x1FFF]
+            }
+            _ => 0xFF,
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        match address {
+            ..0x2000 => self.ram_enabled = value == 0x0A,
+            0x2000..0x3000 => self.rom_bank = self.rom_bank & 0x100 | value as usize,
+            0x3000..0x4000 => self.rom_bank = self.rom_bank & 0xFF | (value as usize & 1) << 8,
+            0x4000..0x6000 => self.ram_bank = value as usize & 0xF,
+            0xA000..0xC000 if self.ram_enabled && !self.ram_banks.is_empty() => {
+                let ram_bank = self.ram_bank % self.ram_banks.len();
+                self.ram_banks[ram_bank][address as usize & 0x1FFF] = value;
+
+                if self.battery {
+                    self.need_save = true;
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.ram_enabled = false;
+        self.rom_bank = 1;
+        self.ram_bank = 0;
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.bool(self.ram_enabled);
+        state.u16(self.rom_bank as u16);
+        state.u8(self.ram_bank as u8);
+        for bank in &self.ram_banks {
+            state.bytes(bank);
+        }
+    }
+
+    // The registers are masked like when they are written
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), InvalidState> {
+        self.ram_enabled = state.bool()?;
+        self.rom_bank = state.u16()? as usize & 0x1FF;
+        self.ram_bank = state.u8()? as usize & 0xF;
+        for bank in &mut self.ram_banks {
+            state.bytes(bank)?;
+        }
+
+        self.need_save = self.battery;
+        Ok(())
+    }
+
+    fn need_save(&mut self) -> bool {
+        let need_save = self.need_save;
+        if need_save { self.need_save = false };
+        need_save
+    }
+
+    fn save(&self, save_path: &PathBuf) {
+        let buffer: Vec<u8> = self.ram_banks.iter()
+            .flatten()
+            .copied()
+            .collect();
+
+        let mut file = File::create(save_path).expect("Failed to create save file");
+        file.write_all(&buffer).expect("Failed to write save file");
+    }
+
+    fn load_save(&mut self, save_path: &PathBuf) -> Result<(), Box<dyn Error>> {
+        // If the save file doesn't exist
+        // it will be created on next frame anyway
+        if let Ok(mut file) = File::open(save_path) {
+            let expected_len = self.ram_banks.len() * RAM_BANK_SIZE;
+
+            let mut buffer = Vec::with_capacity(expected_len);
+            file.read_to_end(&mut buffer)?;
+
+            if buffer.len() == expected_len {
+                for (bank, data) in self.ram_banks.iter_mut().zip(buffer.chunks_exact(RAM_BANK_SIZE)) {
+                    bank.copy_from_slice(data);
+                }
+            } else {
+                return Err(CartridgeError::new("the save doesn't match the RAM size").into())
+            }
+        }
+        Ok(())
+    }
+}
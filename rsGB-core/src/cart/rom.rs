@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::{error::Error, path::PathBuf};
 
 use crate::cart::CartridgeInternals;
 
@@ -26,5 +26,5 @@ impl CartridgeInternals for ROM {
 
     fn save(&self, _save_path: &PathBuf) {}
 
-    fn load_save(&mut self, _save_path: &PathBuf) {}
+    fn load_save(&mut self, _save_path: &PathBuf) -> Result<(), Box<dyn Error>> { Ok(()) }
 }
\ No newline at end of file
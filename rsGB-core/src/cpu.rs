@@ -4,9 +4,10 @@ pub mod registers;
 mod stack;
 pub mod interrupts;
 mod fetch_data;
+mod dispatch;
 
 use crate::{
-    utils::{bit_set, BIT_IGNORE}, Devices
+    state::{InvalidState, SaveState, StateReader, StateWriter}, utils::{bit_set, BIT_IGNORE}, Devices
 };
 
 pub use instruction::*;
@@ -57,10 +58,14 @@ impl CPU {
         if !self.halted {
             self.fetch_instruction(dev);
             dev.incr_cycle(1);
-            self.fetch_data(dev);
 
-            self.execute(dev, self.curr_inst.in_type);
-            
+            dispatch::HANDLERS[self.curr_opcode as usize](self, dev);
+
+            // LD B,B is used as a software breakpoint by mooneye
+            // and many homebrew test ROMs
+            if self.curr_opcode == 0x40 {
+                dev.breakpoint = true;
+            }
         } else {
             dev.incr_cycle(1);
             if dev.bus.get_ie_register() & self.get_int_flags(&dev) & 0x1F != 0 {
@@ -84,8 +89,7 @@ impl CPU {
     }
 
     fn fetch_instruction(&mut self, dev: &mut Devices) {
-        self.curr_opcode = dev.bus.read(self.registers.pc);
-        self.curr_inst = Instruction::from_opcode(self.curr_opcode);
+        self.curr_opcode = dev.read(self.registers.pc);
 
         if self.halt_bug_triggered {
         // Do NOT increment PC this time.
@@ -111,11 +115,11 @@ impl CPU {
         (self.registers.f & 0b00010000) != 0
     }
 
-    fn check_cond(&self) -> bool {
+    fn check_cond(&self, cond: CondType) -> bool {
         let z = self.z_flag();
         let c = self.c_flag();
 
-        match self.curr_inst.cond {
+        match cond {
             CondType::NONE => true,
             CondType::Z => z,
             CondType::NZ => !z,
@@ -147,7 +151,59 @@ impl CPU {
     }
 
     fn set_int_flags(&mut self, dev: &mut Devices, value: u8) {
-        dev.bus.write(0xFF0F, value);
+        dev.write(0xFF0F, value);
+    }
+}
+
+impl SaveState for CPU {
+    fn save_state(&self, state: &mut StateWriter) {
+        let r = &self.registers;
+        for register in [r.a, r.f, r.b, r.c, r.d, r.e, r.h, r.l] {
+            state.u8(register);
+        }
+        state.u16(r.pc);
+        state.u16(r.sp);
+
+        state.u16(self.fetched_data);
+        state.u16(self.mem_dest);
+        state.bool(self.dest_is_mem);
+        state.u8(self.curr_opcode);
+
+        state.bool(self.halted);
+        state.bool(self.halt_bug_triggered);
+
+        state.bool(self.int_master_enabled);
+        state.u8(self.enabling_ime as u8);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), InvalidState> {
+        let r = &mut self.registers;
+        for register in [&mut r.a, &mut r.f, &mut r.b, &mut r.c, &mut r.d, &mut r.e, &mut r.h, &mut r.l] {
+            *register = state.u8()?;
+        }
+        r.pc = state.u16()?;
+        r.sp = state.u16()?;
+
+        self.fetched_data = state.u16()?;
+        self.mem_dest = state.u16()?;
+        self.dest_is_mem = state.bool()?;
+        self.curr_opcode = state.u8()?;
+        // Only shown by the debugger between the steps
+        if let Some(inst) = INSTRUCTIONS[self.curr_opcode as usize] {
+            self.curr_inst = inst;
+        }
+
+        self.halted = state.bool()?;
+        self.halt_bug_triggered = state.bool()?;
+
+        self.int_master_enabled = state.bool()?;
+        self.enabling_ime = match state.u8()? {
+            0 => EnableInterrupt::None,
+            1 => EnableInterrupt::Pending,
+            2 => EnableInterrupt::Activated,
+            _ => return Err(InvalidState::new("invalid interrupt enable state")),
+        };
+        Ok(())
     }
 }
 
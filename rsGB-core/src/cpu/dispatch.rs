@@ -0,0 +1,40 @@
+use super::{instruction::INSTRUCTIONS, proc::proc_cb, CPU};
+use crate::Devices;
+
+// Every opcode has its own handler, generated from the instruction table.
+// The instruction is a constant in each of them, so the addressing mode
+// and operation are resolved at compile time instead of on every step
+pub(super) type Handler = fn(&mut CPU, &mut Devices);
+
+fn handler<const OPCODE: u8>(cpu: &mut CPU, dev: &mut Devices) {
+    let inst = const { INSTRUCTIONS[OPCODE as usize] };
+    let Some(inst) = inst else {
+        panic!("Opcode {OPCODE:02X} not implemented!");
+    };
+
+    cpu.curr_inst = inst;
+    cpu.fetch_data(dev, inst);
+    cpu.execute(dev, inst);
+}
+
+// Builds a 256 entry table from a generic handler, one row of 16 opcodes at a time
+macro_rules! dispatch_table {
+    ($handler:ident) => {
+        dispatch_table!(@rows $handler; 0x00 0x10 0x20 0x30 0x40 0x50 0x60 0x70 0x80 0x90 0xA0 0xB0 0xC0 0xD0 0xE0 0xF0)
+    };
+    (@rows $handler:ident; $($row:literal)*) => {
+        [$(dispatch_table!(@row $handler; $row)),*].as_flattened()
+    };
+    (@row $handler:ident; $row:literal) => {
+        [
+            $handler::<{ $row }>, $handler::<{ $row + 0x1 }>, $handler::<{ $row + 0x2 }>, $handler::<{ $row + 0x3 }>,
+            $handler::<{ $row + 0x4 }>, $handler::<{ $row + 0x5 }>, $handler::<{ $row + 0x6 }>, $handler::<{ $row + 0x7 }>,
+            $handler::<{ $row + 0x8 }>, $handler::<{ $row + 0x9 }>, $handler::<{ $row + 0xA }>, $handler::<{ $row + 0xB }>,
+            $handler::<{ $row + 0xC }>, $handler::<{ $row + 0xD }>, $handler::<{ $row + 0xE }>, $handler::<{ $row + 0xF }>,
+        ]
+    };
+}
+
+pub(super) static HANDLERS: &[Handler] = dispatch_table!(handler);
+
+pub(super) static CB_HANDLERS: &[Handler] = dispatch_table!(proc_cb);
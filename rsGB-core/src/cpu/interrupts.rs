@@ -1,4 +1,4 @@
-use crate::Devices;
+use crate::{Devices, interconnect::OAMCorruption};
 
 use super::CPU;
 
@@ -13,12 +13,13 @@ pub enum InterruptType {
 
 impl CPU {
     fn interrupt_handle(&mut self, dev: &mut Devices, address: u16, interrupt_type: InterruptType) -> bool {
-        // Two internal NOPs
+        // Two internal NOPs, SP is decremented during the second one
         dev.incr_cycle(1);
+        dev.trigger_oam_bug(self.registers.sp, OAMCorruption::Write);
         dev.incr_cycle(1);
 
         // The two push operations
-        self.push(&mut dev.bus, (self.registers.pc >> 8) as u8);
+        self.push(dev, (self.registers.pc >> 8) as u8);
         dev.incr_cycle(1);
 
         let ie_register = dev.bus.get_ie_register();
@@ -29,7 +30,7 @@ impl CPU {
             return true
         }
 
-        self.push(&mut dev.bus, self.registers.pc as u8);
+        self.push(dev, self.registers.pc as u8);
         dev.incr_cycle(1);
 
         if (ie_register & it) == 0 { // The interrupt was cancelled
@@ -1,25 +1,28 @@
-use super::{instruction::*, CPU};
+use super::{dispatch::CB_HANDLERS, instruction::*, CPU};
 
-use crate::{Devices, cpu::EnableInterrupt, utils::*};
+use crate::{Devices, cpu::EnableInterrupt, interconnect::OAMCorruption, utils::*};
 
 impl CPU {
-    pub(super) fn execute(&mut self, dev: &mut Devices, instruction: InType) { // -> impl FnMut(&mut CPU, &mut Interconnect, &mut EmuContext) {
-        match instruction {
+    // Always inlined in the opcode handlers, where the instruction is
+    // a constant: only the code for this opcode is left
+    #[inline(always)]
+    pub(super) fn execute(&mut self, dev: &mut Devices, inst: Instruction) {
+        match inst.in_type {
             InType::NOP => proc_nop(self, dev),
-            InType::LD => proc_ld(self, dev),
-            InType::LDH => proc_ldh(self, dev),
-            InType::JP => proc_jp(self, dev),
-            InType::JR => proc_jr(self, dev),
-            InType::CALL => proc_call(self, dev),
-            InType::RST => proc_rst(self, dev),
-            InType::RET => proc_ret(self, dev),
-            InType::RETI => proc_reti(self, dev),
+            InType::LD => proc_ld(self, dev, inst),
+            InType::LDH => proc_ldh(self, dev, inst),
+            InType::JP => proc_jp(self, dev, inst),
+            InType::JR => proc_jr(self, dev, inst),
+            InType::CALL => proc_call(self, dev, inst),
+            InType::RST => proc_rst(self, dev, inst),
+            InType::RET => proc_ret(self, dev, inst),
+            InType::RETI => proc_reti(self, dev, inst),
             InType::DI => proc_di(self, dev),
-            InType::POP => proc_pop(self, dev),
-            InType::PUSH => proc_push(self, dev),
-            InType::INC => proc_inc(self, dev),
-            InType::DEC => proc_dec(self, dev),
-            InType::ADD => proc_add(self, dev),
+            InType::POP => proc_pop(self, dev, inst),
+            InType::PUSH => proc_push(self, dev, inst),
+            InType::INC => proc_inc(self, dev, inst),
+            InType::DEC => proc_dec(self, dev, inst),
+            InType::ADD => proc_add(self, dev, inst),
             InType::ADC => proc_adc(self, dev),
             InType::SUB => proc_sub(self, dev),
             InType::SBC => proc_sbc(self, dev),
@@ -27,7 +30,7 @@ impl CPU {
             InType::XOR => proc_xor(self, dev),
             InType::OR => proc_or(self, dev),
             InType::CP => proc_cp(self, dev),
-            InType::CB => proc_cb(self, dev),
+            InType::CB => CB_HANDLERS[self.fetched_data as usize](self, dev),
             InType::RLCA => proc_rlca(self, dev),
             InType::RRCA => proc_rrca(self, dev),
             InType::RLA => proc_rla(self, dev),
@@ -46,7 +49,7 @@ impl CPU {
 /* These are local helper functions and lookup tables */
 const REGISTER_LOOKUP: [RegType; 8] = [RegType::B, RegType::C, RegType::D, RegType::E, RegType::H, RegType::L, RegType::HL, RegType::A];
 
-fn decode_register(register: u8) -> Option<RegType>{
+const fn decode_register(register: u8) -> Option<RegType>{
     let reg = register as usize;
     if reg >= REGISTER_LOOKUP.len() {
         None
@@ -59,92 +62,105 @@ fn decode_register(register: u8) -> Option<RegType>{
 
 fn proc_nop(_cpu: &mut CPU, _dev: &mut Devices) {}
 
-fn proc_ld(cpu: &mut CPU, dev: &mut Devices) {
-    if cpu.curr_inst.mode == AddrMode::HL_SP {
+#[inline(always)]
+fn proc_ld(cpu: &mut CPU, dev: &mut Devices, inst: Instruction) {
+    if inst.mode == AddrMode::HL_SP {
         // Check if overflow from bit 3
-        let hflag: bool = ((cpu.registers.read(cpu.curr_inst.reg_2) as u8 & 0xF) + 
+        let hflag: bool = ((cpu.registers.read(inst.reg_2) as u8 & 0xF) + 
             (cpu.fetched_data as u8 & 0xF)) >= 0x10;
         
         // Check if overflow from bit 7
-        let (_, cflag) = (cpu.registers.read(cpu.curr_inst.reg_2) as u8)
+        let (_, cflag) = (cpu.registers.read(inst.reg_2) as u8)
             .overflowing_add(cpu.fetched_data as u8);
 
         cpu.set_flags(0, 0, hflag as u8, cflag as u8);
         let e: i16 = (cpu.fetched_data as u8).cast_signed() as i16;
-        cpu.registers.set(cpu.curr_inst.reg_1,
-            cpu.registers.read(cpu.curr_inst.reg_2).wrapping_add_signed(e));
+        cpu.registers.set(inst.reg_1,
+            cpu.registers.read(inst.reg_2).wrapping_add_signed(e));
         dev.incr_cycle(1);
     } else if cpu.dest_is_mem {
-        if cpu.curr_inst.reg_2.is_16bit() {
+        if inst.reg_2.is_16bit() {
             dev.incr_cycle(1);
-            dev.bus.write16(cpu.mem_dest, cpu.fetched_data);
+            dev.write16(cpu.mem_dest, cpu.fetched_data);
         } else {
-            dev.bus.write(cpu.mem_dest, cpu.fetched_data as u8);
+            dev.write(cpu.mem_dest, cpu.fetched_data as u8);
         }
         dev.incr_cycle(1);
     } else {
-        cpu.registers.set(cpu.curr_inst.reg_1, cpu.fetched_data);
-        if cpu.curr_inst.reg_1.is_16bit() && cpu.curr_inst.reg_2.is_16bit() {
+        cpu.registers.set(inst.reg_1, cpu.fetched_data);
+        if inst.reg_1.is_16bit() && inst.reg_2.is_16bit() {
             dev.incr_cycle(1);
         }
     }
 }
 
-fn proc_ldh(cpu: &mut CPU, dev: &mut Devices) {
-    if cpu.curr_inst.reg_1 == RegType::A {
+#[inline(always)]
+fn proc_ldh(cpu: &mut CPU, dev: &mut Devices, inst: Instruction) {
+    if inst.reg_1 == RegType::A {
         // Loading into register A
         cpu.registers.set(RegType::A, cpu.fetched_data);
     } else {
         // Loading A into a memory region
-        dev.bus.write(cpu.mem_dest, cpu.fetched_data as u8);
+        dev.write(cpu.mem_dest, cpu.fetched_data as u8);
         dev.incr_cycle(1);
     }
 }
 
-fn goto_addr(cpu: &mut CPU, dev: &mut Devices, address: u16, push_pc: bool) {
-    if cpu.check_cond() {
-        if cpu.curr_inst.mode != AddrMode::R {
+#[inline(always)]
+fn goto_addr(cpu: &mut CPU, dev: &mut Devices, inst: Instruction, address: u16, push_pc: bool) {
+    if cpu.check_cond(inst.cond) {
+        if push_pc {
+            // SP is decremented during the internal cycle
+            dev.trigger_oam_bug(cpu.registers.sp, OAMCorruption::Write);
+        }
+        if inst.mode != AddrMode::R {
             // We want to avoid increasing cycles for 0xE9 : JP HL
             dev.incr_cycle(1);
         }
         if push_pc {
-            cpu.push(&mut dev.bus, (cpu.registers.pc >> 8) as u8);
+            cpu.push(dev, (cpu.registers.pc >> 8) as u8);
             dev.incr_cycle(1);
-            cpu.push(&mut dev.bus, cpu.registers.pc as u8);
+            cpu.push(dev, cpu.registers.pc as u8);
             dev.incr_cycle(1);
         }
         cpu.registers.pc = address;
     }
 }
 
-fn proc_jp(cpu: &mut CPU, dev: &mut Devices) {
-    goto_addr(cpu, dev, cpu.fetched_data, false);
+#[inline(always)]
+fn proc_jp(cpu: &mut CPU, dev: &mut Devices, inst: Instruction) {
+    goto_addr(cpu, dev, inst, cpu.fetched_data, false);
 }
 
-fn proc_jr(cpu: &mut CPU, dev: &mut Devices) {
+#[inline(always)]
+fn proc_jr(cpu: &mut CPU, dev: &mut Devices, inst: Instruction) {
     let rel = cpu.fetched_data as i8;
     let addr = cpu.registers.pc.wrapping_add_signed(rel as i16);
-    goto_addr(cpu, dev, addr, false);
+    goto_addr(cpu, dev, inst, addr, false);
 }
 
-fn proc_call(cpu: &mut CPU, dev: &mut Devices) {
-    goto_addr(cpu, dev, cpu.fetched_data, true);
+#[inline(always)]
+fn proc_call(cpu: &mut CPU, dev: &mut Devices, inst: Instruction) {
+    goto_addr(cpu, dev, inst, cpu.fetched_data, true);
 }
 
-fn proc_rst(cpu: &mut CPU, dev: &mut Devices) {
-    goto_addr(cpu, dev, cpu.curr_inst.param as u16, true);
+#[inline(always)]
+fn proc_rst(cpu: &mut CPU, dev: &mut Devices, inst: Instruction) {
+    goto_addr(cpu, dev, inst, inst.param as u16, true);
 }
 
-fn proc_ret(cpu: &mut CPU, dev: &mut Devices) {
-    if cpu.curr_inst.cond != CondType::NONE {
+#[inline(always)]
+fn proc_ret(cpu: &mut CPU, dev: &mut Devices, inst: Instruction) {
+    if inst.cond != CondType::NONE {
         dev.incr_cycle(1);
     }
 
-    if cpu.check_cond() {
-        let low: u16 = cpu.pop(&mut dev.bus) as u16;
+    if cpu.check_cond(inst.cond) {
+        dev.trigger_oam_bug(cpu.registers.sp, OAMCorruption::ReadIncrease);
+        let low: u16 = cpu.pop(dev) as u16;
         dev.incr_cycle(1);
 
-        let high: u16 = cpu.pop(&mut dev.bus) as u16;
+        let high: u16 = cpu.pop(dev) as u16;
         dev.incr_cycle(1);
 
         cpu.registers.pc = (high << 8) | low;
@@ -153,9 +169,10 @@ fn proc_ret(cpu: &mut CPU, dev: &mut Devices) {
     }
 }
 
-fn proc_reti(cpu: &mut CPU, dev: &mut Devices) {
+#[inline(always)]
+fn proc_reti(cpu: &mut CPU, dev: &mut Devices, inst: Instruction) {
     cpu.int_master_enabled = true;
-    proc_ret(cpu, dev);
+    proc_ret(cpu, dev, inst);
 }
 
 fn proc_di(cpu: &mut CPU, _dev: &mut Devices) {
@@ -163,38 +180,45 @@ fn proc_di(cpu: &mut CPU, _dev: &mut Devices) {
     cpu.enabling_ime = EnableInterrupt::None;
 }
 
-fn proc_pop(cpu: &mut CPU, dev: &mut Devices) {
-    let low = cpu.pop(&mut dev.bus) as u16;
+#[inline(always)]
+fn proc_pop(cpu: &mut CPU, dev: &mut Devices, inst: Instruction) {
+    dev.trigger_oam_bug(cpu.registers.sp, OAMCorruption::ReadIncrease);
+    let low = cpu.pop(dev) as u16;
     dev.incr_cycle(1);
 
-    let high = cpu.pop(&mut dev.bus) as u16;
+    let high = cpu.pop(dev) as u16;
     dev.incr_cycle(1);
 
     let data = (high << 8) | low;
 
-    if cpu.curr_inst.reg_1 == RegType::AF {
+    if inst.reg_1 == RegType::AF {
         cpu.registers.set(RegType::AF, data & 0xFFF0);
     } else {
-        cpu.registers.set(cpu.curr_inst.reg_1, data);
+        cpu.registers.set(inst.reg_1, data);
     }
 }
 
-fn proc_push(cpu: &mut CPU, dev: &mut Devices) {
-    let high = (cpu.registers.read(cpu.curr_inst.reg_1) >> 8) as u8;
+#[inline(always)]
+fn proc_push(cpu: &mut CPU, dev: &mut Devices, inst: Instruction) {
+    let high = (cpu.registers.read(inst.reg_1) >> 8) as u8;
+    // SP is decremented during the internal cycle
+    dev.trigger_oam_bug(cpu.registers.sp, OAMCorruption::Write);
     dev.incr_cycle(1);
-    cpu.push(&mut dev.bus, high);
+    cpu.push(dev, high);
 
-    let low = cpu.registers.read(cpu.curr_inst.reg_1) as u8;
+    let low = cpu.registers.read(inst.reg_1) as u8;
     dev.incr_cycle(1);
-    cpu.push(&mut dev.bus, low);
+    cpu.push(dev, low);
 
     dev.incr_cycle(1);
 }
 
-fn proc_inc(cpu: &mut CPU, dev: &mut Devices) {
+#[inline(always)]
+fn proc_inc(cpu: &mut CPU, dev: &mut Devices, inst: Instruction) {
     let mut val = cpu.fetched_data;
     
-    if cpu.curr_inst.reg_1.is_16bit() && !cpu.dest_is_mem {
+    if inst.reg_1.is_16bit() && !cpu.dest_is_mem {
+        dev.trigger_oam_bug(val, OAMCorruption::Write);
         dev.incr_cycle(1);
         val = val.wrapping_add(1);
     } else {
@@ -202,21 +226,23 @@ fn proc_inc(cpu: &mut CPU, dev: &mut Devices) {
     }
 
     if cpu.dest_is_mem {
-        dev.bus.write(cpu.registers.read(cpu.curr_inst.reg_1), val as u8);
+        dev.write(cpu.registers.read(inst.reg_1), val as u8);
         dev.incr_cycle(1);
     } else {
-        cpu.registers.set(cpu.curr_inst.reg_1, val);
+        cpu.registers.set(inst.reg_1, val);
     }
 
-    if !cpu.curr_inst.reg_1.is_16bit() || cpu.dest_is_mem {
+    if !inst.reg_1.is_16bit() || cpu.dest_is_mem {
         cpu.set_flags((val == 0) as u8, 0, if (val & 0x0F) == 0 {1} else {0}, BIT_IGNORE);
     }
 }
 
-fn proc_dec(cpu: &mut CPU, dev: &mut Devices) {
+#[inline(always)]
+fn proc_dec(cpu: &mut CPU, dev: &mut Devices, inst: Instruction) {
     let mut val = cpu.fetched_data;
 
-    if cpu.curr_inst.reg_1.is_16bit() && !cpu.dest_is_mem {
+    if inst.reg_1.is_16bit() && !cpu.dest_is_mem {
+        dev.trigger_oam_bug(val, OAMCorruption::Write);
         dev.incr_cycle(1);
         val = val.wrapping_sub(1);
     } else {
@@ -224,48 +250,49 @@ fn proc_dec(cpu: &mut CPU, dev: &mut Devices) {
     }
 
     if cpu.dest_is_mem {
-        dev.bus.write(cpu.registers.read(cpu.curr_inst.reg_1), val as u8);
+        dev.write(cpu.registers.read(inst.reg_1), val as u8);
         dev.incr_cycle(1);
     } else {
-        cpu.registers.set(cpu.curr_inst.reg_1, val);
+        cpu.registers.set(inst.reg_1, val);
     }
 
-    if !cpu.curr_inst.reg_1.is_16bit() || cpu.curr_inst.mode == AddrMode::MR {
+    if !inst.reg_1.is_16bit() || inst.mode == AddrMode::MR {
         cpu.set_flags((val == 0) as u8, 1, ((val & 0x0F) == 0xF) as u8, BIT_IGNORE);
     }
 }
 
-fn proc_add(cpu: &mut CPU, dev: &mut Devices) {
+#[inline(always)]
+fn proc_add(cpu: &mut CPU, dev: &mut Devices, inst: Instruction) {
     let mut value:u16; 
 
-    if cpu.curr_inst.reg_1.is_16bit() {
-        value = cpu.registers.read(cpu.curr_inst.reg_1).wrapping_add(cpu.fetched_data);
+    if inst.reg_1.is_16bit() {
+        value = cpu.registers.read(inst.reg_1).wrapping_add(cpu.fetched_data);
         dev.incr_cycle(1);
     } else {
-        value = (cpu.registers.read(cpu.curr_inst.reg_1) as u8).wrapping_add(cpu.fetched_data as u8) as u16;
+        value = (cpu.registers.read(inst.reg_1) as u8).wrapping_add(cpu.fetched_data as u8) as u16;
     }
 
-    if cpu.curr_inst.reg_1 == RegType::SP {
+    if inst.reg_1 == RegType::SP {
         let e = (cpu.fetched_data as u8).cast_signed();
-        value = cpu.registers.read(cpu.curr_inst.reg_1).wrapping_add_signed(e as i16);
+        value = cpu.registers.read(inst.reg_1).wrapping_add_signed(e as i16);
         dev.incr_cycle(1);
     }
 
     let mut z = (value == 0) as u8;
-    let mut h = ((cpu.registers.read(cpu.curr_inst.reg_1) & 0xF) + (cpu.fetched_data & 0xF)) > 0xF;
-    let mut c = ((cpu.registers.read(cpu.curr_inst.reg_1) & 0xFF) + (cpu.fetched_data & 0xFF)) > 0xFF;
+    let mut h = ((cpu.registers.read(inst.reg_1) & 0xF) + (cpu.fetched_data & 0xF)) > 0xF;
+    let mut c = ((cpu.registers.read(inst.reg_1) & 0xFF) + (cpu.fetched_data & 0xFF)) > 0xFF;
 
-    if cpu.curr_inst.reg_1.is_16bit() && cpu.curr_inst.reg_1 != RegType::SP {
+    if inst.reg_1.is_16bit() && inst.reg_1 != RegType::SP {
         z = BIT_IGNORE;
-        h  = ((cpu.registers.read(cpu.curr_inst.reg_1) & 0xFFF) + (cpu.fetched_data & 0xFFF)) > 0xFFF;
-        (_, c) = cpu.registers.read(cpu.curr_inst.reg_1).overflowing_add(cpu.fetched_data)
+        h  = ((cpu.registers.read(inst.reg_1) & 0xFFF) + (cpu.fetched_data & 0xFFF)) > 0xFFF;
+        (_, c) = cpu.registers.read(inst.reg_1).overflowing_add(cpu.fetched_data)
     }
 
-    if cpu.curr_inst.reg_1 == RegType::SP {
+    if inst.reg_1 == RegType::SP {
         z = 0;
     }
 
-    cpu.registers.set(cpu.curr_inst.reg_1, value);
+    cpu.registers.set(inst.reg_1, value);
     cpu.set_flags(z, 0, h as u8, c as u8);
 }
 
@@ -329,14 +356,17 @@ fn proc_cp(cpu: &mut CPU, _dev: &mut Devices) {
     cpu.set_flags((n == 0) as u8, 1, h as u8, overflow as u8);
 }
 
-fn proc_cb(cpu: &mut CPU, dev: &mut Devices) {
-    let op = cpu.fetched_data as u8;
-    let register = decode_register(op & 0b111).unwrap();
+// Each CB-prefixed opcode gets its own handler in the dispatch table
+pub(super) fn proc_cb<const OP: u8>(cpu: &mut CPU, dev: &mut Devices) {
+    let register = const { decode_register(OP & 0b111).unwrap() };
 
-    let bit = (op >> 3) & 0b111;
-    let bit_op = (op >> 6) & 0b11;
+    let bit = (OP >> 3) & 0b111;
+    let bit_op = (OP >> 6) & 0b11;
 
-    let mut reg_val = cpu.registers.read_reg8(&dev.bus, register);
+    if register == RegType::HL {
+        dev.trigger_oam_bug(cpu.registers.read(RegType::HL), OAMCorruption::Read);
+    }
+    let mut reg_val = cpu.registers.read_reg8(dev, register);
 
     if register == RegType::HL {
         dev.incr_cycle(1);
@@ -348,11 +378,11 @@ fn proc_cb(cpu: &mut CPU, dev: &mut Devices) {
         }, 
         2 => { // RES
             reg_val &= !(1 << bit);
-            cpu.registers.set_reg8(&mut dev.bus, register, reg_val);
+            cpu.registers.set_reg8(dev, register, reg_val);
         },
         3 => { // SET
             reg_val |= 1 << bit;
-            cpu.registers.set_reg8(&mut dev.bus, register, reg_val);
+            cpu.registers.set_reg8(dev, register, reg_val);
         },
         0 => { // OTHER
             let cflag = cpu.c_flag() as u8;
@@ -365,7 +395,7 @@ fn proc_cb(cpu: &mut CPU, dev: &mut Devices) {
                         value |= 1;
                         set_c = true
                     }
-                    cpu.registers.set_reg8(&mut dev.bus, register, value);
+                    cpu.registers.set_reg8(dev, register, value);
                     cpu.set_flags((value == 0) as u8, 0, 0, set_c as u8);
                 },
                 1 => { // RRC
@@ -373,7 +403,7 @@ fn proc_cb(cpu: &mut CPU, dev: &mut Devices) {
                     reg_val >>= 1;
                     reg_val |= old << 7;
 
-                    cpu.registers.set_reg8(&mut dev.bus, register, reg_val);
+                    cpu.registers.set_reg8(dev, register, reg_val);
                     cpu.set_flags((reg_val == 0) as u8, 0, 0, old & 1);
                 }
                 2 => { // RL
@@ -381,7 +411,7 @@ fn proc_cb(cpu: &mut CPU, dev: &mut Devices) {
                     reg_val <<= 1;
                     reg_val |= cflag;
 
-                    cpu.registers.set_reg8(&mut dev.bus, register, reg_val);
+                    cpu.registers.set_reg8(dev, register, reg_val);
                     cpu.set_flags((reg_val == 0) as u8, 0, 0, ((old & 0x80) != 0) as u8);
                 },
                 3 => { // RR
@@ -389,36 +419,36 @@ fn proc_cb(cpu: &mut CPU, dev: &mut Devices) {
                     reg_val >>= 1;
                     reg_val |= cflag << 7;
 
-                    cpu.registers.set_reg8(&mut dev.bus, register, reg_val);
+                    cpu.registers.set_reg8(dev, register, reg_val);
                     cpu.set_flags((reg_val == 0) as u8, 0, 0, old & 1);
                 },
                 4 => { // SLA,
                     let old = reg_val;
                     reg_val <<= 1;
 
-                    cpu.registers.set_reg8(&mut dev.bus, register, reg_val);
+                    cpu.registers.set_reg8(dev, register, reg_val);
                     cpu.set_flags((reg_val == 0) as u8, 0, 0, ((old & 0x80) != 0) as u8);
                 }
                 5 => { // SRA,
                     let u = (reg_val.cast_signed() >> 1) as u8;
 
-                    cpu.registers.set_reg8(&mut dev.bus, register, u);
+                    cpu.registers.set_reg8(dev, register, u);
                     cpu.set_flags((u == 0) as u8, 0, 0, reg_val & 1);
                 } 
                 6 => { // SWAP
                     reg_val = ((reg_val & 0xF0) >> 4) | ((reg_val & 0x0F) << 4);
 
-                    cpu.registers.set_reg8(&mut dev.bus, register, reg_val);
+                    cpu.registers.set_reg8(dev, register, reg_val);
                     cpu.set_flags((reg_val == 0) as u8, 0, 0, 0);
                 },
                 7 => { // SRL
                     let old = reg_val;
                     reg_val >>= 1;
 
-                    cpu.registers.set_reg8(&mut dev.bus, register, reg_val);
+                    cpu.registers.set_reg8(dev, register, reg_val);
                     cpu.set_flags((reg_val == 0) as u8, 0, 0, old & 1);
                 },
-                _ => panic!("Unknown CB-prefixed command {op}")
+                _ => panic!("Unknown CB-prefixed command {OP}")
             }
         }
         _ => panic!("Invalid bit operator in CB")
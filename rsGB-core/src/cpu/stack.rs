@@ -1,20 +1,22 @@
 use super::CPU;
 
-use crate::Interconnect;
+use crate::{Devices, interconnect::OAMCorruption};
 
 impl CPU {
-    pub fn push(&mut self, bus: &mut Interconnect, value: u8) {
+    pub(crate) fn push(&mut self, dev: &mut Devices, value: u8) {
         self.registers.sp = self.registers.sp.wrapping_sub(1);
-        bus.write(self.registers.sp, value);
+        dev.write(self.registers.sp, value);
     }
 
-    pub fn push16(&mut self, bus: &mut Interconnect, value: u16) {
-        self.push(bus, (value >> 8) as u8);
-        self.push(bus, value as u8);
+    #[allow(dead_code)]
+    pub(crate) fn push16(&mut self, dev: &mut Devices, value: u16) {
+        self.push(dev, (value >> 8) as u8);
+        self.push(dev, value as u8);
     }
 
-    pub fn pop(&mut self, bus: &mut Interconnect) -> u8 {
-        let val = bus.read(self.registers.sp);
+    pub(crate) fn pop(&mut self, dev: &mut Devices) -> u8 {
+        dev.trigger_oam_bug(self.registers.sp, OAMCorruption::Read);
+        let val = dev.read(self.registers.sp);
         self.registers.sp = self.registers.sp.wrapping_add(1);
         val
     }
@@ -1,6 +1,6 @@
 use std::collections::HashMap;
 
-use crate::{cart::Cartridge, cpu::CPU, utils::VRAM};
+use crate::{cart::Cartridge, cpu::CPU, interconnect::Interconnect, utils::VRAM};
 
 pub struct DebugInfo<'dbg> {
     cpu: &'dbg CPU,
@@ -9,15 +9,17 @@ pub struct DebugInfo<'dbg> {
     vram: &'dbg VRAM,
 
     cartridge: &'dbg Cartridge,
+    bus: &'dbg Interconnect,
 }
 
 impl<'dbg> DebugInfo<'dbg> {
-    pub fn new(cpu: &'dbg CPU, vram_updated: bool, vram: &'dbg VRAM, cartridge: &'dbg Cartridge) -> DebugInfo<'dbg> {
+    pub fn new(cpu: &'dbg CPU, vram_updated: bool, bus: &'dbg Interconnect) -> DebugInfo<'dbg> {
         DebugInfo {
             cpu,
             vram_updated,
-            vram,
-            cartridge,
+            vram: &bus.vram,
+            cartridge: bus.cart.as_ref().unwrap(),
+            bus,
         }
     } 
 
@@ -30,6 +32,12 @@ impl<'dbg> DebugInfo<'dbg> {
         self.vram.as_chunks::<16>().0
     }
     
+    /// This function reads a byte from the memory map,
+    /// without any side effect on the emulation
+    pub fn read(&self, address: u16) -> u8 {
+        self.bus.read(address)
+    }
+
     pub fn current_instruction(&self) -> String {
         let cpu = self.cpu;
         cpu.curr_inst.to_str(&cpu).clone()
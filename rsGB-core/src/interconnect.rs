@@ -1,7 +1,7 @@
-use std::{cell::Cell, path::PathBuf};
+use std::{cell::Cell, path::{Path, PathBuf}};
 
 use crate::{
-    ColorMode, InputState, cart::Cartridge
+    ColorMode, InputState, cart::Cartridge, scheduler::{EventType, Scheduler}, settings::{AudioFilter, DmgPalette}, state::{InvalidState, SaveState, StateReader, StateWriter}
 };
 
 pub use crate::{
@@ -14,7 +14,7 @@ mod oam;
 
 use ram::*;
 use io::*;
-pub use oam::OAMEntry;
+pub use oam::{OAMEntry, OAMCorruption};
 
 // 0x0000 - 0x3FFF : ROM Bank 0
 // 0x4000 - 0x7FFF : ROM Bank 1 - Switchable
@@ -36,21 +36,38 @@ pub struct Interconnect {
     pub(crate) vram: [u8; 0x2000],
     ram: RAM,
     oam_ram: [OAMEntry; 40],
+    oam_scan_row: Option<u8>,
+    // The PPU locks the OAM and the VRAM a few dots before its mode changes
+    oam_early_lock: bool,
+    vram_early_lock: bool,
     io: IO,
     ie_register: u8,
+
+    pub(crate) scheduler: Scheduler,
+    timer_synced: u64, // The timestamp the timer was last updated at
+    apu_synced: u64,
 }
 
 impl Interconnect {
     pub fn new(color_mode: ColorMode) -> Interconnect {
-        Interconnect { 
+        let mut bus = Interconnect { 
             cart: None,
             vram_updated: Cell::new(false),
             vram: [0; 0x2000],
             ram: RAM::new(),
             oam_ram: [OAMEntry::new(); 40],
+            oam_scan_row: None,
+            oam_early_lock: false,
+            vram_early_lock: false,
             io: IO::new(color_mode),
             ie_register: 0,
-        }
+
+            scheduler: Scheduler::new(),
+            timer_synced: 0,
+            apu_synced: 0,
+        };
+        bus.schedule_timer();
+        bus
     }
 
     pub fn set_cart(&mut self, cart: Cartridge) {
@@ -64,7 +81,13 @@ impl Interconnect {
             0x0000..0x8000 => self.cart.as_ref().unwrap().read(address),
 
             // Char/Map Data
-            0x8000..0xA000 => self.vram[(address - 0x8000) as usize],
+            0x8000..0xA000 => {
+                if self.vram_locked() {
+                    0xFF
+                } else {
+                    self.vram_read(address)
+                }
+            }
 
             // Cartridge RAM
             0xA000..0xC000 => self.cart.as_ref().unwrap().read(address),
@@ -77,7 +100,7 @@ impl Interconnect {
 
             // OAM
             0xFE00..0xFEA0 => {
-                if self.io.dma_transferring() {
+                if self.io.dma_transferring() || self.oam_locked() {
                     0xFF
                 } else {
                     let sprite_index = ((address - 0xFE00)/4) as usize;
@@ -101,12 +124,18 @@ impl Interconnect {
     }
 
     pub fn write(&mut self, address: u16, value: u8) {
+        self.trigger_oam_bug(address, OAMCorruption::Write);
+        self.catch_up(address);
+
         // ROM only for now
         match address {
             0x0000..0x8000 => self.cart.as_mut().unwrap().write(address, value),
 
            // Char/Map Data
             0x8000..0xA000 => {
+                if self.vram_write_locked() {
+                    return
+                }
                 self.vram_updated.replace(true);
                 self.vram[(address - 0x8000) as usize] = value;
             }
@@ -122,7 +151,7 @@ impl Interconnect {
 
             // OAM
             0xFE00..0xFEA0 => {
-                if self.io.dma_transferring() {
+                if self.io.dma_transferring() || self.oam_write_locked() {
                     return
                 } else {
                     let sprite_index = ((address - 0xFE00)/4) as usize;
@@ -143,6 +172,45 @@ impl Interconnect {
             // CPU Enable Register
             0xFFFF => self.ie_register = value,
         }
+
+        match address {
+            0xFF04..=0xFF07 => self.schedule_timer(),
+            0xFF46 => self.scheduler.schedule_in(EventType::Dma, 4),
+            _ => (),
+        }
+    }
+
+    /// This function is used by the PPU to read the VRAM,
+    /// bypassing the lock applied to the CPU during mode 3
+    pub fn vram_read(&self, address: u16) -> u8 {
+        self.vram[(address - 0x8000) as usize]
+    }
+
+    /// The CPU can't access the VRAM while the PPU
+    /// is drawing pixels (mode 3)
+    fn vram_locked(&self) -> bool {
+        self.io.lcd.enabled() && (self.io.lcd.mode() == 3 || self.vram_early_lock)
+    }
+
+    /// The CPU can't access the OAM while the PPU
+    /// is scanning it or drawing pixels (modes 2 and 3)
+    fn oam_locked(&self) -> bool {
+        self.io.lcd.enabled() && (self.io.lcd.mode() >= 2 || self.oam_early_lock)
+    }
+
+    /// The writes aren't locked early, only once the mode has changed
+    fn vram_write_locked(&self) -> bool {
+        self.io.lcd.enabled() && self.io.lcd.mode() == 3
+    }
+
+    /// The OAM is writable again for one M-cycle at the end of mode 2,
+    /// when the VRAM gets locked early
+    fn oam_write_locked(&self) -> bool {
+        match self.io.lcd.mode() {
+            2 => self.io.lcd.enabled() && !self.vram_early_lock,
+            3 => self.io.lcd.enabled(),
+            _ => false,
+        }
     }
 
     pub fn write16(&mut self, address: u16, value: u16) {
@@ -158,17 +226,69 @@ impl Interconnect {
         self.io.request_interrupt(interrupt);
     }
 
-    /// This function ticks all the devices that tick once
-    /// per clock cycle, like the timer.
-    pub fn tick_t(&mut self) {
-        self.io.tick_timer();
-        self.io.tick_apu();
+    /// Brings the timer or the APU up to date before
+    /// one of their registers is accessed
+    pub fn catch_up(&mut self, address: u16) {
+        match address {
+            // Resetting DIV can step the frame sequencer
+            0xFF04 => {
+                self.sync_timer();
+                self.sync_apu();
+            }
+            0xFF05..=0xFF07 => self.sync_timer(),
+            0xFF10..0xFF40 => self.sync_apu(),
+            _ => (),
+        }
+    }
+
+    /// Handles the events of the devices on the bus,
+    /// `timestamp` is the time the event was scheduled at
+    pub fn handle_event(&mut self, event: EventType, timestamp: u64) {
+        match event {
+            EventType::Timer => {
+                self.sync_timer();
+                self.schedule_timer();
+            }
+            EventType::FrameSequencer => {
+                self.sync_apu();
+                self.io.step_frame_sequencer();
+                self.scheduler.schedule(EventType::FrameSequencer, timestamp + 0x2000);
+            }
+            EventType::Dma => {
+                self.tick_dma();
+                if self.io.dma_active() {
+                    self.scheduler.schedule(EventType::Dma, timestamp + 4);
+                }
+            }
+            EventType::Ppu | EventType::AudioOutput => unreachable!(),
+        }
+    }
+
+    fn sync_timer(&mut self) {
+        let now = self.scheduler.now();
+        self.io.run_timer(now - self.timer_synced);
+        self.timer_synced = now;
+    }
+
+    pub fn sync_apu(&mut self) {
+        let now = self.scheduler.now();
+        self.io.run_apu((now - self.apu_synced) as u32);
+        self.apu_synced = now;
     }
 
+    // The timer must be up to date
+    fn schedule_timer(&mut self) {
+        match self.io.timer_next_event() {
+            Some(cycles) => self.scheduler.schedule_in(EventType::Timer, cycles),
+            None => self.scheduler.cancel(EventType::Timer),
+        }
+
+        let div_apu_edge = self.io.cycles_to_div_apu_edge();
+        self.scheduler.schedule_in(EventType::FrameSequencer, div_apu_edge);
+    }
 
-    /// This function ticks all the devices that tick once
-    /// per machine cycle, like the DMA.
-    pub fn tick_m(&mut self) {
+    /// The DMA copies one byte per machine cycle
+    fn tick_dma(&mut self) {
         if let Some((byte, val)) = self.io.tick_dma() {
             let source_addr = val as u16 * 0x100 + byte as u16;
             let value = self.read(source_addr);
@@ -183,6 +303,36 @@ impl Interconnect {
         &self.io.lcd.bg_colors
     }
 
+    /// These functions are used by the PPU to update
+    /// the read-only parts of the LCD registers
+    pub fn lcd_set_mode(&mut self, mode: u8) {
+        self.io.lcd.set_mode(mode);
+    }
+
+    pub fn lcd_set_ly(&mut self, ly: u8) {
+        self.io.lcd.set_ly(ly);
+    }
+
+    pub fn lcd_set_lyc_flag(&mut self, value: bool) {
+        self.io.lcd.set_lyc_flag(value);
+    }
+
+    pub fn lcd_stat_written(&mut self) -> bool {
+        self.io.lcd.take_stat_written()
+    }
+
+    pub fn lcd_blank_color(&self) -> u32 {
+        self.io.lcd.blank_color()
+    }
+
+    pub fn lcd_color_mode(&self) -> ColorMode {
+        self.io.lcd.color_mode()
+    }
+
+    pub fn lcd_set_palette(&mut self, palette: &DmgPalette) {
+        self.io.lcd.set_palette(palette);
+    }
+
     pub fn lcd_sp1_colors(&self) -> &[u32; 4] {
         &self.io.lcd.sp1_colors
     }
@@ -197,6 +347,28 @@ impl Interconnect {
         self.oam_ram[index as usize]
     }
 
+    /// This function is called by the PPU during mode 2 to indicate
+    /// which OAM row it is currently reading
+    pub fn set_oam_scan_row(&mut self, row: Option<u8>) {
+        self.oam_scan_row = row;
+    }
+
+    pub fn set_oam_early_lock(&mut self, locked: bool) {
+        self.oam_early_lock = locked;
+    }
+
+    pub fn set_vram_early_lock(&mut self, locked: bool) {
+        self.vram_early_lock = locked;
+    }
+
+    /// This function applies the OAM corruption bug if the CPU
+    /// puts an address in 0xFE00-0xFEFF on the bus during mode 2
+    pub fn trigger_oam_bug(&mut self, address: u16, corruption: OAMCorruption) {
+        if let Some(row) = self.oam_scan_row && self.io.lcd.enabled() && (0xFE00..0xFF00).contains(&address) {
+            oam::corrupt_oam(&mut self.oam_ram, row as usize, corruption);
+        }
+    }
+
     pub fn need_save(&mut self) -> bool {
         self.cart.as_mut().unwrap().need_save()
     }
@@ -205,16 +377,108 @@ impl Interconnect {
         self.cart.as_ref().unwrap().save(save_path);
     }
 
-    pub fn load_save(&mut self, save_path: &PathBuf) {
-        self.cart.as_mut().unwrap().load_save(save_path);
-    }
-
     pub fn update_input(&mut self, input: InputState) {
         self.io.update_input(input);
     }
 
-    pub fn apu_output(&self) -> Option<(f32, f32)> {
-        self.io.apu_output()
+    /// The APU must be up to date
+    pub fn apu_read_samples(&mut self, output: impl FnMut((f32, f32))) {
+        self.io.apu_read_samples(output)
+    }
+
+    pub fn apu_set_sample_rate(&mut self, sample_rate: u32, speed: u8) {
+        self.sync_apu();
+        self.io.apu_set_sample_rate(sample_rate, speed)
+    }
+
+    pub fn apu_set_speed(&mut self, speed: u8) {
+        self.sync_apu();
+        self.io.apu_set_speed(speed)
+    }
+
+    pub fn apu_set_filter(&mut self, filter: AudioFilter) {
+        self.io.apu_set_filter(filter)
+    }
+
+    /// The APU must be up to date
+    pub fn apu_read_channel_samples(&mut self, output: impl FnMut([f32; 4])) {
+        self.io.apu_read_channel_samples(output)
+    }
+
+    pub fn apu_enable_channel_outputs(&mut self, enabled: bool) {
+        self.sync_apu();
+        self.io.apu_enable_channel_outputs(enabled)
+    }
+
+    pub fn apu_start_vgm_log(&mut self, path: &Path) -> std::io::Result<()> {
+        self.sync_apu();
+        self.io.apu_start_vgm_log(path)
+    }
+
+    pub fn apu_stop_vgm_log(&mut self) -> std::io::Result<()> {
+        self.sync_apu();
+        self.io.apu_stop_vgm_log()
+    }
+
+    pub fn apu_vgm_logging(&self) -> bool {
+        self.io.apu_vgm_logging()
+    }
+
+    pub fn apu_set_channel_mix(&mut self, muted: [bool; 4], solo: [bool; 4]) {
+        self.sync_apu();
+        self.io.apu_set_channel_mix(muted, solo)
     }
 }
 
+impl SaveState for Interconnect {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.bytes(&self.vram);
+        self.ram.save_state(state);
+        for entry in &self.oam_ram {
+            entry.save_state(state);
+        }
+        state.bool(self.oam_scan_row.is_some());
+        state.u8(self.oam_scan_row.unwrap_or(0));
+        state.bool(self.oam_early_lock);
+        state.bool(self.vram_early_lock);
+        self.io.save_state(state);
+        state.u8(self.ie_register);
+
+        self.scheduler.save_state(state);
+        state.u64(self.timer_synced);
+        state.u64(self.apu_synced);
+
+        if let Some(cart) = &self.cart {
+            cart.save_state(state);
+        }
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), InvalidState> {
+        state.bytes(&mut self.vram)?;
+        self.ram.load_state(state)?;
+        for entry in &mut self.oam_ram {
+            entry.load_state(state)?;
+        }
+        let scanning = state.bool()?;
+        let row = state.u8()?;
+        self.oam_scan_row = scanning.then_some(row);
+        self.oam_early_lock = state.bool()?;
+        self.vram_early_lock = state.bool()?;
+        self.io.load_state(state)?;
+        self.ie_register = state.u8()?;
+
+        self.scheduler.load_state(state)?;
+        self.timer_synced = state.u64()?;
+        self.apu_synced = state.u64()?;
+        let now = self.scheduler.now();
+        if self.timer_synced > now || self.apu_synced > now {
+            return Err(InvalidState::new("devices updated in the future"))
+        }
+
+        if let Some(cart) = &mut self.cart {
+            cart.load_state(state)?;
+        }
+        self.vram_updated.set(true);
+        Ok(())
+    }
+}
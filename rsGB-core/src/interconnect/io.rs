@@ -10,7 +10,9 @@ use lcd::LCD;
 use gamepad::Gamepad;
 use apu::APU;
 
-use crate::{ColorMode, InputState};
+use std::{io, path::Path};
+
+use crate::{ColorMode, InputState, settings::AudioFilter, state::{InvalidState, SaveState, StateReader, StateWriter}};
 
 use super::InterruptType;
 
@@ -42,10 +44,6 @@ pub struct IO {
     apu: APU,
     pub(crate) lcd: LCD,
     dma: DMA,
-
-    // Previous DIV value
-    prev_div: u16,
-    falling_edge: bool,
 }
 
 impl IO {
@@ -58,9 +56,6 @@ impl IO {
             apu: APU::new(),
             lcd: LCD::new(color_mode),
             dma: DMA::new(),
-
-            prev_div: 0,
-            falling_edge: false,
         }
     }
 
@@ -86,7 +81,15 @@ impl IO {
             0xFF00 => self.gamepad.set_sel(value),
             0xFF01 => self.serial[0] = value,
             0xFF02 => self.serial[1] = value | 0b01111110,
-            0xFF04..=0xFF07 => self.timer.write(address, value),
+            0xFF04 => {
+                // Resetting DIV can trigger a falling edge for the APU
+                let div_apu_edge = self.timer.div & 0x1000 != 0;
+                self.timer.write(address, value);
+                if div_apu_edge {
+                    self.apu.step_frame_sequencer();
+                }
+            }
+            0xFF05..=0xFF07 => self.timer.write(address, value),
             0xFF0F => self.if_register = value,
             0xFF10..0xFF40 => self.apu.write(address, value),
             0xFF40..=0xFF4B => {
@@ -97,25 +100,26 @@ impl IO {
         }
     }
 
-    pub fn tick_timer(&mut self) {
-        let interrupt = self.timer.tick();
-        
-        let div_bit = (self.timer.div >> 12) & 1;
-        let prev_bit = (self.prev_div >> 12) & 1;
+    pub fn run_timer(&mut self, cycles: u64) {
+        if self.timer.advance(cycles) {
+            self.if_register |= InterruptType::Timer as u8;
+        }
+    }
 
-        // We read the 12th bit of DIV because it represents the full 16-bit internal counter,
-        // instead of the 8-bit register
-        self.falling_edge = div_bit == 0 && prev_bit == 1;
+    pub fn timer_next_event(&self) -> Option<u64> {
+        self.timer.next_event()
+    }
 
-        self.prev_div = self.timer.div;
+    pub fn cycles_to_div_apu_edge(&self) -> u64 {
+        self.timer.cycles_to_div_apu_edge()
+    }
 
-        if let Some(interrupt) = interrupt {
-            self.if_register |= interrupt as u8;
-        }
+    pub fn run_apu(&mut self, cycles: u32) {
+        self.apu.run(cycles)
     }
 
-    pub fn tick_apu(&mut self) {
-        self.apu.tick(self.falling_edge)
+    pub fn step_frame_sequencer(&mut self) {
+        self.apu.step_frame_sequencer()
     }
 
     pub fn request_interrupt(&mut self, interrupt: InterruptType) {
@@ -126,6 +130,10 @@ impl IO {
         self.dma.tick()
     }
 
+    pub fn dma_active(&self) -> bool {
+        self.dma.active()
+    }
+
     pub fn dma_transferring(&self) -> bool {
         self.dma.transferring()
     }
@@ -134,7 +142,69 @@ impl IO {
         self.gamepad.gamepad_state = input;
     }
 
-    pub fn apu_output(&self) -> Option<(f32, f32)> {
-        self.apu.output()
+    pub fn apu_read_samples(&mut self, output: impl FnMut((f32, f32))) {
+        self.apu.read_samples(output)
+    }
+
+    pub fn apu_set_sample_rate(&mut self, sample_rate: u32, speed: u8) {
+        self.apu.set_sample_rate(sample_rate, speed)
+    }
+
+    pub fn apu_set_speed(&mut self, speed: u8) {
+        self.apu.set_speed(speed)
+    }
+
+    pub fn apu_set_filter(&mut self, filter: AudioFilter) {
+        self.apu.set_filter(filter)
+    }
+
+    pub fn apu_read_channel_samples(&mut self, output: impl FnMut([f32; 4])) {
+        self.apu.read_channel_samples(output)
+    }
+
+    pub fn apu_enable_channel_outputs(&mut self, enabled: bool) {
+        self.apu.enable_channel_outputs(enabled)
+    }
+
+    pub fn apu_start_vgm_log(&mut self, path: &Path) -> io::Result<()> {
+        self.apu.start_vgm_log(path)
+    }
+
+    pub fn apu_stop_vgm_log(&mut self) -> io::Result<()> {
+        self.apu.stop_vgm_log()
+    }
+
+    pub fn apu_vgm_logging(&self) -> bool {
+        self.apu.vgm_logging()
     }
-}
\ No newline at end of file
+
+    pub fn apu_set_channel_mix(&mut self, muted: [bool; 4], solo: [bool; 4]) {
+        for channel in 0..4 {
+            self.apu.set_muted(channel, muted[channel]);
+            self.apu.set_solo(channel, solo[channel]);
+        }
+    }
+}
+
+// The buttons held aren't saved, the frontend applies them on each frame
+impl SaveState for IO {
+    fn save_state(&self, state: &mut StateWriter) {
+        self.gamepad.save_state(state);
+        state.bytes(&self.serial);
+        self.timer.save_state(state);
+        state.u8(self.if_register);
+        self.apu.save_state(state);
+        self.lcd.save_state(state);
+        self.dma.save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), InvalidState> {
+        self.gamepad.load_state(state)?;
+        state.bytes(&mut self.serial)?;
+        self.timer.load_state(state)?;
+        self.if_register = state.u8()?;
+        self.apu.load_state(state)?;
+        self.lcd.load_state(state)?;
+        self.dma.load_state(state)
+    }
+}
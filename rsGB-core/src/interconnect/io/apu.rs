@@ -1,4 +1,5 @@
 use core::panic;
+use std::{io, path::Path};
 
 mod pulse_channel;
 use pulse_channel::PulseChannel;
@@ -12,6 +13,15 @@ use noise_channel::NoiseChannel;
 mod timer;
 use timer::Timer;
 
+mod blip_buffer;
+
+mod high_pass;
+
+mod output;
+use output::Output;
+
+use crate::{settings::AudioFilter, state::{InvalidState, SaveState, StateReader, StateWriter}, utils::{CLOCK_RATE, DEFAULT_SAMPLE_RATE}, vgm::VgmRecorder};
+
 pub struct APU {
     // APU internals
     div_apu: u8,
@@ -31,6 +41,19 @@ pub struct APU {
     master_vol: u8,
     sound_panning: u8,
     audio_master_ctrl: u8,    
+
+    // Output
+    cycles: u64, // The number of cycles the APU has run for
+    output: Output<2>,
+    channel_outputs: Option<Output<4>>, // Only produced when requested
+    muted: [bool; 4],
+    solo: [bool; 4],
+
+    filter: AudioFilter,
+    sample_rate: u32,
+    speed: u8,
+
+    vgm: Option<VgmRecorder>, // The register writes are logged when set
 }
 
 impl APU {
@@ -49,48 +72,184 @@ impl APU {
             master_vol: 0x77,
             sound_panning: 0xF3,
             audio_master_ctrl: 0xF1,
+
+            cycles: 0,
+            output: Output::new(AudioFilter::DMG, CLOCK_RATE as f64, DEFAULT_SAMPLE_RATE),
+            channel_outputs: None,
+            muted: [false; 4],
+            solo: [false; 4],
+
+            filter: AudioFilter::DMG,
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            speed: 1,
+
+            vgm: None,
         }
     }
 
-    pub fn tick(&mut self, div_falling_edge: bool) {
-        if self.audio_enabled() {
-            if div_falling_edge {
-                self.div_apu = (self.div_apu + 1) % 8;
-                if self.div_apu & 1 == 0 { // Length counter step
-                    self.ch1.length_tick();
-                    self.ch2.length_tick();
-                    self.ch3.length_tick();
-                    self.ch4.length_tick();
-                }
-                
-                if self.div_apu == 2 || self.div_apu == 6 { // Sweep step
-                    self.ch1.sweep_tick();
-                }
-                
-                if self.div_apu == 7 { // Envelope step  
-                    self.ch1.enveloppe_tick();
-                    self.ch2.enveloppe_tick();
-                    self.ch4.enveloppe_tick();
-                }
-            }
+    /// Runs the channels for the given number of cycles, stopping
+    /// each time one of them may change its output
+    pub fn run(&mut self, mut cycles: u32) {
+        if !self.audio_enabled() {
+            self.cycles += cycles as u64;
+            return
+        }
 
-            self.ch1.tick();
-            self.ch2.tick();
-            self.ch3.tick();
+        while cycles > 0 {
+            let step = cycles
+                .min(self.ch1.cycles_to_step())
+                .min(self.ch2.cycles_to_step())
+                .min(self.ch3.cycles_to_step())
+                .min(self.ch4.cycles_to_step());
+
+            self.ch1.run(step);
+            self.ch2.run(step);
+            self.ch3.run(step);
+            self.ch4.run(step);
+
+            cycles -= step;
+            self.cycles += step as u64;
+            self.update_output();
+        }
+    }
 
-            // self.output_buffer.push((left, right));
+    /// Sends the samples produced since the last call to the output
+    pub fn read_samples(&mut self, mut output: impl FnMut((f32, f32))) {
+        let dacs_enabled = self.dacs_enabled();
+        self.output.read_samples(self.cycles, dacs_enabled, |[left, right]| output((left, right)));
+    }
 
-            // if self.sample_timer.tick() {
-            //     let (output_l, output_r) = self.filter_audio();
+    /// Sends the output of each channel, before mixing, produced since the last call
+    pub fn read_channel_samples(&mut self, output: impl FnMut([f32; 4])) {
+        let dacs_enabled = self.dacs_enabled();
+        if let Some(channel_outputs) = &mut self.channel_outputs {
+            channel_outputs.read_samples(self.cycles, dacs_enabled, output);
+        }
+    }
+
+    /// The separate outputs of the channels are only produced when enabled
+    pub fn enable_channel_outputs(&mut self, enabled: bool) {
+        self.channel_outputs = if enabled {
+            // The samples start at the current cycle
+            let mut channel_outputs = Output::new(self.filter, self.clock_rate(), self.sample_rate);
+            channel_outputs.configure(self.cycles, self.filter, self.clock_rate(), self.sample_rate);
+            Some(channel_outputs)
+        } else {
+            None
+        };
+    }
 
-            //     let _  = self.sender.try_push((output_l, output_r));
+    /// A muted channel is removed from the mix
+    pub fn set_muted(&mut self, channel: usize, muted: bool) {
+        if self.muted[channel] != muted {
+            self.muted[channel] = muted;
+            self.update_output();
+        }
+    }
 
-            //     self.output_buffer.clear();
-            // }
+    /// When some channels are soloed, only them are mixed
+    pub fn set_solo(&mut self, channel: usize, solo: bool) {
+        if self.solo[channel] != solo {
+            self.solo[channel] = solo;
+            self.update_output();
+        }
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32, speed: u8) {
+        self.sample_rate = sample_rate;
+        self.set_speed(speed);
+    }
+
+    /// At higher speeds, the samples are produced less often
+    /// so that the audio keeps its pitch
+    pub fn set_speed(&mut self, speed: u8) {
+        self.speed = speed;
+        self.configure_outputs();
+    }
+
+    pub fn set_filter(&mut self, filter: AudioFilter) {
+        if filter != self.filter {
+            self.filter = filter;
+            self.configure_outputs();
+        }
+    }
+
+    /// Logs the writes to the registers from now on. The registers that can be
+    /// read back are logged first, so that the log starts from the current state.
+    /// The frequencies are write-only, they are only known after the next write
+    // The log is timed with the cycles of the APU, which aren't
+    // the ones of the console once a state is loaded
+    pub fn start_vgm_log(&mut self, path: &Path) -> io::Result<()> {
+        let mut vgm = VgmRecorder::create(path, self.cycles)?;
+        let registers = [0xFF26, 0xFF24, 0xFF25].into_iter()
+            .chain(0xFF30..0xFF40)
+            .chain([0xFF10, 0xFF11, 0xFF12, 0xFF16, 0xFF17, 0xFF1A, 0xFF1B, 0xFF1C, 0xFF20, 0xFF21, 0xFF22]);
+
+        for address in registers {
+            vgm.write_register(self.cycles, address, self.read(address));
+        }
+        self.vgm = Some(vgm);
+        Ok(())
+    }
+
+    pub fn stop_vgm_log(&mut self) -> io::Result<()> {
+        match self.vgm.take() {
+            Some(vgm) => vgm.finish(self.cycles),
+            None => Ok(()),
+        }
+    }
+
+    pub fn vgm_logging(&self) -> bool {
+        self.vgm.is_some()
+    }
+
+    fn configure_outputs(&mut self) {
+        let clock_rate = self.clock_rate();
+        self.output.configure(self.cycles, self.filter, clock_rate, self.sample_rate);
+
+        if let Some(channel_outputs) = &mut self.channel_outputs {
+            channel_outputs.configure(self.cycles, self.filter, clock_rate, self.sample_rate);
+        }
+    }
+
+    fn clock_rate(&self) -> f64 {
+        CLOCK_RATE as f64 * self.speed as f64
+    }
+
+    /// Called on each falling edge of DIV bit 4
+    pub fn step_frame_sequencer(&mut self) {
+        if self.audio_enabled() {
+            self.div_apu = (self.div_apu + 1) % 8;
+            if self.div_apu & 1 == 0 { // Length counter step
+                self.ch1.length_tick();
+                self.ch2.length_tick();
+                self.ch3.length_tick();
+                self.ch4.length_tick();
+            }
+            
+            if self.div_apu == 2 || self.div_apu == 6 { // Sweep step
+                self.ch1.sweep_tick();
+            }
+            
+            if self.div_apu == 7 { // Envelope step  
+                self.ch1.enveloppe_tick();
+                self.ch2.enveloppe_tick();
+                self.ch4.enveloppe_tick();
+            }
+            self.update_output();
         }
     }
 
     pub fn write(&mut self, address: u16, value: u8) {
+        if let Some(vgm) = &mut self.vgm {
+            vgm.write_register(self.cycles, address, value);
+        }
+
+        self.write_register(address, value);
+        self.update_output();
+    }
+
+    fn write_register(&mut self, address: u16, value: u8) {
         // On DMG, the timer length registers are writable even with the APU off
         // but they can only modify the length, not the duty cycle
         if self.audio_enabled() || address > 0xFF25 || address == 0xFF1B || address == 0xFF20 {     
@@ -168,12 +327,32 @@ impl APU {
         }
     }
 
-    pub fn output(&self) -> Option<(f32, f32)> {
+    // Adds the changes of the outputs to their buffers
+    fn update_output(&mut self) {
+        let channels = if self.audio_enabled() {
+            [self.ch1.output(), self.ch2.output(), self.ch3.output(), self.ch4.output()]
+        } else {
+            [0.0; 4]
+        };
+
+        let (left, right) = self.mix(channels);
+        self.output.update(self.cycles, [left, right]);
+
+        if let Some(channel_outputs) = &mut self.channel_outputs {
+            channel_outputs.update(self.cycles, channels);
+        }
+    }
+
+    fn mix(&self, channels: [f32; 4]) -> (f32, f32) {
         if self.audio_enabled() {
-            let ch1_output = self.ch1.output();
-            let ch2_output = self.ch2.output();
-            let ch3_output = self.ch3.output();
-            let ch4_output = 0.0;
+            let soloing = self.solo.contains(&true);
+            let [ch1_output, ch2_output, ch3_output, ch4_output] = std::array::from_fn(|channel| {
+                if self.muted[channel] || (soloing && !self.solo[channel]) {
+                    0.0
+                } else {
+                    channels[channel]
+                }
+            });
 
             let left_vol = (self.master_vol >> 4) & 0x07;
             let right_vol = self.master_vol & 0x07;
@@ -199,9 +378,10 @@ impl APU {
             if pan & 0b1000 != 0 { right += ch4_output; }
             if pan & 0b1000_0000 != 0 { left += ch4_output; }
 
-            // Apply master volume scaling
-            left *= left_vol as f32 / 7.0;
-            right *= right_vol as f32 / 7.0;
+            // The master volume goes from 1/8 to 8/8, it can't mute the output.
+            // The VIN bits mix in the cartridge audio, which no cartridge produces
+            left *= (left_vol + 1) as f32 / 8.0;
+            right *= (right_vol + 1) as f32 / 8.0;
 
             // Normalise for the 4 channels
             left /= 4.0;
@@ -211,10 +391,10 @@ impl APU {
             left = left.clamp(-1.0, 1.0);
             right = right.clamp(-1.0, 1.0);
 
-            Some((left, right))
+            (left, right)
         }
         else {
-            None
+            (0.0, 0.0)
         }
     }
 
@@ -239,7 +419,37 @@ impl APU {
         }
     }
 
+    fn dacs_enabled(&self) -> bool {
+        self.audio_enabled() && (self.ch1.is_dac_enabled() || self.ch2.is_dac_enabled()
+            || self.ch3.is_dac_enabled() || self.ch4.is_dac_enabled())
+    }
+
     fn audio_enabled(&self) -> bool {
         self.audio_master_ctrl & 0b10000000 != 0
     }
-}
\ No newline at end of file
+}
+
+// The output keeps its own timeline, which isn't saved:
+// the samples continue from where they were
+impl SaveState for APU {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.u8(self.div_apu);
+        self.ch1.save_state(state);
+        self.ch2.save_state(state);
+        self.ch3.save_state(state);
+        self.ch4.save_state(state);
+        state.bytes(&[self.master_vol, self.sound_panning, self.audio_master_ctrl]);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), InvalidState> {
+        self.div_apu = state.u8()?;
+        self.ch1.load_state(state)?;
+        self.ch2.load_state(state)?;
+        self.ch3.load_state(state)?;
+        self.ch4.load_state(state)?;
+        let mut registers = [0; 3];
+        state.bytes(&mut registers)?;
+        [self.master_vol, self.sound_panning, self.audio_master_ctrl] = registers;
+        Ok(())
+    }
+}
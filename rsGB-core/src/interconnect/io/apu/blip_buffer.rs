@@ -0,0 +1,126 @@
+use std::f64::consts::PI;
+
+// The output of the APU only changes at discrete cycles. Instead of
+// picking one value every few cycles, which aliases, each change is
+// added to the buffer as a band-limited step at its exact position,
+// and the buffer is read at the sample rate of the host
+
+// The number of output samples each step is spread over
+const KERNEL_WIDTH: usize = 16;
+
+// The number of sub-sample positions the kernel is computed for
+const PHASES: usize = 64;
+
+// The cutoff frequency of the filter, relative to the sample rate
+const CUTOFF: f64 = 0.45;
+
+pub struct BlipBuffer<const N: usize> {
+    kernel: Vec<[f32; KERNEL_WIDTH]>,
+
+    samples_per_cycle: f64,
+    start: u64,  // The cycle the first sample of the buffer starts at
+    offset: f64, // The fractional position of that cycle in the sample
+
+    // The changes of the output, filtered and spread over the samples
+    deltas: Vec<[f32; N]>,
+    accumulator: [f32; N],
+}
+
+impl<const N: usize> BlipBuffer<N> {
+    pub fn new(clock_rate: f64, sample_rate: u32) -> BlipBuffer<N> {
+        BlipBuffer {
+            kernel: compute_kernel(),
+
+            samples_per_cycle: sample_rate as f64 / clock_rate,
+            start: 0,
+            offset: 0.0,
+
+            deltas: Vec::new(),
+            accumulator: [0.0; N],
+        }
+    }
+
+    /// Changes the conversion ratio from the given cycle on
+    pub fn set_rates(&mut self, cycle: u64, clock_rate: f64, sample_rate: u32) {
+        self.offset = self.position(cycle);
+        self.start = cycle;
+        self.samples_per_cycle = sample_rate as f64 / clock_rate;
+    }
+
+    /// Adds a change of the output at the given cycle
+    pub fn add_delta(&mut self, cycle: u64, delta: [f32; N]) {
+        let position = self.position(cycle);
+        let index = position as usize;
+        let phase = ((position - index as f64) * PHASES as f64) as usize;
+
+        if self.deltas.len() < index + KERNEL_WIDTH {
+            self.deltas.resize(index + KERNEL_WIDTH, [0.0; N]);
+        }
+
+        for (sample, weight) in self.deltas[index..].iter_mut().zip(self.kernel[phase]) {
+            for (value, delta) in sample.iter_mut().zip(delta) {
+                *value += delta * weight;
+            }
+        }
+    }
+
+    /// Outputs all the samples before the given cycle, they
+    /// can't be modified by the changes that come after it
+    pub fn read_samples(&mut self, cycle: u64, mut output: impl FnMut([f32; N])) {
+        let position = self.position(cycle);
+        let count = position as usize;
+
+        if self.deltas.len() < count {
+            self.deltas.resize(count, [0.0; N]);
+        }
+
+        for sample in self.deltas.drain(..count) {
+            for (total, delta) in self.accumulator.iter_mut().zip(sample) {
+                *total += delta;
+            }
+            output(self.accumulator);
+        }
+
+        self.offset = position - count as f64;
+        self.start = cycle;
+    }
+
+    fn position(&self, cycle: u64) -> f64 {
+        self.offset + (cycle - self.start) as f64 * self.samples_per_cycle
+    }
+}
+
+// Each phase holds the band-limited impulse for a change at that
+// sub-sample position: a windowed sinc, delayed by half its width.
+// It's normalised so that the steps always reach their final value
+fn compute_kernel() -> Vec<[f32; KERNEL_WIDTH]> {
+    let half_width = (KERNEL_WIDTH / 2) as f64;
+
+    (0..PHASES).map(|phase| {
+        let fraction = phase as f64 / PHASES as f64;
+        let mut taps = [0.0; KERNEL_WIDTH];
+
+        for (k, tap) in taps.iter_mut().enumerate() {
+            let x = k as f64 - half_width - fraction + 1.0;
+
+            let sinc = if x == 0.0 {
+                1.0
+            } else {
+                (PI * 2.0 * CUTOFF * x).sin() / (PI * 2.0 * CUTOFF * x)
+            };
+
+            // Blackman window
+            let window = if x.abs() < half_width {
+                let t = PI * x / half_width;
+                0.42 + 0.5 * t.cos() + 0.08 * (2.0 * t).cos()
+            } else {
+                0.0
+            };
+
+            *tap = sinc * window;
+        }
+
+        let sum: f64 = taps.iter().sum();
+        taps.map(|tap| (tap / sum) as f32)
+    }).collect()
+}
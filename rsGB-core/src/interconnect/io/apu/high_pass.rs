@@ -0,0 +1,55 @@
+use crate::settings::AudioFilter;
+
+// The output of the console goes through a capacitor, which removes the
+// constant offset of the DACs: the output settles back to 0 during silences.
+// It charges a bit slower on the DMG than on later models
+const DMG_CHARGE_FACTOR: f64 = 0.999958;
+const CGB_CHARGE_FACTOR: f64 = 0.998943;
+
+pub struct HighPassFilter<const N: usize> {
+    filter: AudioFilter,
+    charge_factor: f32, // For the duration of one sample
+    capacitors: [f32; N],
+}
+
+impl<const N: usize> HighPassFilter<N> {
+    pub fn new(filter: AudioFilter, cycles_per_sample: f64) -> HighPassFilter<N> {
+        let mut high_pass = HighPassFilter {
+            filter,
+            charge_factor: 0.0,
+            capacitors: [0.0; N],
+        };
+        high_pass.configure(filter, cycles_per_sample);
+        high_pass
+    }
+
+    /// The charge factors are given per cycle
+    pub fn configure(&mut self, filter: AudioFilter, cycles_per_sample: f64) {
+        let charge_factor = match filter {
+            AudioFilter::DMG => DMG_CHARGE_FACTOR,
+            AudioFilter::CGB => CGB_CHARGE_FACTOR,
+            AudioFilter::Raw => 1.0,
+        };
+
+        self.filter = filter;
+        self.charge_factor = charge_factor.powf(cycles_per_sample) as f32;
+    }
+
+    pub fn apply(&mut self, input: [f32; N], dacs_enabled: bool) -> [f32; N] {
+        if self.filter == AudioFilter::Raw {
+            return input
+        }
+
+        // The capacitor only charges while one of the DACs is on
+        if !dacs_enabled {
+            return [0.0; N]
+        }
+
+        let mut output = [0.0; N];
+        for ((output, capacitor), input) in output.iter_mut().zip(&mut self.capacitors).zip(input) {
+            *output = input - *capacitor;
+            *capacitor = input - *output * self.charge_factor;
+        }
+        output
+    }
+}
@@ -1,4 +1,5 @@
 use super::Timer;
+use crate::state::{InvalidState, SaveState, StateReader, StateWriter};
 
 #[derive(Debug, Default)]
 pub struct NoiseChannel {
@@ -16,7 +17,8 @@ pub struct NoiseChannel {
     enveloppe_direction: bool,
 
     length_timer: u8,
-    _timer: Timer,
+    timer: Timer,
+    lfsr: u16, // The linear-feedback shift register, 15 bits
 }
 
 impl NoiseChannel {
@@ -42,7 +44,10 @@ impl NoiseChannel {
                     self.enabled = false;
                 }
             } 
-            0xFF22 => self.freq_randomness = value,
+            0xFF22 => {
+                self.freq_randomness = value;
+                self.timer.set_period(self.period());
+            }
             0xFF23 => {
                 let old_length_enable = self.length_enable();
 
@@ -73,12 +78,58 @@ impl NoiseChannel {
                     self.enveloppe_pace = self.enveloppe_pace();
 
                     self.volume = self.initial_volume();
+
+                    self.timer.set_period(self.period());
+                    self.lfsr = 0;
                 }
             }
             _ => unreachable!()
         }
     }
 
+    pub fn run(&mut self, cycles: u32) {
+        let steps = self.timer.advance(cycles);
+
+        // With a shift of 14 or 15 the LFSR isn't clocked
+        if self.clock_shift() < 14 {
+            for _ in 0..steps {
+                self.lfsr_step();
+            }
+        }
+    }
+
+    /// The number of cycles before the LFSR is clocked
+    pub fn cycles_to_step(&self) -> u32 {
+        if self.enabled && self.clock_shift() < 14 { self.timer.cycles_to_reload() } else { u32::MAX }
+    }
+
+    // The XNOR of the two lowest bits is shifted in at bit 14,
+    // and also copied to bit 6 with the 7-bit width
+    fn lfsr_step(&mut self) {
+        let bit = !(self.lfsr ^ (self.lfsr >> 1)) & 1;
+        self.lfsr = (self.lfsr >> 1) | (bit << 14);
+
+        if self.short_width() {
+            self.lfsr = (self.lfsr & !(1 << 6)) | (bit << 6);
+        }
+    }
+
+    /// The DAC keeps its output level while it's on, even if
+    /// the channel is disabled: it then receives a 0
+    pub fn output(&self) -> f32 {
+        if !self.is_dac_enabled() {
+            return 0.0
+        }
+
+        let sample = if self.enabled && self.lfsr & 1 != 0 {
+            self.volume as f32
+        } else {
+            0.0
+        };
+
+        (7.5 - sample) / 7.5
+    }
+
     pub fn length_tick(&mut self) {
         if self.length_enable() {
             self.length_timer = self.length_timer.saturating_sub(1);
@@ -115,6 +166,24 @@ impl NoiseChannel {
         self.enabled = false;
     }
 
+    /// The LFSR is clocked every 16 cycles times the divider,
+    /// a divider of 0 counting as 0.5, shifted by the clock shift
+    fn period(&self) -> u32 {
+        let divider = match self.freq_randomness & 0b111 {
+            0 => 8,
+            divider => divider as u32 * 16,
+        };
+        divider << self.clock_shift()
+    }
+
+    fn clock_shift(&self) -> u8 {
+        self.freq_randomness >> 4
+    }
+
+    fn short_width(&self) -> bool {
+        self.freq_randomness & 0b1000 != 0
+    }
+
     fn initial_length_timer(&self) -> u8 {
         self.length_timer_reg & 0b111111
     }
@@ -133,7 +202,7 @@ impl NoiseChannel {
         self.volume_envelope & 0b111
     }
 
-    fn is_dac_enabled(&self) -> bool {
+    pub fn is_dac_enabled(&self) -> bool {
         // DAC is enabled if the upper 5 bits of NRx2 are non-zero.
         self.volume_envelope & 0xF8 != 0
     }
@@ -145,4 +214,38 @@ impl NoiseChannel {
     fn trigger(&self, value: u8) -> bool {
         value & 0b10000000 != 0
     }
-}
\ No newline at end of file
+}
+
+impl SaveState for NoiseChannel {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.bytes(&[self.length_timer_reg, self.volume_envelope, self.freq_randomness, self.control]);
+        state.bool(self.enabled);
+
+        state.u8(self.volume);
+        state.u8(self.enveloppe_pace);
+        state.u8(self.enveloppe_timer);
+        state.bool(self.enveloppe_direction);
+
+        state.u8(self.length_timer);
+        self.timer.save_state(state);
+        state.u16(self.lfsr);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), InvalidState> {
+        let mut registers = [0; 4];
+        state.bytes(&mut registers)?;
+        [self.length_timer_reg, self.volume_envelope, self.freq_randomness, self.control] = registers;
+        self.enabled = state.bool()?;
+
+        self.volume = state.u8()?;
+        self.enveloppe_pace = state.u8()?;
+        self.enveloppe_timer = state.u8()?;
+        self.enveloppe_direction = state.bool()?;
+
+        self.length_timer = state.u8()?;
+        self.timer.load_state(state)?;
+        // The register is 15 bits wide
+        self.lfsr = state.u16()? & 0x7FFF;
+        Ok(())
+    }
+}
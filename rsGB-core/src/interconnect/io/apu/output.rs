@@ -0,0 +1,46 @@
+use super::{blip_buffer::BlipBuffer, high_pass::HighPassFilter};
+use crate::settings::AudioFilter;
+
+/// Turns N signals, which change at given cycles,
+/// into filtered samples at the host sample rate
+pub struct Output<const N: usize> {
+    levels: [f32; N],
+    blip_buffer: BlipBuffer<N>,
+    high_pass: HighPassFilter<N>,
+}
+
+impl<const N: usize> Output<N> {
+    pub fn new(filter: AudioFilter, clock_rate: f64, sample_rate: u32) -> Output<N> {
+        Output {
+            levels: [0.0; N],
+            blip_buffer: BlipBuffer::new(clock_rate, sample_rate),
+            high_pass: HighPassFilter::new(filter, clock_rate / sample_rate as f64),
+        }
+    }
+
+    pub fn configure(&mut self, cycle: u64, filter: AudioFilter, clock_rate: f64, sample_rate: u32) {
+        self.blip_buffer.set_rates(cycle, clock_rate, sample_rate);
+        self.high_pass.configure(filter, clock_rate / sample_rate as f64);
+    }
+
+    /// Sets the levels of the signals from the given cycle on
+    pub fn update(&mut self, cycle: u64, levels: [f32; N]) {
+        if levels != self.levels {
+            let mut delta = [0.0; N];
+            for ((delta, new), old) in delta.iter_mut().zip(levels).zip(self.levels) {
+                *delta = new - old;
+            }
+
+            self.blip_buffer.add_delta(cycle, delta);
+            self.levels = levels;
+        }
+    }
+
+    pub fn read_samples(&mut self, cycle: u64, dacs_enabled: bool, mut output: impl FnMut([f32; N])) {
+        let high_pass = &mut self.high_pass;
+
+        self.blip_buffer.read_samples(cycle, |sample| {
+            output(high_pass.apply(sample, dacs_enabled))
+        });
+    }
+}
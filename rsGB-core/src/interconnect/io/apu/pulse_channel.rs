@@ -1,4 +1,5 @@
 use super::Timer;
+use crate::state::{InvalidState, SaveState, StateReader, StateWriter};
 
 const WAVEFORMS: [[bool; 8]; 4] = [
     [false, false, false, false, false, false, false, true],
@@ -115,7 +116,7 @@ impl PulseChannel {
                         }
                     }
 
-                    self.timer.set_period((2048 - self.period()) * 4);
+                    self.timer.set_period((2048 - self.period() as u32) * 4);
 
                     self.enveloppe_direction = self.enveloppe_direction();
                     self.enveloppe_timer = if self.enveloppe_pace() == 0 {
@@ -142,27 +143,34 @@ impl PulseChannel {
         }
     }
 
-    pub fn tick(&mut self) {
-        if self.timer.tick() {
-            self.waveform_pointer = (self.waveform_pointer + 1) % 8;
-        }
+    pub fn run(&mut self, cycles: u32) {
+        let steps = self.timer.advance(cycles);
+        self.waveform_pointer = ((self.waveform_pointer as u32 + steps) % 8) as u8;
+    }
+
+    /// The number of cycles before the waveform moves, the
+    /// output can't change before that while the channel is on
+    pub fn cycles_to_step(&self) -> u32 {
+        if self.enabled { self.timer.cycles_to_reload() } else { u32::MAX }
     }
 
+    /// The DAC keeps its output level while it's on, even if
+    /// the channel is disabled: it then receives a 0
     pub fn output(&self) -> f32 {
-        if self.enabled {
-            let duty = self.wave_duty() as usize;
-            let pattern = WAVEFORMS[duty];
+        if !self.is_dac_enabled() {
+            return 0.0
+        }
 
-            let sample = if pattern[self.waveform_pointer as usize] {
-                self.volume as f32
-            } else {
-                0.0
-            };
+        let duty = self.wave_duty() as usize;
+        let pattern = WAVEFORMS[duty];
 
-            (7.5 - sample) / 7.5
+        let sample = if self.enabled && pattern[self.waveform_pointer as usize] {
+            self.volume as f32
         } else {
             0.0
-        }  
+        };
+
+        (7.5 - sample) / 7.5
     }
 
     pub fn length_tick(&mut self) {
@@ -238,7 +246,7 @@ impl PulseChannel {
         new_freq
     }
 
-    fn is_dac_enabled(&self) -> bool {
+    pub fn is_dac_enabled(&self) -> bool {
         // DAC is enabled if the upper 5 bits of NRx2 are non-zero.
         self.volume_envelope & 0xF8 != 0
     }
@@ -290,4 +298,48 @@ impl PulseChannel {
     fn trigger(&self, value: u8) -> bool {
         value & 0b10000000 != 0
     }
-}
\ No newline at end of file
+}
+
+impl SaveState for PulseChannel {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.bytes(&[self.sweep, self.length_timer_duty_cycle, self.volume_envelope, self.period_low, self.period_high_ctrl]);
+        state.bool(self.enabled);
+
+        state.bool(self.sweep_enabled);
+        state.u8(self.sweep_timer);
+        state.u16(self.shadow_register);
+        state.bool(self.negate_has_been_used);
+
+        state.u8(self.volume);
+        state.u8(self.enveloppe_pace);
+        state.u8(self.enveloppe_timer);
+        state.bool(self.enveloppe_direction);
+
+        state.u8(self.length_timer);
+        self.timer.save_state(state);
+        state.u8(self.waveform_pointer);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), InvalidState> {
+        let mut registers = [0; 5];
+        state.bytes(&mut registers)?;
+        [self.sweep, self.length_timer_duty_cycle, self.volume_envelope, self.period_low, self.period_high_ctrl] = registers;
+        self.enabled = state.bool()?;
+
+        self.sweep_enabled = state.bool()?;
+        self.sweep_timer = state.u8()?;
+        self.shadow_register = state.u16()?;
+        self.negate_has_been_used = state.bool()?;
+
+        self.volume = state.u8()?;
+        self.enveloppe_pace = state.u8()?;
+        self.enveloppe_timer = state.u8()?;
+        self.enveloppe_direction = state.bool()?;
+
+        self.length_timer = state.u8()?;
+        self.timer.load_state(state)?;
+        // Indexes the waveforms
+        self.waveform_pointer = state.u8()? % 8;
+        Ok(())
+    }
+}
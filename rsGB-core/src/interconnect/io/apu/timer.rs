@@ -1,20 +1,48 @@
+use crate::state::{InvalidState, SaveState, StateReader, StateWriter};
+
 #[derive(Debug, Default)]
 pub struct Timer {
-    period: u16,
-    value: u16,
+    period: u32,
+    value: u32,
 }
 
 impl Timer {
-    pub fn tick(&mut self) -> bool {
-        self.value = self.value.wrapping_sub(1);
-        if self.value == 0 {
-            self.value = self.period;
-            return true
+    /// Runs the timer for the given number of cycles,
+    /// returns the number of times it was reloaded
+    pub fn advance(&mut self, cycles: u32) -> u32 {
+        // A value or a period of 0 means the counter wraps around first
+        let value = self.cycles_to_reload();
+
+        if cycles < value {
+            self.value = value - cycles;
+            return 0
         }
-        false
+
+        let period = if self.period == 0 { 0x10000 } else { self.period };
+        let remaining = cycles - value;
+
+        self.value = period - remaining % period;
+        1 + remaining / period
+    }
+
+    pub fn cycles_to_reload(&self) -> u32 {
+        if self.value == 0 { 0x10000 } else { self.value }
     }
 
-    pub fn set_period(&mut self, period: u16) {
+    pub fn set_period(&mut self, period: u32) {
         self.period = period;
     }
-}
\ No newline at end of file
+}
+
+impl SaveState for Timer {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.u32(self.period);
+        state.u32(self.value);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), InvalidState> {
+        self.period = state.u32()?;
+        self.value = state.u32()?;
+        Ok(())
+    }
+}
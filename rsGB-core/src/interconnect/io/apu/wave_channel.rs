@@ -1,4 +1,5 @@
 use super::Timer;
+use crate::state::{InvalidState, SaveState, StateReader, StateWriter};
 
 #[derive(Debug, Default)]
 pub struct WaveChannel {
@@ -75,7 +76,7 @@ impl WaveChannel {
                         }
                     }
 
-                    self.period_divider.set_period((2048 - self.period()) * 2);
+                    self.period_divider.set_period((2048 - self.period() as u32) * 2);
 
                     self.wave_ram_pointer = 0;
                 }
@@ -90,10 +91,13 @@ impl WaveChannel {
         }
     }
 
-    pub fn tick(&mut self) {
-        if self.period_divider.tick() {
-            self.wave_ram_pointer = (self.wave_ram_pointer + 1) % 32;
-            
+    pub fn run(&mut self, cycles: u32) {
+        let steps = self.period_divider.advance(cycles);
+
+        if steps > 0 {
+            self.wave_ram_pointer = ((self.wave_ram_pointer as u32 + steps) % 32) as u8;
+
+            // Only the last sample read is kept in the buffer
             let byte = self.wave_pattern_ram[(self.wave_ram_pointer / 2) as usize];
             self.buffer = if self.wave_ram_pointer % 2 == 0 {
                 byte >> 4
@@ -103,6 +107,10 @@ impl WaveChannel {
         }
     }
 
+    pub fn cycles_to_step(&self) -> u32 {
+        if self.enabled { self.period_divider.cycles_to_reload() } else { u32::MAX }
+    }
+
     pub fn length_tick(&mut self) {
         if self.length_enable() {
             self.length_timer = self.length_timer.saturating_sub(1);
@@ -113,12 +121,12 @@ impl WaveChannel {
     }
 
     pub fn output(&self) -> f32 {
-        if self.enabled {
-            let sample = self.buffer >> self.output_level();
-            (7.5 - sample as f32) / 7.5
-        } else {
-            0.0
+        if !self.is_dac_enabled() {
+            return 0.0
         }
+
+        let sample = if self.enabled { self.buffer >> self.output_level() } else { 0 };
+        (7.5 - sample as f32) / 7.5
     }
 
     pub fn is_enabled(&self) -> bool {
@@ -135,7 +143,7 @@ impl WaveChannel {
         self.enabled = false;
     }
 
-    fn is_dac_enabled(&self) -> bool {
+    pub fn is_dac_enabled(&self) -> bool {
         self.dac_enable & (1 << 7) != 0
     }
 
@@ -162,4 +170,32 @@ impl WaveChannel {
     fn trigger(&self) -> bool {
         self.period_high_ctrl & 0b10000000 != 0
     }
-}
\ No newline at end of file
+}
+
+impl SaveState for WaveChannel {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.bytes(&[self.dac_enable, self.initial_length_timer, self.output_level, self.period_low, self.period_high_ctrl]);
+        state.bytes(&self.wave_pattern_ram);
+        state.bool(self.enabled);
+
+        state.u16(self.length_timer);
+        self.period_divider.save_state(state);
+        state.u8(self.wave_ram_pointer);
+        state.u8(self.buffer);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), InvalidState> {
+        let mut registers = [0; 5];
+        state.bytes(&mut registers)?;
+        [self.dac_enable, self.initial_length_timer, self.output_level, self.period_low, self.period_high_ctrl] = registers;
+        state.bytes(&mut self.wave_pattern_ram)?;
+        self.enabled = state.bool()?;
+
+        self.length_timer = state.u16()?;
+        self.period_divider.load_state(state)?;
+        // Indexes the 32 samples of the wave RAM
+        self.wave_ram_pointer = state.u8()? % 32;
+        self.buffer = state.u8()?;
+        Ok(())
+    }
+}
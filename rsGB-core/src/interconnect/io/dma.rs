@@ -1,3 +1,5 @@
+use crate::state::{InvalidState, SaveState, StateReader, StateWriter};
+
 pub struct DMA {
     active: bool,
     byte: u8,
@@ -61,7 +63,30 @@ impl DMA {
         self.value = value;
     }
 
+    pub fn active(&self) -> bool {
+        self.active
+    }
+
     pub fn transferring(&self) -> bool {
         (self.active && self.start_delay == 0) || self.restarted
     }
-}
\ No newline at end of file
+}
+
+impl SaveState for DMA {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.bool(self.active);
+        state.u8(self.byte);
+        state.u8(self.value);
+        state.u8(self.start_delay);
+        state.bool(self.restarted);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), InvalidState> {
+        self.active = state.bool()?;
+        self.byte = state.u8()?;
+        self.value = state.u8()?;
+        self.start_delay = state.u8()?;
+        self.restarted = state.bool()?;
+        Ok(())
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{Button, InputState};
+use crate::{Button, InputState, state::{InvalidState, SaveState, StateReader, StateWriter}};
 
 #[derive(Debug, Default)]
 pub struct Gamepad {
@@ -57,3 +57,16 @@ impl Gamepad {
         output
     }
 }
+
+impl SaveState for Gamepad {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.bool(self.button_select);
+        state.bool(self.direction_select);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), InvalidState> {
+        self.button_select = state.bool()?;
+        self.direction_select = state.bool()?;
+        Ok(())
+    }
+}
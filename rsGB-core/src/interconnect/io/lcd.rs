@@ -1,8 +1,4 @@
-use crate::ColorMode;
-
-
-const COLORS_DEFAULT_ARGB : [u32; 4] = [0xFFFFFFFF, 0xFFAAAAAA, 0xFF555555, 0xFF000000];
-const COLORS_DEFAULT_RGBA : [u32; 4] = [0xFFFFFFFF, 0xAAAAAAFF, 0x555555FF, 0x000000FF];
+use crate::{ColorMode, settings::{DmgPalette, PalettePreset}, state::{InvalidState, SaveState, StateReader, StateWriter}};
 
 pub struct LCD {
     // Registers
@@ -18,8 +14,13 @@ pub struct LCD {
     win_y: u8,
     win_x: u8,
 
+    // Set when the CPU writes to STAT, which triggers a spurious interrupt on DMG
+    stat_written: bool,
+
     // Other data
     color_mode: ColorMode,
+    palette: DmgPalette,
+    shades: [[u32; 4]; 3], // The palette in the color mode, for BG, OBJ0 and OBJ1
     pub(crate) bg_colors: [u32; 4],
     pub(crate) sp1_colors: [u32; 4],
     pub(crate) sp2_colors: [u32; 4],
@@ -27,11 +28,10 @@ pub struct LCD {
 
 impl LCD {
     pub fn new(color_mode: ColorMode) -> LCD {
-        let default = match color_mode {
-            ColorMode::ARGB => COLORS_DEFAULT_ARGB,
-            ColorMode::RGBA => COLORS_DEFAULT_RGBA,
-        };
-        LCD {
+        let palette = PalettePreset::Grayscale.palette();
+        let shades = convert_palette(&palette, color_mode);
+
+        let mut lcd = LCD {
             lcdc: 0x91,
             status: 0x02,
             scroll_y: 0,
@@ -44,11 +44,17 @@ impl LCD {
             win_y: 0,
             win_x: 0,
 
+            stat_written: false,
+
             color_mode,
-            bg_colors: default,
-            sp1_colors: default,
-            sp2_colors: default,
-        }
+            palette,
+            shades,
+            bg_colors: [0; 4],
+            sp1_colors: [0; 4],
+            sp2_colors: [0; 4],
+        };
+        lcd.update_palettes();
+        lcd
     }
 
     pub fn read(&self, address: u16) -> u8 {
@@ -72,10 +78,14 @@ impl LCD {
     pub fn write(&mut self, address: u16, value: u8) {
         match address {
             0xFF40 => self.lcdc = value,
-            0xFF41 => self.status = value | 0x80,
+            // The mode and LY=LYC bits are read-only
+            0xFF41 => {
+                self.status = (self.status & 0b111) | (value & 0x78) | 0x80;
+                self.stat_written = true;
+            }
             0xFF42 => self.scroll_y = value,
             0xFF43 => self.scroll_x = value,
-            0xFF44 => self.ly = value,
+            0xFF44 => (), // LY is read-only
             0xFF45 => self.ly_compare = value,
             0xFF46 => self.dma = value,
             0xFF47 => self.bg_palette = value,
@@ -95,6 +105,52 @@ impl LCD {
         }
     }
 
+    /// The color displayed by the screen when the LCD is off
+    pub fn blank_color(&self) -> u32 {
+        self.shades[0][0]
+    }
+
+    pub fn color_mode(&self) -> ColorMode {
+        self.color_mode
+    }
+
+    /// Changes the colors of the shades, the next pixels drawn use them
+    pub fn set_palette(&mut self, palette: &DmgPalette) {
+        if *palette != self.palette {
+            self.palette = *palette;
+            self.shades = convert_palette(palette, self.color_mode);
+            self.update_palettes();
+        }
+    }
+
+    pub fn set_mode(&mut self, mode: u8) {
+        self.status = (self.status & !0b11) | (mode & 0b11);
+    }
+
+    pub fn set_ly(&mut self, ly: u8) {
+        self.ly = ly;
+    }
+
+    pub fn set_lyc_flag(&mut self, value: bool) {
+        if value {
+            self.status |= 1 << 2;
+        } else {
+            self.status &= !(1 << 2);
+        }
+    }
+
+    pub fn take_stat_written(&mut self) -> bool {
+        std::mem::take(&mut self.stat_written)
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.lcdc & 0x80 != 0
+    }
+
+    pub fn mode(&self) -> u8 {
+        self.status & 0b11
+    }
+
     fn update_palette(&mut self, palette_data: u8, palette: u8) {
         let mut p_colors = &mut self.bg_colors;
         if palette == 1 {
@@ -103,14 +159,48 @@ impl LCD {
             p_colors = &mut self.sp2_colors;
         }
 
-        let colors = match self.color_mode {
-            ColorMode::ARGB => &COLORS_DEFAULT_ARGB,
-            ColorMode::RGBA => &COLORS_DEFAULT_RGBA,
-        };
+        let colors = &self.shades[palette as usize];
 
         p_colors[0] = colors[palette_data as usize & 0b11];
         p_colors[1] = colors[(palette_data >> 2) as usize & 0b11];
         p_colors[2] = colors[(palette_data >> 4) as usize & 0b11];
         p_colors[3] = colors[(palette_data >> 6) as usize & 0b11];
     }
-}
\ No newline at end of file
+
+    fn update_palettes(&mut self) {
+        self.update_palette(self.bg_palette, 0);
+        self.update_palette(self.obj_palette[0] & 0b11111100, 1);
+        self.update_palette(self.obj_palette[1] & 0b11111100, 2);
+    }
+}
+
+fn convert_palette(palette: &DmgPalette, color_mode: ColorMode) -> [[u32; 4]; 3] {
+    let palettes = [palette.bg, palette.obj0, palette.obj1];
+    std::array::from_fn(|palette| {
+        std::array::from_fn(|shade| color_mode.encode_shade(palettes[palette][shade], shade, palette))
+    })
+}
+
+// The colors come from the palette chosen in the settings
+impl SaveState for LCD {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.bytes(&[
+            self.lcdc, self.status, self.scroll_y, self.scroll_x, self.ly, self.ly_compare,
+            self.dma, self.bg_palette, self.obj_palette[0], self.obj_palette[1], self.win_y, self.win_x,
+        ]);
+        state.bool(self.stat_written);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), InvalidState> {
+        let mut registers = [0; 12];
+        state.bytes(&mut registers)?;
+        [
+            self.lcdc, self.status, self.scroll_y, self.scroll_x, self.ly, self.ly_compare,
+            self.dma, self.bg_palette, self.obj_palette[0], self.obj_palette[1], self.win_y, self.win_x,
+        ] = registers;
+        self.stat_written = state.bool()?;
+
+        self.update_palettes();
+        Ok(())
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{cpu::interrupts::InterruptType};
+use crate::{cpu::interrupts::InterruptType, state::{InvalidState, SaveState, StateReader, StateWriter}};
 
 #[derive(Debug, Default)]
 pub struct Timer {
@@ -21,7 +21,76 @@ impl Timer {
         }
     }
 
-    pub fn tick(&mut self) -> Option<InterruptType> {
+    /// Runs the timer for the given number of cycles, jumping directly
+    /// to the falling edges of the selected DIV bit. Returns true if
+    /// a timer interrupt was requested
+    pub fn advance(&mut self, mut cycles: u64) -> bool {
+        let mut interrupt = false;
+
+        while cycles > 0 {
+            if !self.tima_overflow && !self.tima_reload_cycle {
+                let skip = match self.cycles_to_falling_edge() {
+                    Some(edge) => (edge - 1).min(cycles),
+                    None => cycles,
+                };
+
+                if skip > 0 {
+                    // Nothing happens until the next falling edge
+                    self.div = self.div.wrapping_add(skip as u16);
+                    self.previous_result = self.timer_enabled() && self.selected_bit();
+                    cycles -= skip;
+                    continue;
+                }
+            }
+
+            interrupt |= self.tick().is_some();
+            cycles -= 1;
+        }
+        interrupt
+    }
+
+    /// Returns the number of cycles until the timer needs to be updated,
+    /// which is when TIMA overflows, or None if the timer is stopped
+    pub fn next_event(&self) -> Option<u64> {
+        if self.tima_overflow || self.tima_reload_cycle {
+            return Some(1)
+        }
+
+        let edge = self.cycles_to_falling_edge()?;
+        Some(edge + (0xFF - self.tima) as u64 * self.period())
+    }
+
+    /// The APU frame sequencer is clocked by the falling edge of DIV bit 4,
+    /// which is bit 12 of the internal counter
+    pub fn cycles_to_div_apu_edge(&self) -> u64 {
+        0x2000 - (self.div as u64 % 0x2000)
+    }
+
+    fn cycles_to_falling_edge(&self) -> Option<u64> {
+        if !self.timer_enabled() {
+            return None
+        }
+
+        let period = self.period();
+        Some(period - (self.div as u64 % period))
+    }
+
+    fn timer_enabled(&self) -> bool {
+        (self.tac & (1 << 2)) != 0
+    }
+
+    // The number of cycles between two increments of TIMA
+    fn period(&self) -> u64 {
+        match self.tac & 0b11 {
+            0b00 => 1024,
+            0b01 => 16,
+            0b10 => 64,
+            0b11 => 256,
+            _ => unreachable!()
+        }
+    }
+
+    fn tick(&mut self) -> Option<InterruptType> {
         let mut interrupt = None;
 
         self.div = self.div.wrapping_add(1);
@@ -143,3 +212,28 @@ impl Timer {
         }
     }
 }
+
+impl SaveState for Timer {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.u16(self.div);
+        state.bytes(&[self.tima, self.tma, self.tac]);
+
+        state.bool(self.previous_result);
+        state.bool(self.tima_overflow);
+        state.u8(self.tima_overflow_counter);
+        state.bool(self.tima_reload_cycle);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), InvalidState> {
+        self.div = state.u16()?;
+        let mut registers = [0; 3];
+        state.bytes(&mut registers)?;
+        [self.tima, self.tma, self.tac] = registers;
+
+        self.previous_result = state.bool()?;
+        self.tima_overflow = state.bool()?;
+        self.tima_overflow_counter = state.u8()?;
+        self.tima_reload_cycle = state.bool()?;
+        Ok(())
+    }
+}
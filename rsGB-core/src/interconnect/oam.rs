@@ -1,3 +1,5 @@
+use crate::state::{InvalidState, SaveState, StateReader, StateWriter};
+
 #[derive(Debug, Default, Clone, Copy)]
 pub struct OAMEntry {
     pub y: u8,
@@ -66,3 +68,94 @@ impl OAMEntry {
     }
 }
 
+impl SaveState for OAMEntry {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.bytes(&[self.y, self.x, self.tile, self.flags]);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), InvalidState> {
+        let mut bytes = [0; 4];
+        state.bytes(&mut bytes)?;
+        [self.y, self.x, self.tile, self.flags] = bytes;
+        Ok(())
+    }
+}
+
+
+/*
+OAM corruption bug (DMG only):
+ During mode 2, the PPU reads OAM one 8-byte row at a time. If the CPU puts an
+ address in 0xFE00-0xFEFF on the bus at the same time, the row being accessed
+ by the PPU gets corrupted with the contents of the preceding row.
+ The first row is never affected.
+*/
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OAMCorruption {
+    Read,
+    Write,
+    ReadIncrease,
+}
+
+pub fn corrupt_oam(oam: &mut [OAMEntry; 40], row: usize, corruption: OAMCorruption) {
+    if row == 0 || row >= 20 {
+        return
+    }
+
+    match corruption {
+        OAMCorruption::Write => {
+            let a = read_word(oam, row, 0);
+            let b = read_word(oam, row - 1, 0);
+            let c = read_word(oam, row - 1, 2);
+
+            write_word(oam, row, 0, ((a ^ c) & (b ^ c)) ^ c);
+            copy_row(oam, row - 1, row, 1);
+        }
+        OAMCorruption::Read => {
+            let a = read_word(oam, row, 0);
+            let b = read_word(oam, row - 1, 0);
+            let c = read_word(oam, row - 1, 2);
+
+            let value = b | (a & c);
+            write_word(oam, row - 1, 0, value);
+            write_word(oam, row, 0, value);
+            copy_row(oam, row - 1, row, 1);
+        }
+        OAMCorruption::ReadIncrease => {
+            // Only happens outside of the first four rows and the last one,
+            // the regular read corruption is applied afterwards by the read itself
+            if row < 4 || row == 19 {
+                return
+            }
+
+            let a = read_word(oam, row - 2, 0);
+            let b = read_word(oam, row - 1, 0);
+            let c = read_word(oam, row, 0);
+            let d = read_word(oam, row - 1, 2);
+
+            write_word(oam, row - 1, 0, (b & (a | c | d)) | (a & c & d));
+            copy_row(oam, row - 1, row - 2, 0);
+        }
+    }
+}
+
+// A row is made of two entries, so four 16-bit words
+fn read_word(oam: &[OAMEntry; 40], row: usize, word: usize) -> u16 {
+    let entry = &oam[row * 2 + word / 2];
+    let byte = (word % 2) as u8 * 2;
+    entry.read(byte) as u16 | (entry.read(byte + 1) as u16) << 8
+}
+
+fn write_word(oam: &mut [OAMEntry; 40], row: usize, word: usize, value: u16) {
+    let entry = &mut oam[row * 2 + word / 2];
+    let byte = (word % 2) as u8 * 2;
+    entry.write(byte, value as u8);
+    entry.write(byte + 1, (value >> 8) as u8);
+}
+
+fn copy_row(oam: &mut [OAMEntry; 40], src: usize, dst: usize, first_word: usize) {
+    for word in first_word..4 {
+        let value = read_word(oam, src, word);
+        write_word(oam, dst, word, value);
+    }
+}
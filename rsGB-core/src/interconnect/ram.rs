@@ -1,3 +1,5 @@
+use crate::state::{InvalidState, SaveState, StateReader, StateWriter};
+
 pub struct RAM {
     wram: [u8; 0x2000],
     hram: [u8; 0x80],
@@ -42,4 +44,16 @@ impl RAM {
 
         self.hram[index] = value;
     }
-}
\ No newline at end of file
+}
+
+impl SaveState for RAM {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.bytes(&self.wram);
+        state.bytes(&self.hram);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), InvalidState> {
+        state.bytes(&mut self.wram)?;
+        state.bytes(&mut self.hram)
+    }
+}
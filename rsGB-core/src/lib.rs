@@ -1,18 +1,31 @@
+mod archive;
+mod blending;
 mod cart;
 mod cpu;
 mod debug;
 mod interconnect;
 mod ppu;
+mod scheduler;
+mod state;
 mod utils;
+mod vgm;
+mod wav;
 pub mod settings;
 
-use std::path::{Path, PathBuf};
+use std::{error::Error, io, path::{Path, PathBuf}};
 
 use crate::{
-    cart::Cartridge, cpu::CPU, interconnect::Interconnect, ppu::PPU, settings::SaveLocation, utils::TICKS_PER_SAMPLE
+    cart::Cartridge, cpu::CPU, interconnect::{Interconnect, OAMCorruption}, ppu::PPU, scheduler::EventType, settings::SaveLocation,
+    state::{SaveState, StateReader, StateWriter}, utils::{AUDIO_BATCH_CYCLES, DEFAULT_SAMPLE_RATE}
 };
 
+pub use archive::{InvalidArchive, archived_roms};
+pub use blending::FrameBlender;
+pub use cart::GbsInfo;
 pub use debug::DebugInfo;
+pub use state::InvalidState;
+pub use vgm::VgmRecorder;
+pub use wav::WavRecorder;
 
 pub use utils::{
     Button, InputState,
@@ -28,55 +41,149 @@ struct Devices {
     ppu: PPU,
 
     audio_callback: Box<dyn FnMut((f32, f32)) + Send>,
+    channel_callback: Option<Box<dyn FnMut([f32; 4]) + Send>>,
+    recorder: Option<WavRecorder>,
     framebuffer: Option<*mut [u32]>,
 
     speed: u8,
     frames: u8,
 
-    ticks: u64,
-    last_sample_tick: u64,
+    ppu_synced: u64, // The timestamp the PPU was last updated at
+
+    breakpoint: bool,
 }
 
 impl Devices {
-    fn new<F>(bus: Interconnect, ppu: PPU, audio_callback: F) -> Devices 
+    fn new<F>(mut bus: Interconnect, ppu: PPU, audio_callback: F) -> Devices 
     where F: FnMut((f32, f32)) + Send + 'static {
+        bus.scheduler.schedule(EventType::Ppu, 1);
+        bus.scheduler.schedule(EventType::AudioOutput, AUDIO_BATCH_CYCLES);
+
         Devices {
             bus,
             ppu,
             audio_callback: Box::new(audio_callback),
+            channel_callback: None,
+            recorder: None,
             framebuffer: None,
 
             speed: 1,
             frames: 0,
 
-            ticks: 0,
-            last_sample_tick: 0,
+            ppu_synced: 0,
+
+            breakpoint: false,
         }
     }
 
+    /// Replaces the bus and the PPU by powered off ones. The callbacks,
+    /// the WAV recording and the speed are kept
+    fn power_cycle(&mut self, mut bus: Interconnect, sample_rate: u32) {
+        bus.scheduler.schedule(EventType::Ppu, 1);
+        bus.scheduler.schedule(EventType::AudioOutput, AUDIO_BATCH_CYCLES);
+        bus.apu_set_sample_rate(sample_rate, self.speed);
+        bus.apu_enable_channel_outputs(self.channel_callback.is_some());
+
+        self.bus = bus;
+        self.ppu = PPU::new();
+        self.frames = 0;
+        self.ppu_synced = 0;
+        self.breakpoint = false;
+    }
+
+    /// The devices are only updated when one of their events is due
     fn incr_cycle(&mut self, cpu_cycles: u16) {
         for _ in 0..cpu_cycles {
-            for _ in 0..4 {
-                self.ticks += 1;
-                self.bus.tick_t();
-                if let Some(ptr) = self.framebuffer {
-                    unsafe {
-                        let fb = &mut *ptr;
-                        if self.ppu.tick(&mut self.bus, fb, self.frames == self.speed - 1) { // Frame updated
-                            self.frames += 1;
+            self.bus.scheduler.advance(4);
+
+            while let Some((event, timestamp)) = self.bus.scheduler.pop_due() {
+                match event {
+                    EventType::Ppu => self.sync_ppu(),
+                    EventType::AudioOutput => {
+                        self.bus.sync_apu();
+                        let (callback, recorder) = (&mut self.audio_callback, &mut self.recorder);
+                        self.bus.apu_read_samples(|sample| {
+                            if let Some(recorder) = recorder {
+                                recorder.write_sample(sample);
+                            }
+                            callback(sample)
+                        });
+                        if let Some(callback) = &mut self.channel_callback {
+                            self.bus.apu_read_channel_samples(callback);
                         }
-                    } 
+                        self.bus.scheduler.schedule(EventType::AudioOutput, timestamp + AUDIO_BATCH_CYCLES);
+                    }
+                    _ => self.bus.handle_event(event, timestamp),
                 }
+            }
+        }
+    }
 
-                if self.ticks - self.last_sample_tick >= TICKS_PER_SAMPLE * self.speed as u64 {
-                    if let Some(sample) = self.bus.apu_output() {
-                        (self.audio_callback)(sample)
-                    }
-                    self.last_sample_tick = self.ticks;
+    /// Runs the PPU up to the current cycle, and schedules
+    /// its update at the next dot where something happens
+    fn sync_ppu(&mut self) {
+        let now = self.bus.scheduler.now();
+
+        if let Some(ptr) = self.framebuffer {
+            let fb = unsafe { &mut *ptr };
+
+            while self.ppu_synced < now {
+                let render = self.frames == self.speed - 1;
+                let (dots, frame) = self.ppu.run(&mut self.bus, fb, (now - self.ppu_synced) as u32, render);
+
+                self.ppu_synced += dots as u64;
+                if frame { // Frame updated
+                    self.frames += 1;
                 }
             }
-            self.bus.tick_m();
+
+            let idle = self.ppu.idle_dots(&mut self.bus) as u64;
+            self.bus.scheduler.schedule(EventType::Ppu, now + idle + 1);
+        } else {
+            self.ppu_synced = now;
+            self.bus.scheduler.schedule(EventType::Ppu, now + 1);
+        }
+    }
+
+    // The PPU state is visible through the VRAM and OAM locks and the LCD registers
+    fn ppu_address(address: u16) -> bool {
+        matches!(address, 0x8000..0xA000 | 0xFE00..0xFF00 | 0xFF40..0xFF4C)
+    }
+
+    /// The CPU accesses the memory through these functions, which
+    /// bring the devices up to date before the access
+    fn read(&mut self, address: u16) -> u8 {
+        if Self::ppu_address(address) {
+            self.sync_ppu();
+        }
+        self.bus.catch_up(address);
+        self.bus.read(address)
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        if Self::ppu_address(address) {
+            self.sync_ppu();
+            self.bus.write(address, value);
+
+            if (0xFF40..0xFF4C).contains(&address) {
+                self.ppu.lcd_written();
+                self.bus.scheduler.schedule_in(EventType::Ppu, 1);
+            }
+        } else {
+            self.bus.write(address, value);
+        }
+    }
+
+    fn write16(&mut self, address: u16, value: u16) {
+        self.write(address, value as u8);
+        self.write(address + 1, (value >> 8) as u8);
+    }
+
+    fn trigger_oam_bug(&mut self, address: u16, corruption: OAMCorruption) {
+        if Self::ppu_address(address) {
+            self.sync_ppu();
         }
+        self.bus.trigger_oam_bug(address, corruption);
     }
 
     fn attach_buffer(&mut self, framebuffer: &mut [u32]) {
@@ -91,11 +198,41 @@ impl Devices {
     }
 }
 
+// The callbacks and the recording are kept when a state is loaded
+impl SaveState for Devices {
+    fn save_state(&self, state: &mut StateWriter) {
+        self.bus.save_state(state);
+        self.ppu.save_state(state);
+        state.u64(self.ppu_synced);
+        state.u8(self.frames);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), InvalidState> {
+        self.bus.load_state(state)?;
+        self.ppu.load_state(state)?;
+        self.ppu_synced = state.u64()?;
+        self.frames = state.u8()?;
+        if self.ppu_synced > self.bus.scheduler.now() {
+            return Err(InvalidState::new("devices updated in the future"))
+        }
+        Ok(())
+    }
+}
+
+const STATE_MAGIC: &[u8; 4] = b"RGBS";
+const STATE_VERSION: u8 = 5;
+const GAME_ID_LEN: usize = 18;
+const STATE_HEADER_LEN: usize = STATE_MAGIC.len() + 1 + GAME_ID_LEN;
+
 pub struct Gameboy {
     cpu: CPU,
     devices: Devices,
 
     save_path: PathBuf,
+    sample_rate: u32,
+    blender: FrameBlender,
+
+    paused: bool,
 }
 
 impl Gameboy {
@@ -110,54 +247,281 @@ impl Gameboy {
             devices,
 
             save_path: PathBuf::new(),
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            blender: FrameBlender::new(),
+
+            paused: false,
         }
     }
 
-    pub fn load_cartridge(&mut self, rom_path: &PathBuf, settings: &Settings) {
+    pub fn load_cartridge(&mut self, rom_path: &Path, settings: &Settings) -> Result<(), Box<dyn Error>> {
+        self.load_archived_cartridge(rom_path, None, settings)
+    }
+
+    /// Loads the ROM with this name in a zip, 7z or gzip archive, or its
+    /// first one. The save is named after the ROM instead of the archive
+    pub fn load_archived_cartridge(&mut self, archive_path: &Path, rom_name: Option<&str>, settings: &Settings) -> Result<(), Box<dyn Error>> {
+        let mut cartridge = Cartridge::load(archive_path, rom_name)?;
+
+        // The ROM can be in a folder of the archive
+        let mut file_name = PathBuf::from(Path::new(&cartridge.rom_name).file_name().unwrap_or_default());
+        file_name.set_extension("sav");
+
         let save_path = match settings.get_save_location() {
-            SaveLocation::GameLoc => {
-                let mut clone = rom_path.clone();
-                clone.set_extension("sav");
-                clone
-            },
-            SaveLocation::SaveFolder(path) => {
-                let file_name = Path::new(self.save_path.file_name().unwrap());
-                
-                let mut clone = path.clone();
-                clone.push(file_name);
-
-                clone
-            }
+            SaveLocation::GameLoc => archive_path.with_file_name(file_name),
+            SaveLocation::SaveFolder(path) => path.join(file_name),
         };
 
-        let cartridge = Cartridge::load(rom_path).unwrap();
+        cartridge.load_save(&save_path)?;
         self.devices.bus.set_cart(cartridge);
-        self.devices.bus.load_save(&save_path);
+        self.blender.reset();
 
         self.save_path = save_path;
+        Ok(())
+    }
+
+    /// Loads a GBS file and starts playing its first track. The PPU is
+    /// only used for the VBlank interrupts, the screen stays blank
+    pub fn load_gbs(&mut self, gbs_path: &Path) -> Result<GbsInfo, Box<dyn Error>> {
+        let cartridge = Cartridge::load_gbs(gbs_path)?;
+        let info = cartridge.gbs_info.clone().unwrap();
+
+        self.power_cycle(Some(cartridge))?;
+        self.save_path = PathBuf::new();
+
+        Ok(info)
+    }
+
+    /// Restarts the driver of the GBS file with another track, starting at 0.
+    /// The console is power cycled, so that the INIT routine of the track
+    /// doesn't see what the previous one left in the memory and the registers
+    pub fn select_track(&mut self, track: u8) -> io::Result<()> {
+        if let Some(cart) = &mut self.devices.bus.cart
+            && let Some(info) = &cart.gbs_info
+            && track < info.track_count {
+            cart.select_track(track);
+
+            let cart = self.devices.bus.cart.take();
+            return self.power_cycle(cart)
+        }
+        Ok(())
+    }
+
+    pub fn gbs_info(&self) -> Option<&GbsInfo> {
+        self.devices.bus.cart.as_ref()?.gbs_info.as_ref()
+    }
+
+    /// Power cycles the console with the same cartridge, without reading it
+    /// from the disk again. Its RAM and clock are kept. The cycles restart
+    /// from 0, so a VGM log is finished first
+    pub fn reset(&mut self) -> io::Result<()> {
+        let cart = self.devices.bus.cart.take();
+        self.power_cycle(cart)
+    }
+
+    /// Removes the cartridge, the console is left turned off
+    pub fn eject(&mut self) -> io::Result<()> {
+        self.power_cycle(None)?;
+        self.save_path = PathBuf::new();
+        Ok(())
+    }
+
+    fn power_cycle(&mut self, cart: Option<Cartridge>) -> io::Result<()> {
+        let result = self.stop_vgm_log();
+
+        let mut bus = Interconnect::new(self.devices.bus.lcd_color_mode());
+        if let Some(mut cart) = cart {
+            cart.reset();
+            bus.set_cart(cart);
+        }
+
+        self.devices.power_cycle(bus, self.sample_rate);
+        self.cpu = CPU::new();
+        self.blender.reset();
+        result
+    }
+
+    /// Where the battery save of the game is written, None for GBS files
+    pub fn save_path(&self) -> Option<&Path> {
+        (!self.save_path.as_os_str().is_empty()).then_some(self.save_path.as_path())
+    }
+
+    /// Saves the state of the console and the cartridge, its RAM included
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = StateWriter::new();
+        state.bytes(STATE_MAGIC);
+        state.u8(STATE_VERSION);
+        state.bytes(&self.game_id());
+
+        self.cpu.save_state(&mut state);
+        self.devices.save_state(&mut state);
+        state.finish()
+    }
+
+    /// Loads a state saved with the same game. The console is left as it was
+    /// if the state is invalid. The cycles jump, so a VGM log is finished first
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        let mut state = StateReader::new(data);
+
+        let mut magic = [0; STATE_MAGIC.len()];
+        state.bytes(&mut magic)?;
+        if &magic != STATE_MAGIC {
+            return Err(InvalidState::new("not a save state").into())
+        }
+        if state.u8()? != STATE_VERSION {
+            return Err(InvalidState::new("saved by another version").into())
+        }
+        let mut game_id = [0; GAME_ID_LEN];
+        state.bytes(&mut game_id)?;
+        if game_id != self.game_id() {
+            return Err(InvalidState::new("saved with another game").into())
+        }
+
+        self.stop_vgm_log()?;
+        let backup = self.save_state();
+        if let Err(error) = self.load_components(state) {
+            self.load_components(StateReader::new(&backup[STATE_HEADER_LEN..])).unwrap();
+            return Err(error.into())
+        }
+
+        self.blender.reset();
+        Ok(())
+    }
+
+    fn load_components(&mut self, mut state: StateReader) -> Result<(), InvalidState> {
+        self.cpu.load_state(&mut state)?;
+        self.devices.load_state(&mut state)?;
+        state.finish()
+    }
+
+    // The title and the global checksum of the cartridge header
+    fn game_id(&self) -> [u8; GAME_ID_LEN] {
+        let mut id = [0; GAME_ID_LEN];
+        if let Some(cart) = &self.devices.bus.cart {
+            let title = cart.header.title.as_bytes();
+            let len = title.len().min(16);
+            id[..len].copy_from_slice(&title[..len]);
+            id[16..].copy_from_slice(&cart.header.global_checksum.to_le_bytes());
+        }
+        id
+    }
+
+    /// While paused, `next_frame` leaves the console and the framebuffer
+    /// as they are. `advance_frame` still runs one frame at a time
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
     }
 
     pub fn next_frame(&mut self, framebuffer: &mut [u32], settings: &Settings) {
+        if !self.paused {
+            self.advance_frame(framebuffer, settings);
+        }
+    }
+
+    /// Runs one frame, even if the console is paused
+    pub fn advance_frame(&mut self, framebuffer: &mut [u32], settings: &Settings) {
         self.devices.attach_buffer(framebuffer);
 
         let speed = settings.speed as u8;
+        if speed != self.devices.speed {
+            self.devices.bus.apu_set_speed(speed);
+        }
         self.devices.speed = speed;
+        self.devices.bus.apu_set_filter(settings.audio_filter);
+        self.devices.bus.apu_set_channel_mix(settings.muted_channels, settings.solo_channels);
+        self.devices.bus.lcd_set_palette(&settings.palette);
+        self.devices.breakpoint = false;
+        self.devices.ppu.set_scanline_renderer(settings.scanline_renderer);
 
         while self.devices.frames < speed {
             self.cpu.step(&mut self.devices);
+
+            if settings.software_breakpoints && self.devices.breakpoint {
+                break
+            }
         }
         
         if self.devices.bus.need_save() {
             self.devices.bus.save(&self.save_path);
         }
+        self.blender.apply(settings.frame_blending, self.devices.bus.lcd_color_mode(), framebuffer);
         self.devices.frames = 0;
         self.devices.detach_buffer();
     }
 
+    /// Sets the sample rate of the audio sent to the callback,
+    /// 44100 Hz by default
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.devices.bus.apu_set_sample_rate(sample_rate, self.devices.speed);
+    }
+
+    /// Starts writing the audio output to a WAV file, at the current sample
+    /// rate. A previous recording is finished first
+    pub fn start_recording(&mut self, path: &Path) -> io::Result<()> {
+        self.stop_recording()?;
+        self.devices.recorder = Some(WavRecorder::create(path, self.sample_rate)?);
+        Ok(())
+    }
+
+    pub fn stop_recording(&mut self) -> io::Result<()> {
+        match self.devices.recorder.take() {
+            Some(recorder) => recorder.finish(),
+            None => Ok(()),
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.devices.recorder.is_some()
+    }
+
+    /// Starts logging the writes to the APU registers to a VGM file.
+    /// A previous log is finished first
+    pub fn start_vgm_log(&mut self, path: &Path) -> io::Result<()> {
+        self.stop_vgm_log()?;
+        self.devices.bus.apu_start_vgm_log(path)
+    }
+
+    pub fn stop_vgm_log(&mut self) -> io::Result<()> {
+        self.devices.bus.apu_stop_vgm_log()
+    }
+
+    pub fn is_vgm_logging(&self) -> bool {
+        self.devices.bus.apu_vgm_logging()
+    }
+
+    /// Sets a callback receiving the output of each channel separately,
+    /// before they are mixed, at the same rate as the audio callback
+    pub fn set_channel_callback<F>(&mut self, channel_callback: F)
+    where F: FnMut([f32; 4]) + Send + 'static {
+        self.devices.bus.apu_enable_channel_outputs(true);
+        self.devices.channel_callback = Some(Box::new(channel_callback));
+    }
+
+    pub fn remove_channel_callback(&mut self) {
+        self.devices.bus.apu_enable_channel_outputs(false);
+        self.devices.channel_callback = None;
+    }
+
     pub fn apply_input(&mut self, input: InputState) {
         self.devices.bus.update_input(input);
     }
 
+    /// Returns true if the last call to `next_frame` was stopped
+    /// by a software breakpoint (`LD B,B`)
+    pub fn breakpoint_hit(&self) -> bool {
+        self.devices.breakpoint
+    }
+
+    /// Returns the number of T-cycles emulated since power on
+    pub fn cycles(&self) -> u64 {
+        self.devices.bus.scheduler.now()
+    }
+
     pub fn cartridge_loaded(&self) -> bool {
         self.devices.bus.cart.is_some()
     }
@@ -169,8 +533,7 @@ impl Gameboy {
         DebugInfo::new(
             &self.cpu, 
             vram_updated,
-            &self.devices.bus.vram, 
-            &self.devices.bus.cart.as_ref().unwrap()
+            &self.devices.bus,
         )
     }
 }
@@ -1,23 +1,24 @@
-use crate::{interconnect::{Interconnect, OAMEntry}, utils::BoundedQueue};
+use crate::{interconnect::{Interconnect, OAMEntry}, state::{InvalidState, SaveState, StateReader, StateWriter}, utils::BoundedQueue};
 
 mod state_machine;
 mod pipeline;
 mod utils;
 mod fetcher;
+mod scanline;
 
 use fetcher::Fetcher;
-use utils::{status_mode, LCDMode};
+use utils::{lcdc_lcd_enable, status_mode, LCDMode};
 
 const LINES_PER_FRAME: u8 = 154;
 const TICKS_PER_LINE: u32 = 456;
-const YRES: usize = 144;
-const XRES: usize = 160;
+pub(crate) const YRES: usize = 144;
+pub(crate) const XRES: usize = 160;
 
 #[derive(Debug)]
 pub struct PPU {
     fetcher: Fetcher,
-    bgw_fifo: BoundedQueue<(u32, u8), 8>,
-    obj_fifo: BoundedQueue<(u32, u8, bool), 8>,
+    bgw_fifo: BoundedQueue<u8, 8>, // The color index of each pixel
+    obj_fifo: BoundedQueue<(u8, bool, bool), 8>, // The color index, palette number and OBJ-to-BG priority
 
     visible_sprites: Vec<OAMEntry>,
     fetched_sprites: [bool; 10],
@@ -25,9 +26,25 @@ pub struct PPU {
     pushed_x: u8, // The pixel position to push in the framebuffer
     current_x: u8, // The current position we're dealing with on the screen
 
+    window_y_triggered: bool, // LY was equal to WY at some point during the frame
+    window_next_line: bool, // Set when the window was triggered with WX=166
+    window_skip: u8, // The number of window pixels to discard when WX < 7
+    window_line: u8, // The window internal line counter
+    window_drawn: bool, // The window line counter only increments on lines where the window was drawn
+
+    scanline_renderer: bool, // Draw whole lines at the end of mode 3 instead of running the pixel FIFO
+    xfer_end: u32, // The dot at which mode 3 ends, computed ahead by the scanline renderer
+
+    lcd_written: bool, // The LCD registers were written since the last dot
+
     current_frame: u32,
     line_ticks: u32,
     new_frame: bool,
+
+    lcd_enabled: bool,
+    lcd_on_line: bool, // The first line after the LCD is turned on has no OAM scan
+    skip_frame: bool, // The first frame after the LCD is turned on isn't displayed
+    stat_line: bool, // The STAT interrupt line, interrupts are requested on its rising edge
 }
 
 impl PPU {
@@ -43,13 +60,73 @@ impl PPU {
             pushed_x: 0,
             current_x: 0,
 
+            window_y_triggered: false,
+            window_next_line: false,
+            window_skip: 0,
+            window_line: 0,
+            window_drawn: false,
+
+            scanline_renderer: false,
+            xfer_end: 0,
+
+            lcd_written: false,
+
             current_frame: 0,
             line_ticks: 0,
             new_frame: false,
+
+            lcd_enabled: true,
+            lcd_on_line: false,
+            skip_frame: false,
+            stat_line: false,
+        }
+    }
+
+    /// The renderer can be changed between frames, the mode timings
+    /// and the interrupts are the same with both renderers
+    pub fn set_scanline_renderer(&mut self, enabled: bool) {
+        self.scanline_renderer = enabled;
+    }
+
+    /// Must be called when the CPU writes to the LCD registers,
+    /// so the next dot isn't skipped
+    pub fn lcd_written(&mut self) {
+        self.lcd_written = true;
+    }
+
+    /// Runs the PPU for up to `dots` dots, skipping the ones where nothing happens.
+    /// Stops after a frame is completed, and returns the number
+    /// of dots that were run and whether a frame was completed
+    pub fn run(&mut self, bus: &mut Interconnect, framebuffer: &mut [u32], dots: u32, render: bool) -> (u32, bool) {
+        let mut ran = 0;
+
+        while ran < dots {
+            let idle = self.idle_dots(bus).min(dots - ran);
+            self.line_ticks += idle;
+            ran += idle;
+
+            if ran < dots {
+                ran += 1;
+                if self.tick(bus, framebuffer, render) {
+                    return (ran, true)
+                }
+            }
         }
+        (ran, false)
     }
 
     pub fn tick(&mut self, bus: &mut Interconnect, framebuffer: &mut [u32], render: bool) -> bool {
+        self.lcd_written = false;
+
+        if !lcdc_lcd_enable(bus) {
+            if self.lcd_enabled {
+                self.lcd_off(bus, framebuffer);
+            }
+            return self.lcd_off_tick(bus, framebuffer, render);
+        } else if !self.lcd_enabled {
+            self.lcd_on(bus);
+        }
+
         self.line_ticks += 1;
 
         let lcd_mode = status_mode(bus);
@@ -58,9 +135,11 @@ impl PPU {
             LCDMode::HBlank => self.hblank(bus),
             LCDMode::VBlank => self.vblank(bus),
             LCDMode::OAM => self.oam(bus),
-            LCDMode::XFer => self.xfer(bus, framebuffer, render),
+            LCDMode::XFer => self.xfer(bus, framebuffer, render && !self.skip_frame),
         };
 
+        self.update_stat_line(bus);
+
         if self.new_frame {
             self.new_frame = false;
             true
@@ -68,4 +147,108 @@ impl PPU {
             false
         }
     }
-}
\ No newline at end of file
+}
+
+// The renderer is chosen in the settings
+impl SaveState for PPU {
+    fn save_state(&self, state: &mut StateWriter) {
+        self.fetcher.save_state(state);
+        state.u8(self.bgw_fifo.len() as u8);
+        for i in 0..self.bgw_fifo.len() {
+            state.u8(self.bgw_fifo[i]);
+        }
+        state.u8(self.obj_fifo.len() as u8);
+        for i in 0..self.obj_fifo.len() {
+            let (index, palette, bg_priority) = self.obj_fifo[i];
+            state.u8(index);
+            state.bool(palette);
+            state.bool(bg_priority);
+        }
+
+        state.u8(self.visible_sprites.len() as u8);
+        for sprite in &self.visible_sprites {
+            sprite.save_state(state);
+        }
+        for fetched in self.fetched_sprites {
+            state.bool(fetched);
+        }
+
+        state.u8(self.pushed_x);
+        state.u8(self.current_x);
+
+        state.bool(self.window_y_triggered);
+        state.bool(self.window_next_line);
+        state.u8(self.window_skip);
+        state.u8(self.window_line);
+        state.bool(self.window_drawn);
+
+        state.u32(self.xfer_end);
+        state.bool(self.lcd_written);
+
+        state.u32(self.current_frame);
+        state.u32(self.line_ticks);
+        state.bool(self.new_frame);
+
+        state.bool(self.lcd_enabled);
+        state.bool(self.lcd_on_line);
+        state.bool(self.skip_frame);
+        state.bool(self.stat_line);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), InvalidState> {
+        let too_long = || InvalidState::new("invalid PPU queue length");
+
+        self.fetcher.load_state(state)?;
+        self.bgw_fifo.clear();
+        for _ in 0..state.u8()? {
+            self.bgw_fifo.push_back(state.u8()?).map_err(|_| too_long())?;
+        }
+        self.obj_fifo.clear();
+        for _ in 0..state.u8()? {
+            let pixel = (state.u8()?, state.bool()?, state.bool()?);
+            self.obj_fifo.push_back(pixel).map_err(|_| too_long())?;
+        }
+
+        let sprite_count = state.u8()?;
+        if sprite_count > 10 {
+            return Err(too_long())
+        }
+        self.visible_sprites.clear();
+        for _ in 0..sprite_count {
+            let mut sprite = OAMEntry::new();
+            sprite.load_state(state)?;
+            self.visible_sprites.push(sprite);
+        }
+        for fetched in &mut self.fetched_sprites {
+            *fetched = state.bool()?;
+        }
+
+        self.pushed_x = state.u8()?;
+        self.current_x = state.u8()?;
+
+        self.window_y_triggered = state.bool()?;
+        self.window_next_line = state.bool()?;
+        self.window_skip = state.u8()?;
+        self.window_line = state.u8()?;
+        self.window_drawn = state.bool()?;
+
+        self.xfer_end = state.u32()?;
+        self.lcd_written = state.bool()?;
+
+        self.current_frame = state.u32()?;
+        self.line_ticks = state.u32()?;
+        self.new_frame = state.bool()?;
+
+        self.lcd_enabled = state.bool()?;
+        self.lcd_on_line = state.bool()?;
+        self.skip_frame = state.bool()?;
+        self.stat_line = state.bool()?;
+
+        // While the LCD is off, the ticks are counted over the whole frame
+        let max_ticks = if self.lcd_enabled { TICKS_PER_LINE } else { TICKS_PER_LINE * LINES_PER_FRAME as u32 };
+        if self.pushed_x as usize > XRES || self.window_skip > 7 || self.line_ticks >= max_ticks {
+            return Err(InvalidState::new("invalid PPU position"))
+        }
+        Ok(())
+    }
+}
@@ -1,6 +1,8 @@
-use std::u32;
 
-use crate::{interconnect::{Interconnect, OAMEntry}, ppu::utils::{lcd_read_ly, lcd_read_scroll_x, lcd_read_scroll_y, lcdc_bg_map_area, lcdc_bgw_data_area, lcdc_bgw_enable, lcdc_obj_height, lcdc_win_map_area}};
+use crate::{interconnect::{Interconnect, OAMEntry}, state::{InvalidState, SaveState, StateReader, StateWriter}, ppu::utils::{lcd_read_ly, lcd_read_scroll_x, lcd_read_scroll_y, lcdc_bg_map_area, bgw_tile_address, lcdc_obj_height, lcdc_win_map_area}};
+
+// The first fetch of a line is discarded, which delays mode 3 by 3 dots
+pub(super) const STARTUP_DELAY: u8 = 3;
 
 #[derive(Debug)]
 enum Step {
@@ -25,6 +27,7 @@ enum FetchMode {
 #[derive(Debug)]
 pub(super) struct Fetcher {
     state: FetchState,
+    sprite_state: FetchState,
     mode: FetchMode,
 
     pub lx: u8,
@@ -32,9 +35,11 @@ pub(super) struct Fetcher {
     bgw_fetched_data: [u8; 3],
     data_address: u16,
 
-    window_line: u8,
+    window_line: u8, // The window line being fetched, from the PPU internal counter
 
     fetching_sprite: bool,
+    sprite_wait: u8, // The dots the sprite fetch waits for the background fetcher
+    startup_delay: u8, // The first tile of a line is fetched twice
     current_sprite: Option<OAMEntry>,
     sprite_data: [u8; 2],
 
@@ -45,6 +50,7 @@ impl Fetcher {
     pub fn new() -> Fetcher {
         Fetcher { 
             state : FetchState::TileID(Step::First), 
+            sprite_state: FetchState::TileID(Step::First),
             mode: FetchMode::Background,
 
             lx: 0,
@@ -55,6 +61,8 @@ impl Fetcher {
             window_line: 0,
 
             fetching_sprite: false,
+            sprite_wait: 0,
+            startup_delay: STARTUP_DELAY,
             current_sprite: None,
             sprite_data: [0; 2],
 
@@ -64,28 +72,30 @@ impl Fetcher {
 
     pub fn reset(&mut self) {
         self.state = FetchState::TileID(Step::First);
+        self.sprite_state = FetchState::TileID(Step::First);
         self.mode = FetchMode::Background;
         self.fetching_sprite = false;
+        self.sprite_wait = 0;
+        self.startup_delay = STARTUP_DELAY;
 
         self.lx = 0;
         self.pushed_x = 0;
     }
 
-    pub fn reset_window_line(&mut self) {
-        self.window_line = 0;
-    }
-
-    pub fn switch_to_window(&mut self) {
+    pub fn switch_to_window(&mut self, window_line: u8) {
         // When switching to window mid-scanline, reset fetch position
         self.mode = FetchMode::Window;
+        self.window_line = window_line;
         self.lx = 0;
         self.state = FetchState::TileID(Step::First);
     }
 
-    pub fn increment_window_line(&mut self) {
-        if self.mode == FetchMode::Window {
-            self.window_line += 1;
-        }
+    /// When the window is disabled mid-scanline, the background
+    /// is fetched again from the current screen position
+    pub fn switch_to_background(&mut self, lx: u8) {
+        self.mode = FetchMode::Background;
+        self.lx = lx;
+        self.state = FetchState::TileID(Step::First);
     }
 
     pub fn is_window_mode(&self) -> bool {
@@ -96,21 +106,23 @@ impl Fetcher {
         self.fetching_sprite
     }
 
-    pub fn trigger_sprite_fetching(&mut self, sprite: OAMEntry) {
+    pub fn trigger_sprite_fetching(&mut self, sprite: OAMEntry, wait: u8) {
         self.fetching_sprite = true;
+        self.sprite_wait = wait;
         self.current_sprite = Some(sprite);
-        self.state = FetchState::TileRowLow(Step::First);
+        self.sprite_state = FetchState::TileID(Step::First);
     }
 
-    pub fn fetch(&mut self, bus: &mut Interconnect) {
-        if self.fetching_sprite {
-            self.fetch_sprite(bus);
-        } else {
-            self.fetch_bgw(bus);
-        }
+    pub fn bgw_ready(&self) -> bool {
+        matches!(self.state, FetchState::Push)
     }
 
-    fn fetch_bgw(&mut self, bus: &mut Interconnect) {
+    pub fn fetch_bgw(&mut self, bus: &mut Interconnect) {
+        if self.startup_delay > 0 {
+            self.startup_delay -= 1;
+            return;
+        }
+
         match self.state {
             FetchState::TileID(Step::First) => {                
                 self.tile_address = if self.mode == FetchMode::Background {
@@ -132,43 +144,29 @@ impl Fetcher {
             }
 
             FetchState::TileID(Step::Second) => {
-                self.bgw_fetched_data[0] = bus.read(self.tile_address);
+                self.bgw_fetched_data[0] = bus.vram_read(self.tile_address);
                 self.state = FetchState::TileRowLow(Step::First);
             }
 
             FetchState::TileRowLow(Step::First) => {
-                let bgw_data_area = lcdc_bgw_data_area(bus);
-                let tile_id = self.bgw_fetched_data[0];
-                
-                let tile_row = if self.mode == FetchMode::Background {
-                    let (ly, scy) = (lcd_read_ly(bus), lcd_read_scroll_y(bus));
-                    ly.wrapping_add(scy) % 8
-                } else {
-                    self.window_line % 8
-                };
-
-                self.data_address = if bgw_data_area == 0x8000 {
-                    // Unsigned: 0x8000-0x8FFF, tile_id as u8
-                    0x8000 + ((tile_id as u16) << 4) + ((tile_row as u16) << 1)
-                } else {
-                    // Signed: 0x9000 base, tile_id as i8
-                    0x9000_u16.wrapping_add_signed((tile_id as i8 as i16) << 4) + ((tile_row as u16) << 1)
-                };
+                self.data_address = self.tile_data_address(bus);
                 self.state = FetchState::TileRowLow(Step::Second);
             }
 
             FetchState::TileRowLow(Step::Second) => {
-                self.bgw_fetched_data[1] = bus.read(self.data_address);
+                self.bgw_fetched_data[1] = bus.vram_read(self.data_address);
                 self.state = FetchState::TileRowHigh(Step::First);
             }
 
             FetchState::TileRowHigh(Step::First) => { 
-                self.data_address += 1;
+                // SCY and LCDC are read again, so mid-scanline
+                // writes can affect the two bytes of a tile differently
+                self.data_address = self.tile_data_address(bus) + 1;
                 self.state = FetchState::TileRowHigh(Step::Second);
             }
 
             FetchState::TileRowHigh(Step::Second) => {
-                self.bgw_fetched_data[2] = bus.read(self.data_address);
+                self.bgw_fetched_data[2] = bus.vram_read(self.data_address);
                 self.state = FetchState::Push;
             }
 
@@ -176,8 +174,29 @@ impl Fetcher {
         }
     }
 
-    fn fetch_sprite(&mut self, bus: &mut Interconnect) {
-        match self.state {
+    fn tile_data_address(&self, bus: &mut Interconnect) -> u16 {
+        let tile_row = if self.mode == FetchMode::Background {
+            let (ly, scy) = (lcd_read_ly(bus), lcd_read_scroll_y(bus));
+            ly.wrapping_add(scy) % 8
+        } else {
+            self.window_line % 8
+        };
+
+        bgw_tile_address(bus, self.bgw_fetched_data[0], tile_row)
+    }
+
+    pub fn fetch_sprite(&mut self, bus: &mut Interconnect) {
+        if self.sprite_wait > 0 {
+            self.sprite_wait -= 1;
+            return;
+        }
+
+        match self.sprite_state {
+            // The tile ID was already read during the OAM scan
+            FetchState::TileID(Step::First) => self.sprite_state = FetchState::TileID(Step::Second),
+
+            FetchState::TileID(Step::Second) => self.sprite_state = FetchState::TileRowLow(Step::First),
+
             FetchState::TileRowLow(Step::First) => {
                 let ly = lcd_read_ly(bus);
                 let sprite_height = lcdc_obj_height(bus);         
@@ -195,22 +214,22 @@ impl Fetcher {
                 };
 
                 self.data_address = 0x8000 + (tile_index << 4) + ((tile_y as u16) << 1);
-                self.state = FetchState::TileRowLow(Step::Second);
+                self.sprite_state = FetchState::TileRowLow(Step::Second);
             }
 
             FetchState::TileRowLow(Step::Second) => {
-                self.sprite_data[0] = bus.read(self.data_address);
-                self.state = FetchState::TileRowHigh(Step::First);
+                self.sprite_data[0] = bus.vram_read(self.data_address);
+                self.sprite_state = FetchState::TileRowHigh(Step::First);
             }
 
             FetchState::TileRowHigh(Step::First) => { 
                 self.data_address += 1;
-                self.state = FetchState::TileRowHigh(Step::Second);
+                self.sprite_state = FetchState::TileRowHigh(Step::Second);
             }
 
             FetchState::TileRowHigh(Step::Second) => {
-                self.sprite_data[1] = bus.read(self.data_address);
-                self.state = FetchState::Push;
+                self.sprite_data[1] = bus.vram_read(self.data_address);
+                self.sprite_state = FetchState::Push;
             }
 
             _ => { 
@@ -219,24 +238,18 @@ impl Fetcher {
         }
     }
 
-    // This function returns the color value for the background
-    // and the index, to handle transparency
-    pub fn push_bgw(&mut self, bus: &mut Interconnect) -> Option<[(u32, u8); 8]> {
+    // This function returns the color index of the background pixels,
+    // the palette is only applied when the pixels are shifted out
+    pub fn push_bgw(&mut self) -> Option<[u8; 8]> {
         if let FetchState::Push = self.state {
-            let mut pixels = [(0, 0); 8];
+            let mut pixels = [0; 8];
             for i in 0..8 {
                 let bit: u8 = 7 - i;
                 let low = ((self.bgw_fetched_data[1] & (1 << bit)) != 0) as u8;
 
                 let high = ((self.bgw_fetched_data[2] & (1 << bit)) != 0) as u8;
 
-                let index = if lcdc_bgw_enable(bus) {
-                    high << 1 | low
-                } else { 0 };
-
-                let color = bus.lcd_bg_colors()[index as usize];
-
-                pixels[i as usize] = (color, index);
+                pixels[i as usize] = high << 1 | low;
             }
             self.pushed_x += 8;
             self.state = FetchState::TileID(Step::First);
@@ -246,10 +259,10 @@ impl Fetcher {
         }
     }
 
-    // This function returns the pixel value, with the palette number
+    // This function returns the color index, with the palette number
     // and the OBJ-to-BG priority flag
-    pub fn push_obj(&mut self, bus: &mut Interconnect) -> Option<Vec<(u32, u8, bool)>> {
-        if let FetchState::Push = self.state {
+    pub fn push_obj(&mut self) -> Option<Vec<(u8, bool, bool)>> {
+        if let FetchState::Push = self.sprite_state {
             let mut pixels = Vec::with_capacity(8);
             let sprite = self.current_sprite.unwrap();
 
@@ -263,28 +276,100 @@ impl Fetcher {
                 let high = (self.sprite_data[1] & (1 << bit) != 0) as u8;
 
                 let bg_priority = sprite.bg_over_obj();
-                let index = (high << 1 | low) as usize;
-                let color = if index == 0 {
-                    u32::MAX // This will never be read, since index = 0 means transparent
-                } else if sprite.palette_nb() {
-                    bus.lcd_sp2_colors()[index]
-                } else {
-                    bus.lcd_sp1_colors()[index]
-                };
+                let index = high << 1 | low;
                 if invisible_pixels > 0 {
                     invisible_pixels -= 1;
                 } else {
-                    pixels.push((color, index as u8, bg_priority));
+                    pixels.push((index, sprite.palette_nb(), bg_priority));
                 }
             };
             self.fetching_sprite = false;
             self.current_sprite = None;
-            self.state = FetchState::TileID(Step::First);
+            self.sprite_state = FetchState::TileID(Step::First);
             while pixels.len() < 8 {
-                pixels.push((u32::MAX, 0, true));
+                pixels.push((0, false, true));
             }
             return Some(pixels)
         }
         None        
     }
-}
\ No newline at end of file
+}
+
+impl FetchState {
+    fn id(&self) -> u8 {
+        match self {
+            FetchState::TileID(Step::First) => 0,
+            FetchState::TileID(Step::Second) => 1,
+            FetchState::TileRowLow(Step::First) => 2,
+            FetchState::TileRowLow(Step::Second) => 3,
+            FetchState::TileRowHigh(Step::First) => 4,
+            FetchState::TileRowHigh(Step::Second) => 5,
+            FetchState::Push => 6,
+        }
+    }
+
+    fn from_id(id: u8) -> Result<FetchState, InvalidState> {
+        Ok(match id {
+            0 => FetchState::TileID(Step::First),
+            1 => FetchState::TileID(Step::Second),
+            2 => FetchState::TileRowLow(Step::First),
+            3 => FetchState::TileRowLow(Step::Second),
+            4 => FetchState::TileRowHigh(Step::First),
+            5 => FetchState::TileRowHigh(Step::Second),
+            6 => FetchState::Push,
+            _ => return Err(InvalidState::new("invalid fetcher state")),
+        })
+    }
+}
+
+impl SaveState for Fetcher {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.u8(self.state.id());
+        state.u8(self.sprite_state.id());
+        state.bool(self.mode == FetchMode::Window);
+
+        state.u8(self.lx);
+        state.u16(self.tile_address);
+        state.bytes(&self.bgw_fetched_data);
+        state.u16(self.data_address);
+
+        state.u8(self.window_line);
+
+        state.bool(self.fetching_sprite);
+        state.u8(self.sprite_wait);
+        state.u8(self.startup_delay);
+        state.bool(self.current_sprite.is_some());
+        self.current_sprite.unwrap_or_default().save_state(state);
+        state.bytes(&self.sprite_data);
+
+        state.u8(self.pushed_x);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), InvalidState> {
+        self.state = FetchState::from_id(state.u8()?)?;
+        self.sprite_state = FetchState::from_id(state.u8()?)?;
+        self.mode = if state.bool()? { FetchMode::Window } else { FetchMode::Background };
+
+        self.lx = state.u8()?;
+        self.tile_address = state.u16()?;
+        state.bytes(&mut self.bgw_fetched_data)?;
+        self.data_address = state.u16()?;
+
+        self.window_line = state.u8()?;
+
+        self.fetching_sprite = state.bool()?;
+        self.sprite_wait = state.u8()?;
+        self.startup_delay = state.u8()?;
+        let has_sprite = state.bool()?;
+        let mut sprite = OAMEntry::new();
+        sprite.load_state(state)?;
+        self.current_sprite = has_sprite.then_some(sprite);
+        if self.fetching_sprite && !has_sprite {
+            return Err(InvalidState::new("invalid fetcher state"))
+        }
+        state.bytes(&mut self.sprite_data)?;
+
+        self.pushed_x = state.u8()?;
+        Ok(())
+    }
+}
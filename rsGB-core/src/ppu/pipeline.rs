@@ -1,51 +1,48 @@
-use crate::{interconnect::Interconnect, ppu::{XRES, utils::{lcd_read_ly, lcd_read_scroll_x, lcd_read_win_x, lcd_read_win_y, lcdc_obj_enable, lcdc_obj_height, lcdc_win_enable}}};
+use crate::{interconnect::{Interconnect, OAMEntry}, ppu::{XRES, utils::{lcd_read_ly, lcd_read_scroll_x, lcd_read_win_x, lcd_read_win_y, lcdc_bgw_enable, lcdc_obj_enable, lcdc_obj_height, lcdc_win_enable}}};
 
 use super::PPU;
 
+const SPRITE_MAX_WAIT: u8 = 5;
+
 impl PPU {
     pub(super) fn process_fifo(&mut self, bus: &mut Interconnect, framebuffer: &mut [u32], render: bool) {
         self.check_window_trigger(bus);
 
-        self.check_sprite_displayed(bus);
-
         if self.fetcher.is_fetching_sprite() {
-            self.fetcher.fetch(bus);
-            if let Some(data) = self.fetcher.push_obj(bus) {
-                while self.obj_fifo.len() < 8 {
-                    self.obj_fifo.push_back((u32::MAX, 0, true)).unwrap();
-                }
-                for i in 0..8 {
-                    let (new_pixel, new_index, new_bg_priority) = data[i];
-
-                    // Only merge non-transparent pixels
-                    if new_index != 0 {
-                        let (_old_pixel, old_index, _old_bg_priority) = self.obj_fifo[i];
-
-                        // X-Coordinate Priority:
-                        // If the FIFO slot is empty (old_index 0), this new sprite wins.
-                        // If the slot is *already* full, the old sprite (which had a
-                        // lower X-coordinate) wins, and this new pixel is discarded.
-                        if old_index == 0 {
-                            self.obj_fifo[i] = (new_pixel, new_index, new_bg_priority);
-                        }
-                    }
-                }
-            } else {
-                return;
+            // The pixel output is paused while a sprite is fetched,
+            // and the background fetcher completes its current tile
+            if !self.fetcher.bgw_ready() {
+                self.fetcher.fetch_bgw(bus);
             }
-        } else if self.bgw_fifo.len() == 0 {
-            self.fetcher.fetch(bus);
-            if let Some(pixels) = self.fetcher.push_bgw(bus) {
-                for pixel in pixels {
-                    self.bgw_fifo.push_back(pixel).unwrap();
-                }
+            self.fetch_sprite(bus);
+            return;
+        }
+
+        // The fetcher runs while the pixels are shifted out, and
+        // waits for the FIFO to be empty before pushing the next tile
+        if self.bgw_fifo.len() == 0 && let Some(pixels) = self.fetcher.push_bgw() {
+            for pixel in pixels {
+                self.bgw_fifo.push_back(pixel).unwrap();
             }
         }
+        self.fetcher.fetch_bgw(bus);
+
+        // Sprites are only checked when a pixel is about to be shifted out
+        if self.bgw_fifo.len() != 0 && self.check_sprite_displayed(bus) {
+            self.fetch_sprite(bus);
+            return;
+        }
 
         if !(self.bgw_fifo.len() == 0) {
-            let (bgw_pixel, bgw_index) = self.bgw_fifo.pop_front().unwrap();
+            let bgw_index = self.bgw_fifo.pop_front().unwrap();
 
-            if !self.fetcher.is_window_mode() {
+            if self.fetcher.is_window_mode() {
+                // With WX < 7, the first pixels of the window are offscreen
+                if self.window_skip > 0 {
+                    self.window_skip -= 1;
+                    return;
+                }
+            } else {
                 let scx = lcd_read_scroll_x(bus);
                 
                 // Discard the first (SCX % 8) pixels
@@ -55,20 +52,27 @@ impl PPU {
                 }
             }
 
-            let (obj_pixel, obj_index, bg_priority) = self.obj_fifo.pop_front().unwrap_or((u32::MAX, 0, true));
+            // The palettes and LCDC are only read when the pixel is shifted out,
+            // so mid-scanline writes take effect at the next pixel
+            let bgw_index = if lcdc_bgw_enable(bus) { bgw_index } else { 0 };
+
+            let (obj_index, obj_palette, bg_priority) = match self.obj_fifo.pop_front() {
+                Some(obj) if lcdc_obj_enable(bus) => obj,
+                _ => (0, false, true),
+            };
 
-            let pixel = if obj_index == 0 {
-                bgw_pixel
-            } else if bg_priority && bgw_index != 0 {
-                bgw_pixel
+            let pixel = if obj_index == 0 || (bg_priority && bgw_index != 0) {
+                bus.lcd_bg_colors()[bgw_index as usize]
+            } else if obj_palette {
+                bus.lcd_sp2_colors()[obj_index as usize]
             } else {
-                obj_pixel
+                bus.lcd_sp1_colors()[obj_index as usize]
             };
 
             let x = self.pushed_x as usize + lcd_read_ly(bus) as usize * XRES;
             
             if render {
-                framebuffer[x] = pixel;
+                bus.lcd_color_mode().write_pixel(framebuffer, x, pixel);
             }
             self.pushed_x += 1;
         }
@@ -82,19 +86,43 @@ impl PPU {
         self.obj_fifo.clear();
         self.fetched_sprites.fill(false);
         self.fetcher.reset();
+
+        self.window_drawn = false;
+        self.xfer_end = 0;
+    }
+
+    /// The window can only be displayed once LY has been equal to WY
+    /// during the frame, even if WY is changed afterwards
+    pub fn check_window_y(&mut self, bus: &mut Interconnect) {
+        if lcd_read_ly(bus) == lcd_read_win_y(bus) {
+            self.window_y_triggered = true;
+        }
     }
 
     fn check_window_trigger(&mut self, bus: &mut Interconnect) {
-        if self.fetcher.is_window_mode() || !lcdc_win_enable(bus) {
+        if !lcdc_win_enable(bus) {
+            if self.fetcher.is_window_mode() {
+                // Disabling the window mid-scanline resumes the background,
+                // and the window can be triggered again on the same line
+                let lx = self.pushed_x + self.bgw_fifo.len() as u8;
+                self.fetcher.switch_to_background(lx);
+            }
+            return;
+        }
+
+        if self.fetcher.is_window_mode() || !self.window_y_triggered {
             return;
         }
 
-        let ly = lcd_read_ly(bus);
-        let wy = lcd_read_win_y(bus);
         let wx = lcd_read_win_x(bus);
-       
-        // Check if we've reached the window Y position
-        if ly < wy {
+
+        // With WX=166, the window is triggered on the last pixel
+        // and covers the whole next line
+        if self.window_next_line {
+            if self.pushed_x == 0 {
+                self.window_next_line = false;
+                self.switch_to_window_mode(0);
+            }
             return;
         }
 
@@ -103,31 +131,46 @@ impl PPU {
         if wx < 7 {
             // Window starts before or at screen edge
             if self.pushed_x == 0 {
-                self.switch_to_window_mode();
+                let skip = if wx == 0 {
+                    // With WX=0, the window is shifted by the fine scroll
+                    7 - (lcd_read_scroll_x(bus) % 8)
+                } else {
+                    7 - wx
+                };
+                self.switch_to_window_mode(skip);
             }
-        } else if self.pushed_x + 7 >= wx {
+        } else if self.pushed_x + 7 == wx {
             // Normal case: window starts mid-screen
-            self.switch_to_window_mode();
+            if wx == 166 {
+                self.window_next_line = true;
+            }
+            self.switch_to_window_mode(0);
         }
     }
 
-    fn switch_to_window_mode(&mut self) {
+    fn switch_to_window_mode(&mut self, skip: u8) {
         // Clear the FIFO when switching to window
         self.bgw_fifo.clear();
+        self.window_skip = skip;
+        self.window_drawn = true;
         
         // Switch fetcher to window mode
-        self.fetcher.switch_to_window();
+        self.fetcher.switch_to_window(self.window_line);
     }
 
     pub fn scanline_complete(&mut self) {
         // Increment window line counter if window was rendered this scanline
-        self.fetcher.increment_window_line();
+        if self.window_drawn {
+            self.window_line += 1;
+        }
         self.visible_sprites.clear();
     }
 
     pub fn frame_complete(&mut self) {
         // Reset window line counter at the start of each frame
-        self.fetcher.reset_window_line();
+        self.window_line = 0;
+        self.window_y_triggered = false;
+        self.window_next_line = false;
     }
 
     pub fn oam_fetch(&mut self, bus: &mut Interconnect) {
@@ -138,12 +181,10 @@ impl PPU {
 
             let index = ((self.line_ticks - 1) / 2) as u8;
             // println!("Current OAM index: {index}");
+            // Sprites with X=0 are offscreen, but they are still
+            // fetched and count in the limit of 10 per line
             let obj = bus.oam_sprite(index);
 
-            if obj.x == 0 {
-                return;
-            }
-
             if obj.y <= ly + 16 && obj.y + sprite_height > ly + 16 {
                 // This sprite is on the current line
                 self.visible_sprites.push(obj);
@@ -151,17 +192,60 @@ impl PPU {
         }
     }
 
-    fn check_sprite_displayed(&mut self, bus: &mut Interconnect) {
-        if lcdc_obj_enable(bus) && !self.fetcher.is_fetching_sprite() {
-            for (index, sprite) in self.visible_sprites.iter().enumerate() {
-                let sprite_x = sprite.x.saturating_sub(8);
+    fn check_sprite_displayed(&mut self, bus: &mut Interconnect) -> bool {
+        if !lcdc_obj_enable(bus) {
+            return false
+        }
+
+        for (index, sprite) in self.visible_sprites.iter().enumerate() {
+            let sprite_x = sprite.x.saturating_sub(8);
 
-                if !self.fetched_sprites[index] && self.pushed_x >= sprite_x && self.pushed_x < sprite_x + 8 {
-                    self.fetcher.trigger_sprite_fetching(*sprite);
-                    self.fetched_sprites[index] = true;
-                    break;
+            if !self.fetched_sprites[index] && self.pushed_x >= sprite_x && self.pushed_x < sprite_x + 8 {
+                let wait = self.sprite_wait(index, lcd_read_scroll_x(bus));
+                self.fetcher.trigger_sprite_fetching(*sprite, wait);
+                self.fetched_sprites[index] = true;
+                return true
+            }
+        }
+        false
+    }
+
+    fn fetch_sprite(&mut self, bus: &mut Interconnect) {
+        self.fetcher.fetch_sprite(bus);
+
+        if let Some(data) = self.fetcher.push_obj() {
+            while self.obj_fifo.len() < 8 {
+                self.obj_fifo.push_back((0, false, true)).unwrap();
+            }
+            for i in 0..8 {
+                let (new_index, new_palette, new_bg_priority) = data[i];
+
+                // Only merge non-transparent pixels
+                if new_index != 0 {
+                    let (old_index, _old_palette, _old_bg_priority) = self.obj_fifo[i];
+
+                    // X-Coordinate Priority:
+                    // If the FIFO slot is empty (old_index 0), this new sprite wins.
+                    // If the slot is *already* full, the old sprite (which had a
+                    // lower X-coordinate) wins, and this new pixel is discarded.
+                    if old_index == 0 {
+                        self.obj_fifo[i] = (new_index, new_palette, new_bg_priority);
+                    }
                 }
             }
         }
     }
+
+    /// Before a sprite is fetched, the first sprite on a background tile waits
+    /// up to 5 dots for the fetcher, depending on its position in the tile
+    pub(super) fn sprite_wait(&self, index: usize, scx: u8) -> u8 {
+        let pixel = |sprite: &OAMEntry| sprite.x as u16 + (scx % 8) as u16;
+        let tile = pixel(&self.visible_sprites[index]) / 8;
+
+        // The sprites are sorted, the previous ones were fetched first
+        if self.visible_sprites[..index].iter().any(|sprite| pixel(sprite) / 8 == tile) {
+            return 0
+        }
+        SPRITE_MAX_WAIT.saturating_sub((pixel(&self.visible_sprites[index]) % 8) as u8)
+    }
 }
\ No newline at end of file
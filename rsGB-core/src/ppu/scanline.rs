@@ -0,0 +1,144 @@
+use crate::{interconnect::Interconnect, ppu::{XRES, utils::{bgw_tile_address, lcd_read_ly, lcd_read_scroll_x, lcd_read_scroll_y, lcd_read_win_x, lcdc_bg_map_area, lcdc_bgw_enable, lcdc_obj_enable, lcdc_obj_height, lcdc_win_enable, lcdc_win_map_area}}};
+
+use super::{PPU, fetcher::STARTUP_DELAY};
+
+// A tile is fetched in 6 dots, the background and the sprites alike
+const TILE_FETCH_LENGTH: u32 = 6;
+
+// Mode 3 lasts at least as long as the FIFO takes to shift out a line,
+// after the startup delay and the discarded first fetch
+const XFER_BASE_LENGTH: u32 = STARTUP_DELAY as u32 + TILE_FETCH_LENGTH + XRES as u32;
+
+// Fetching the first window tile restarts the background fetcher
+const WINDOW_PENALTY: u32 = TILE_FETCH_LENGTH;
+
+impl PPU {
+    /// The scanline renderer doesn't fetch anything during mode 3: its
+    /// length is computed when it starts, and the whole line is drawn
+    /// when it ends, using the register values at that time
+    pub(super) fn scanline_xfer(&mut self, bus: &mut Interconnect, framebuffer: &mut [u32], render: bool) {
+        if self.xfer_end == 0 {
+            self.xfer_end = self.line_ticks + self.compute_xfer_length(bus) - 1;
+        }
+
+        if self.line_ticks >= self.xfer_end {
+            let window = self.window_on_line(bus);
+            if window {
+                self.window_drawn = true;
+            }
+
+            if render {
+                self.render_scanline(bus, framebuffer, window);
+            }
+            self.pushed_x = XRES as u8;
+        }
+    }
+
+    fn window_on_line(&self, bus: &mut Interconnect) -> bool {
+        lcdc_win_enable(bus) && self.window_y_triggered && lcd_read_win_x(bus) <= 166
+    }
+
+    fn compute_xfer_length(&self, bus: &mut Interconnect) -> u32 {
+        let scx = lcd_read_scroll_x(bus);
+        let mut length = XFER_BASE_LENGTH + (scx % 8) as u32;
+
+        if self.window_on_line(bus) {
+            length += WINDOW_PENALTY;
+        }
+
+        if lcdc_obj_enable(bus) {
+            // The sprites wait for the fetcher like in the FIFO
+            for (index, sprite) in self.visible_sprites.iter().enumerate() {
+                if sprite.x < 168 {
+                    length += self.sprite_wait(index, scx) as u32 + TILE_FETCH_LENGTH;
+                }
+            }
+        }
+        length
+    }
+
+    fn render_scanline(&mut self, bus: &mut Interconnect, framebuffer: &mut [u32], window: bool) {
+        let ly = lcd_read_ly(bus);
+        let (scx, scy, wx) = (lcd_read_scroll_x(bus), lcd_read_scroll_y(bus), lcd_read_win_x(bus));
+
+        let mut bgw_indices = [0; XRES];
+
+        if lcdc_bgw_enable(bus) {
+            for (x, index) in bgw_indices.iter_mut().enumerate() {
+                *index = if window && x + 7 >= wx as usize {
+                    let map_area = lcdc_win_map_area(bus);
+                    bgw_pixel(bus, map_area, (x + 7 - wx as usize) as u8, self.window_line)
+                } else {
+                    let map_area = lcdc_bg_map_area(bus);
+                    bgw_pixel(bus, map_area, (x as u8).wrapping_add(scx), ly.wrapping_add(scy))
+                };
+            }
+        }
+
+        // The color index, palette number and OBJ-to-BG priority of each sprite pixel
+        let mut obj_pixels = [(0, false, true); XRES];
+
+        if lcdc_obj_enable(bus) {
+            let sprite_height = lcdc_obj_height(bus);
+
+            // The sprites are sorted by X coordinate, so the first
+            // non-transparent pixel written at a position has priority
+            for sprite in &self.visible_sprites {
+                let mut tile_y = ly + 16 - sprite.y;
+                if sprite.y_flip() {
+                    tile_y = sprite_height - 1 - tile_y;
+                }
+
+                let tile_index = if sprite_height == 16 {
+                    sprite.tile as u16 & !0b1
+                } else {
+                    sprite.tile as u16
+                };
+
+                let address = 0x8000 + (tile_index << 4) + ((tile_y as u16) << 1);
+                let (low, high) = (bus.vram_read(address), bus.vram_read(address + 1));
+
+                for i in 0..8 {
+                    let x = sprite.x as usize + i;
+                    if !(8..XRES + 8).contains(&x) || obj_pixels[x - 8].0 != 0 {
+                        continue;
+                    }
+
+                    let bit = if sprite.x_flip() { i } else { 7 - i };
+                    let index = ((high >> bit) & 1) << 1 | ((low >> bit) & 1);
+
+                    obj_pixels[x - 8] = (index, sprite.palette_nb(), sprite.bg_over_obj());
+                }
+            }
+        }
+
+        let line = ly as usize * XRES;
+        let color_mode = bus.lcd_color_mode();
+        for x in 0..XRES {
+            let bgw_index = bgw_indices[x];
+            let (obj_index, obj_palette, bg_priority) = obj_pixels[x];
+
+            let pixel = if obj_index == 0 || (bg_priority && bgw_index != 0) {
+                bus.lcd_bg_colors()[bgw_index as usize]
+            } else if obj_palette {
+                bus.lcd_sp2_colors()[obj_index as usize]
+            } else {
+                bus.lcd_sp1_colors()[obj_index as usize]
+            };
+            color_mode.write_pixel(framebuffer, line + x, pixel);
+        }
+    }
+}
+
+// Returns the color index of a background or window pixel,
+// from its coordinates in the tile map
+fn bgw_pixel(bus: &mut Interconnect, map_area: u16, x: u8, y: u8) -> u8 {
+    let tile_id = bus.vram_read(map_area + ((y as u16 / 8) << 5) + (x as u16 / 8));
+    let address = bgw_tile_address(bus, tile_id, y % 8);
+
+    let bit = 7 - (x % 8);
+    let low = (bus.vram_read(address) >> bit) & 1;
+    let high = (bus.vram_read(address + 1) >> bit) & 1;
+
+    high << 1 | low
+}
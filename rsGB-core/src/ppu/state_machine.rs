@@ -4,40 +4,109 @@ use crate::{
 
 use super::{
     LINES_PER_FRAME, PPU, TICKS_PER_LINE, XRES, YRES,
-    utils::{LCDMode, StatusSrc, status_stat_int, status_lyc_set, change_lcd_mode, status_mode_set}
+    utils::{LCDMode, StatusSrc, lcdc_lcd_enable, status_lyc, status_lyc_set, status_mode, change_lcd_mode, lcd_read_ly_compare}
 };
 
+// On the first line after the LCD is turned on, the PPU stays
+// in mode 0 instead of scanning the OAM before drawing
+const LCD_ON_XFER_START: u32 = 80;
+
+// LY is incremented, and the OAM locked, 4 dots before the next line starts.
+// The LY=LYC flag stays cleared until then
+const LY_INCREMENT: u32 = TICKS_PER_LINE - 4;
+
+// The VRAM is locked 4 dots before the STAT mode switches to 3
+const VRAM_LOCK_START: u32 = 76;
+
+// The mode 0 interrupt is requested 3 dots after STAT reads mode 0
+const HBLANK_INTERRUPT_DELAY: u32 = 3;
+
+// On line 153, LY already reads 0 after a few dots
+const LY_153_RESET: u32 = 4;
+
+// On DMG, writing to STAT briefly behaves as if every source
+// but the OAM one was enabled, which can trigger a spurious interrupt
+const STAT_WRITE_SOURCES: u8 = StatusSrc::HBlank as u8 | StatusSrc::VBlank as u8 | StatusSrc::LYC as u8;
+
 impl PPU {
     pub fn hblank(&mut self, bus: &mut Interconnect) {
-        if self.line_ticks >= TICKS_PER_LINE {
-            let ly = increment_ly(bus);
+        if self.lcd_on_line && self.line_ticks >= LCD_ON_XFER_START {
+            self.lcd_on_line = false;
+            self.check_window_y(bus);
+            change_lcd_mode(bus, LCDMode::XFer);
+            self.pipeline_reset();
+        } else if self.line_ticks == LY_INCREMENT {
+            if increment_ly(bus) < YRES as u8 {
+                lock_oam_early(bus);
+            }
+        } else if self.line_ticks >= TICKS_PER_LINE {
+            bus.set_oam_early_lock(false);
             self.scanline_complete();
 
-            if ly >= YRES as u8 {
+            if lcd_read_ly(bus) >= YRES as u8 {
                 change_lcd_mode(bus, LCDMode::VBlank);
 
                 bus.request_interrupt(InterruptType::VBlank);
 
-                if status_stat_int(bus, StatusSrc::VBlank) {
-                    bus.request_interrupt(InterruptType::LcdStat);
-                }
                 self.current_frame += 1;
                 self.new_frame = true;
+                self.skip_frame = false;
             } else {
-                status_mode_set(bus, LCDMode::OAM);
+                start_oam_scan(bus);
             }
             self.line_ticks = 0;
         }
     }
 
+    /// Returns the number of upcoming dots during which the PPU only
+    /// counts, without changing its state or the STAT interrupt line
+    pub fn idle_dots(&mut self, bus: &mut Interconnect) -> u32 {
+        if self.lcd_written {
+            return 0
+        }
+
+        if !lcdc_lcd_enable(bus) {
+            return if self.lcd_enabled {
+                0
+            } else {
+                (TICKS_PER_LINE * LINES_PER_FRAME as u32).saturating_sub(self.line_ticks + 1)
+            }
+        } else if !self.lcd_enabled {
+            return 0
+        }
+
+        // The dot at which the target is reached isn't idle
+        let target = match status_mode(bus) {
+            LCDMode::HBlank if self.lcd_on_line => LCD_ON_XFER_START,
+            LCDMode::HBlank if self.hblank_interrupt_pending() => self.xfer_end + HBLANK_INTERRUPT_DELAY,
+            LCDMode::VBlank if lcd_read_ly(bus) == LINES_PER_FRAME - 1 && self.line_ticks < LY_153_RESET => LY_153_RESET,
+            LCDMode::HBlank | LCDMode::VBlank if self.line_ticks < LY_INCREMENT => LY_INCREMENT,
+            LCDMode::HBlank | LCDMode::VBlank => TICKS_PER_LINE,
+            LCDMode::XFer if self.scanline_renderer && self.xfer_end != 0 => self.xfer_end,
+            _ => return 0,
+        };
+        target.saturating_sub(self.line_ticks + 1)
+    }
+
     pub fn vblank(&mut self, bus: &mut Interconnect) {
-        if self.line_ticks >= TICKS_PER_LINE {
-            let ly = increment_ly(bus);
+        let ly = lcd_read_ly(bus);
 
-            if ly >= LINES_PER_FRAME {
+        if ly == LINES_PER_FRAME - 1 && self.line_ticks == LY_153_RESET {
+            lcd_write_ly(bus, 0);
+        }
+
+        if self.line_ticks == LY_INCREMENT {
+            // LY was already reset to 0 during line 153
+            if ly == 0 {
+                lock_oam_early(bus);
+            } else {
+                increment_ly(bus);
+            }
+        } else if self.line_ticks >= TICKS_PER_LINE {
+            if ly == 0 {
+                bus.set_oam_early_lock(false);
                 self.frame_complete();
-                change_lcd_mode(bus, LCDMode::OAM);
-                bus.write(0xFF44, 0);
+                start_oam_scan(bus);
             }
 
             self.line_ticks = 0;
@@ -46,41 +115,146 @@ impl PPU {
 
     pub fn oam(&mut self, bus: &mut Interconnect) {
         if self.line_ticks >= 80 {
+            bus.set_oam_scan_row(None);
+            bus.set_vram_early_lock(false);
             change_lcd_mode(bus, LCDMode::XFer);
             self.pipeline_reset();
             self.visible_sprites.sort_by_key(|e| e.x);
         } else {
+            // The PPU reads one OAM row (two entries) per M-cycle
+            bus.set_oam_scan_row(oam_scan_row(self.line_ticks));
+            if self.line_ticks >= VRAM_LOCK_START {
+                bus.set_vram_early_lock(true);
+            }
+            self.check_window_y(bus);
             self.oam_fetch(bus);
         }
     }
 
+    pub fn lcd_off(&mut self, bus: &mut Interconnect, framebuffer: &mut [u32]) {
+        self.lcd_enabled = false;
+        self.line_ticks = 0;
+
+        lcd_write_ly(bus, 0);
+        change_lcd_mode(bus, LCDMode::HBlank);
+        bus.set_oam_scan_row(None);
+        bus.set_oam_early_lock(false);
+        bus.set_vram_early_lock(false);
+
+        self.pipeline_reset();
+        self.scanline_complete();
+        self.frame_complete();
+        // The STAT line is kept, along with the LY=LYC flag, until the LCD is turned on
+
+        self.blank_frame(bus, framebuffer);
+    }
+
+    pub fn lcd_on(&mut self, bus: &mut Interconnect) {
+        self.lcd_enabled = true;
+        self.line_ticks = 0;
+        self.lcd_on_line = true;
+        self.skip_frame = true;
+
+        compare_ly(bus);
+    }
+
+    /// The STAT interrupt is requested on the rising edge of the OR
+    /// of every enabled source, so a source becoming active while
+    /// another one is already active doesn't trigger a new interrupt
+    pub fn update_stat_line(&mut self, bus: &mut Interconnect) {
+        if self.line_ticks < LY_INCREMENT {
+            compare_ly(bus);
+        }
+
+        let mut sources = bus.read(0xFF41);
+        if bus.lcd_stat_written() {
+            sources |= STAT_WRITE_SOURCES;
+        }
+
+        let active = |src: StatusSrc| sources & (src as u8) != 0;
+
+        let line = match status_mode(bus) {
+            LCDMode::HBlank => active(StatusSrc::HBlank) && !self.hblank_interrupt_pending(),
+            // The OAM source is also checked when entering VBlank
+            LCDMode::VBlank => active(StatusSrc::VBlank) || (
+                active(StatusSrc::OAM) && lcd_read_ly(bus) == YRES as u8 && self.line_ticks == 0
+            ),
+            LCDMode::OAM => active(StatusSrc::OAM),
+            LCDMode::XFer => false,
+        } || (active(StatusSrc::LYC) && status_lyc(bus));
+
+        if line && !self.stat_line {
+            bus.request_interrupt(InterruptType::LcdStat);
+        }
+        self.stat_line = line;
+    }
+
+    fn hblank_interrupt_pending(&self) -> bool {
+        self.pushed_x >= XRES as u8 && self.line_ticks < self.xfer_end + HBLANK_INTERRUPT_DELAY
+    }
+
+    /// While the LCD is off the PPU doesn't do anything, but the frontend
+    /// still needs to be signaled at the usual frame rate
+    pub fn lcd_off_tick(&mut self, bus: &mut Interconnect, framebuffer: &mut [u32], render: bool) -> bool {
+        self.line_ticks += 1;
+
+        if self.line_ticks >= TICKS_PER_LINE * LINES_PER_FRAME as u32 {
+            self.line_ticks = 0;
+            if render {
+                self.blank_frame(bus, framebuffer);
+            }
+            return true
+        }
+        false
+    }
+
+    fn blank_frame(&mut self, bus: &mut Interconnect, framebuffer: &mut [u32]) {
+        let (color_mode, color) = (bus.lcd_color_mode(), bus.lcd_blank_color());
+        for index in 0..XRES * YRES {
+            color_mode.write_pixel(framebuffer, index, color);
+        }
+    }
+
     pub fn xfer(&mut self, bus: &mut Interconnect, framebuffer: &mut [u32], render: bool) {
-        self.process_fifo(bus, framebuffer, render);
+        if self.scanline_renderer {
+            self.scanline_xfer(bus, framebuffer, render);
+        } else {
+            self.process_fifo(bus, framebuffer, render);
+        }
 
         if self.pushed_x >= XRES as u8 {
+            self.xfer_end = self.line_ticks;
             change_lcd_mode(bus, LCDMode::HBlank);
-
-            if status_stat_int(bus, StatusSrc::HBlank) {
-                bus.request_interrupt(InterruptType::LcdStat);
-            }
         }
     }
 }
 
+// The OAM scan starts along with the early lock, one row ahead of the mode
+fn oam_scan_row(line_ticks: u32) -> Option<u8> {
+    let row = line_ticks / 4 + 1;
+    (row < 20).then_some(row as u8)
+}
+
+fn lock_oam_early(bus: &mut Interconnect) {
+    bus.set_oam_early_lock(true);
+    bus.set_oam_scan_row(Some(0));
+}
+
+fn start_oam_scan(bus: &mut Interconnect) {
+    change_lcd_mode(bus, LCDMode::OAM);
+    bus.set_oam_scan_row(oam_scan_row(0));
+}
+
 fn increment_ly(bus: &mut Interconnect) -> u8 {
     let mut ly = lcd_read_ly(bus);
 
     ly = ly.wrapping_add(1);
     lcd_write_ly(bus, ly);
-
-    if ly == bus.read(0xFF45) { // If LY == LYC
-        status_lyc_set(bus, 1);
-
-        if status_stat_int(bus, StatusSrc::LYC) {
-            bus.request_interrupt(crate::interconnect::InterruptType::LcdStat);
-        }
-    } else {
-        status_lyc_set(bus, 0);
-    }
+    status_lyc_set(bus, false);
     ly
+}
+
+fn compare_ly(bus: &mut Interconnect) {
+    let equal = lcd_read_ly(bus) == lcd_read_ly_compare(bus);
+    status_lyc_set(bus, equal);
 }
\ No newline at end of file
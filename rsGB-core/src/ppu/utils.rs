@@ -7,7 +7,6 @@ pub enum LCDMode {
     XFer = 3,
 }
 
-#[allow(dead_code)]
 pub enum StatusSrc {
     HBlank = (1 << 3),
     VBlank = (1 << 4),
@@ -17,7 +16,9 @@ pub enum StatusSrc {
 
 pub fn lcd_read_ly(bus: &mut Interconnect) -> u8 { bus.read(0xFF44) }
 
-pub fn lcd_write_ly(bus: &mut Interconnect, value: u8) { bus.write(0xFF44, value) }
+pub fn lcd_write_ly(bus: &mut Interconnect, value: u8) { bus.lcd_set_ly(value) }
+
+pub fn lcd_read_ly_compare(bus: &mut Interconnect) -> u8 { bus.read(0xFF45) }
 
 pub fn lcd_read_scroll_x(bus: &mut Interconnect) -> u8 { bus.read(0xFF43) }
 
@@ -27,12 +28,7 @@ pub fn lcd_read_win_y(bus: &mut Interconnect) -> u8 { bus.read(0xFF4A) }
 
 pub fn lcd_read_win_x(bus: &mut Interconnect) -> u8 { bus.read(0xFF4B) }
 
-pub fn change_lcd_mode(bus: &mut Interconnect, mode: LCDMode) {
-    let mut status = bus.read(0xFF41);
-    status &= !0b11;
-    status |= mode as u8;
-    bus.write(0xFF41, status);
-}
+pub fn change_lcd_mode(bus: &mut Interconnect, mode: LCDMode) { bus.lcd_set_mode(mode as u8) }
 
 pub fn lcdc_bgw_enable(bus: &mut Interconnect) -> bool { (bus.read(0xFF40) & 1) != 0 }
 
@@ -63,6 +59,17 @@ pub fn lcdc_bgw_data_area(bus: &mut Interconnect) -> u16 {
     }
 }
 
+/// Returns the address of a row of a background or window tile
+pub fn bgw_tile_address(bus: &mut Interconnect, tile_id: u8, tile_row: u8) -> u16 {
+    if lcdc_bgw_data_area(bus) == 0x8000 {
+        // Unsigned: 0x8000-0x8FFF, tile_id as u8
+        0x8000 + ((tile_id as u16) << 4) + ((tile_row as u16) << 1)
+    } else {
+        // Signed: 0x9000 base, tile_id as i8
+        0x9000_u16.wrapping_add_signed((tile_id as i8 as i16) << 4) + ((tile_row as u16) << 1)
+    }
+}
+
 pub fn lcdc_win_enable(bus: &mut Interconnect) -> bool { (bus.read(0xFF40) & (1 << 5)) != 0 }
 
 pub fn lcdc_win_map_area(bus: &mut Interconnect) -> u16 {
@@ -73,7 +80,7 @@ pub fn lcdc_win_map_area(bus: &mut Interconnect) -> u16 {
     }
 }
 
-pub fn _lcdc_lcd_enable(bus: &mut Interconnect) -> bool { (bus.read(0xFF40) & (1 << 7)) != 0 }
+pub fn lcdc_lcd_enable(bus: &mut Interconnect) -> bool { (bus.read(0xFF40) & (1 << 7)) != 0 }
 
 pub fn status_mode(bus: &mut Interconnect) -> LCDMode {
     match bus.read(0xFF41) & 0b11 {
@@ -84,22 +91,6 @@ pub fn status_mode(bus: &mut Interconnect) -> LCDMode {
     }
 }
 
-pub fn status_mode_set(bus: &mut Interconnect, mode: LCDMode) {
-    let mut status = bus.read(0xFF41) & !0b11;
-    match mode {
-        LCDMode::HBlank => status |= 0b00,
-        LCDMode::VBlank => status |= 0b01,
-        LCDMode::OAM => status |= 0b10,
-        LCDMode::XFer => status |= 0b11, 
-    }
-    bus.write(0xFF41, status)
-}
-
-pub fn _status_lyc(bus: &mut Interconnect) -> bool { bus.read(0xFF41) & (1 << 2) != 0 }
-
-pub fn status_lyc_set(bus: &mut Interconnect, value: u8) {
-    let status = bus.read(0xFF41) | (value << 2);
-    bus.write(0xFF41, status) 
-} 
+pub fn status_lyc(bus: &mut Interconnect) -> bool { bus.read(0xFF41) & (1 << 2) != 0 }
 
-pub fn status_stat_int(bus: &mut Interconnect, src: StatusSrc) -> bool { bus.read(0xFF41) & (src as u8) != 0 }
\ No newline at end of file
+pub fn status_lyc_set(bus: &mut Interconnect, value: bool) { bus.lcd_set_lyc_flag(value) }
\ No newline at end of file
@@ -0,0 +1,94 @@
+// The devices that don't need to run every cycle register the timestamp
+// of their next event here. They are only updated when that event is due,
+// or when the CPU accesses them, and catch up on the elapsed cycles.
+
+use crate::state::{InvalidState, SaveState, StateReader, StateWriter};
+
+// Events scheduled at the same time are handled in this order
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EventType {
+    Timer,
+    FrameSequencer,
+    Ppu,
+    AudioOutput,
+    Dma,
+}
+
+const EVENT_COUNT: usize = 5;
+
+#[derive(Debug)]
+pub struct Scheduler {
+    now: u64, // The number of T-cycles since power on
+    events: [u64; EVENT_COUNT],
+    next: u64, // The timestamp of the earliest event
+}
+
+impl Scheduler {
+    pub fn new() -> Scheduler {
+        Scheduler {
+            now: 0,
+            events: [u64::MAX; EVENT_COUNT],
+            next: u64::MAX,
+        }
+    }
+
+    pub fn now(&self) -> u64 {
+        self.now
+    }
+
+    pub fn advance(&mut self, cycles: u64) {
+        self.now += cycles;
+    }
+
+    /// Schedules an event at the given timestamp, replacing
+    /// the previous one of the same type
+    pub fn schedule(&mut self, event: EventType, timestamp: u64) {
+        self.events[event as usize] = timestamp;
+        self.next = *self.events.iter().min().unwrap();
+    }
+
+    pub fn schedule_in(&mut self, event: EventType, cycles: u64) {
+        self.schedule(event, self.now + cycles);
+    }
+
+    pub fn cancel(&mut self, event: EventType) {
+        self.schedule(event, u64::MAX);
+    }
+
+    /// Returns the earliest event that is due with its timestamp, and
+    /// removes it from the schedule. Events are returned in timestamp order
+    pub fn pop_due(&mut self) -> Option<(EventType, u64)> {
+        if self.next > self.now {
+            return None
+        }
+
+        let timestamp = self.next;
+        let event = match self.events.iter().position(|&t| t == timestamp).unwrap() {
+            0 => EventType::Timer,
+            1 => EventType::FrameSequencer,
+            2 => EventType::Ppu,
+            3 => EventType::AudioOutput,
+            _ => EventType::Dma,
+        };
+        self.cancel(event);
+        Some((event, timestamp))
+    }
+}
+
+impl SaveState for Scheduler {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.u64(self.now);
+        for timestamp in self.events {
+            state.u64(timestamp);
+        }
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), InvalidState> {
+        self.now = state.u64()?;
+        for timestamp in &mut self.events {
+            *timestamp = state.u64()?;
+        }
+        self.next = *self.events.iter().min().unwrap();
+        Ok(())
+    }
+}
@@ -4,6 +4,13 @@ use std::path::PathBuf;
 pub struct Settings {
     pub speed: SpeedOption,
     pub save_location: SaveLocation,
+    pub software_breakpoints: bool,
+    pub scanline_renderer: bool,
+    pub audio_filter: AudioFilter,
+    pub muted_channels: [bool; 4],
+    pub solo_channels: [bool; 4],
+    pub palette: DmgPalette,
+    pub frame_blending: FrameBlending,
 }
 
 impl Settings {
@@ -11,6 +18,13 @@ impl Settings {
         Settings {
             speed: SpeedOption::Normal,
             save_location: SaveLocation::GameLoc,
+            software_breakpoints: false,
+            scanline_renderer: false,
+            audio_filter: AudioFilter::DMG,
+            muted_channels: [false; 4],
+            solo_channels: [false; 4],
+            palette: PalettePreset::Grayscale.palette(),
+            frame_blending: FrameBlending::Off,
         }
     }
 
@@ -18,6 +32,13 @@ impl Settings {
         Settings { 
             speed: SpeedOption::Normal, 
             save_location: SaveLocation::SaveFolder(save_folder), 
+            software_breakpoints: false,
+            scanline_renderer: false,
+            audio_filter: AudioFilter::DMG,
+            muted_channels: [false; 4],
+            solo_channels: [false; 4],
+            palette: PalettePreset::Grayscale.palette(),
+            frame_blending: FrameBlending::Off,
         }
     }
 
@@ -29,6 +50,43 @@ impl Settings {
         self.save_location = save_location;
     }
 
+    /// When enabled, `Gameboy::next_frame` returns as soon as
+    /// an `LD B,B` instruction is executed
+    pub fn set_software_breakpoints(&mut self, enabled: bool) {
+        self.software_breakpoints = enabled;
+    }
+
+    /// The scanline renderer draws whole lines instead of emulating the
+    /// pixel FIFO. It is much faster, but mid-scanline effects are lost
+    pub fn set_scanline_renderer(&mut self, enabled: bool) {
+        self.scanline_renderer = enabled;
+    }
+
+    pub fn set_audio_filter(&mut self, audio_filter: AudioFilter) {
+        self.audio_filter = audio_filter;
+    }
+
+    /// The channels are numbered from 0 (CH1) to 3 (CH4)
+    pub fn set_channel_muted(&mut self, channel: usize, muted: bool) {
+        self.muted_channels[channel] = muted;
+    }
+
+    /// When some channels are soloed, the others aren't heard
+    pub fn set_channel_solo(&mut self, channel: usize, solo: bool) {
+        self.solo_channels[channel] = solo;
+    }
+
+    /// The palette can be changed while a game is running
+    pub fn set_palette(&mut self, palette: DmgPalette) {
+        self.palette = palette;
+    }
+
+    /// Smooths the sprites that flicker every other frame
+    /// for transparency, and emulates the slow response of the LCD
+    pub fn set_frame_blending(&mut self, frame_blending: FrameBlending) {
+        self.frame_blending = frame_blending;
+    }
+
     pub fn get_save_location(&self) -> &SaveLocation {
         &self.save_location
     }
@@ -42,6 +100,96 @@ pub enum SpeedOption {
     X4 = 4,
 }
 
+/// The filtering applied to the audio output
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AudioFilter {
+    DMG,
+    CGB, // Also used by the MGB
+    Raw, // Keeps the DC offset of the DACs
+}
+
+/// How each frame is blended with the previous ones
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrameBlending {
+    Off,
+    Mix, // The average of the last 2 frames
+    Ghosting(f32), // The fraction of the previous image that persists, from 0 to 1
+}
+
+/// The colors the 4 shades of the DMG are displayed with, from the
+/// lightest to the darkest, as 0xRRGGBB. The background and window,
+/// and each of the 2 object palettes can have their own colors
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DmgPalette {
+    pub bg: [u32; 4],
+    pub obj0: [u32; 4],
+    pub obj1: [u32; 4],
+}
+
+impl DmgPalette {
+    pub fn new(bg: [u32; 4], obj0: [u32; 4], obj1: [u32; 4]) -> DmgPalette {
+        DmgPalette { bg, obj0, obj1 }
+    }
+
+    /// Uses the same colors for the background and the objects
+    pub fn uniform(colors: [u32; 4]) -> DmgPalette {
+        DmgPalette::new(colors, colors, colors)
+    }
+
+    /// Returns the preset with these colors, if any
+    pub fn preset(&self) -> Option<PalettePreset> {
+        PalettePreset::ALL.into_iter().find(|preset| preset.palette() == *self)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PalettePreset {
+    Grayscale,
+    DmgGreen,
+    Pocket,
+    Light,
+    HighContrast,
+    Colorblind,
+}
+
+impl PalettePreset {
+    pub const ALL: [PalettePreset; 6] = [
+        PalettePreset::Grayscale,
+        PalettePreset::DmgGreen,
+        PalettePreset::Pocket,
+        PalettePreset::Light,
+        PalettePreset::HighContrast,
+        PalettePreset::Colorblind,
+    ];
+
+    pub fn palette(self) -> DmgPalette {
+        match self {
+            PalettePreset::Grayscale => DmgPalette::uniform([0xFFFFFF, 0xAAAAAA, 0x555555, 0x000000]),
+            PalettePreset::DmgGreen => DmgPalette::uniform([0x9BBC0F, 0x8BAC0F, 0x306230, 0x0F380F]),
+            PalettePreset::Pocket => DmgPalette::uniform([0xC4CFA1, 0x8B956D, 0x4D533C, 0x1F1F1F]),
+            PalettePreset::Light => DmgPalette::uniform([0x00B581, 0x009A71, 0x00694A, 0x004F3B]),
+            PalettePreset::HighContrast => DmgPalette::uniform([0xFFFFFF, 0xFFE000, 0x0040FF, 0x000000]),
+            // Blue and orange tones, which stay distinct with all types of color blindness
+            PalettePreset::Colorblind => DmgPalette::new(
+                [0xFFFFFF, 0x56B4E9, 0x0072B2, 0x000000],
+                [0xFFFFFF, 0xE69F00, 0xD55E00, 0x000000],
+                [0xFFFFFF, 0xF0E442, 0x999999, 0x000000],
+            ),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PalettePreset::Grayscale => "Grayscale",
+            PalettePreset::DmgGreen => "DMG Green",
+            PalettePreset::Pocket => "Pocket",
+            PalettePreset::Light => "Light",
+            PalettePreset::HighContrast => "High Contrast",
+            PalettePreset::Colorblind => "Colorblind Friendly",
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum SaveLocation {
     GameLoc,
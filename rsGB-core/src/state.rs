@@ -0,0 +1,125 @@
+use std::{error::Error, fmt};
+
+/// A save state holds the fields of each component one after the other,
+/// in little endian. The components write and read their own fields,
+/// in the same order
+pub(crate) trait SaveState {
+    fn save_state(&self, state: &mut StateWriter);
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), InvalidState>;
+}
+
+pub(crate) struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> StateWriter {
+        StateWriter { data: Vec::new() }
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.data.extend(value.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.data.extend(value.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.data.extend(value.to_le_bytes());
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.data.push(value as u8);
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.data.extend(bytes);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+pub(crate) struct StateReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> StateReader<'a> {
+        StateReader { data, offset: 0 }
+    }
+
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], InvalidState> {
+        let mut bytes = [0; N];
+        self.bytes(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, InvalidState> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, InvalidState> {
+        Ok(u16::from_le_bytes(self.take()?))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, InvalidState> {
+        Ok(u32::from_le_bytes(self.take()?))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, InvalidState> {
+        Ok(u64::from_le_bytes(self.take()?))
+    }
+
+    pub fn bool(&mut self) -> Result<bool, InvalidState> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(InvalidState::new("invalid boolean")),
+        }
+    }
+
+    pub fn bytes(&mut self, output: &mut [u8]) -> Result<(), InvalidState> {
+        let bytes = self.data.get(self.offset..self.offset + output.len())
+            .ok_or_else(|| InvalidState::new("unexpected end of the state"))?;
+        output.copy_from_slice(bytes);
+        self.offset += output.len();
+        Ok(())
+    }
+
+    /// The whole state must have been read
+    pub fn finish(self) -> Result<(), InvalidState> {
+        if self.offset == self.data.len() {
+            Ok(())
+        } else {
+            Err(InvalidState::new("unexpected data at the end of the state"))
+        }
+    }
+}
+
+/// This error is returned when a save state can't be loaded
+#[derive(Debug)]
+pub struct InvalidState {
+    reason: &'static str,
+}
+
+impl InvalidState {
+    pub(crate) fn new(reason: &'static str) -> InvalidState {
+        InvalidState { reason }
+    }
+}
+
+impl fmt::Display for InvalidState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid save state loaded: {}", self.reason)
+    }
+}
+
+impl Error for InvalidState {}
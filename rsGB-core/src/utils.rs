@@ -24,12 +24,76 @@ pub const BIT_IGNORE: u8 = 2;
 
 pub type VRAM = [u8; 0x2000];
 
-pub const TICKS_PER_SAMPLE: u64 = 95;
+// The number of T-cycles per second
+pub const CLOCK_RATE: u32 = 4194304;
 
-#[derive(Debug, Clone, Copy)]
+pub const DEFAULT_SAMPLE_RATE: u32 = 44100;
+
+// The APU samples are sent to the audio callback in batches
+pub const AUDIO_BATCH_CYCLES: u64 = 4096;
+
+/// The format of the pixels written to the framebuffer. The 32-bit formats
+/// write one `u32` per pixel, named from the most significant byte. The
+/// other formats are packed: their bytes follow each other in memory
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ColorMode {
     RGBA,
     ARGB,
+    BGRA,
+    ABGR, // The bytes are in RGBA order on little-endian machines
+    RGB565, // 2 bytes per pixel, as a native-endian u16
+    RGB24, // 3 bytes per pixel, in RGB order
+    // 1 byte per pixel: the shade (0 to 3, from the lightest) in bits 0-1,
+    // and the palette in bits 2-3 (0 for BG/window, 1 for OBJ0, 2 for OBJ1)
+    Indexed,
+}
+
+impl ColorMode {
+    pub fn bytes_per_pixel(&self) -> usize {
+        match self {
+            ColorMode::RGBA | ColorMode::ARGB | ColorMode::BGRA | ColorMode::ABGR => 4,
+            ColorMode::RGB565 => 2,
+            ColorMode::RGB24 => 3,
+            ColorMode::Indexed => 1,
+        }
+    }
+
+    /// Converts the 0xRRGGBB color of a shade to this format. The packed
+    /// formats are returned as their bytes, starting from the least significant
+    pub(crate) fn encode_shade(&self, rgb: u32, shade: usize, palette: usize) -> u32 {
+        let [_, r, g, b] = rgb.to_be_bytes();
+
+        match self {
+            ColorMode::RGBA => rgb << 8 | 0xFF,
+            ColorMode::ARGB => 0xFF000000 | rgb,
+            ColorMode::BGRA => u32::from_be_bytes([b, g, r, 0xFF]),
+            ColorMode::ABGR => u32::from_be_bytes([0xFF, b, g, r]),
+            ColorMode::RGB565 => {
+                let rgb565 = (r as u16 >> 3) << 11 | (g as u16 >> 2) << 5 | b as u16 >> 3;
+                let [low, high] = rgb565.to_ne_bytes();
+                u32::from_le_bytes([low, high, 0, 0])
+            }
+            ColorMode::RGB24 => u32::from_le_bytes([r, g, b, 0]),
+            ColorMode::Indexed => (palette << 2 | shade) as u32,
+        }
+    }
+
+    /// Writes the pixel at the given index of the screen
+    #[inline(always)]
+    pub(crate) fn write_pixel(&self, framebuffer: &mut [u32], index: usize, color: u32) {
+        let size = self.bytes_per_pixel();
+        if size == 4 {
+            framebuffer[index] = color;
+            return
+        }
+
+        for (offset, byte) in (index * size..).zip(&color.to_le_bytes()[..size]) {
+            let word = &mut framebuffer[offset / 4];
+            let mut bytes = word.to_ne_bytes();
+            bytes[offset % 4] = *byte;
+            *word = u32::from_ne_bytes(bytes);
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
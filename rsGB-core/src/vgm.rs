@@ -0,0 +1,113 @@
+use std::{fs::File, io::{self, BufWriter, Seek, SeekFrom, Write}, path::Path};
+
+use crate::utils::CLOCK_RATE;
+
+const HEADER_SIZE: u32 = 0x100;
+const VERSION: u32 = 0x170;
+
+// The timestamps of a VGM file are counted in samples at 44100 Hz
+const VGM_SAMPLE_RATE: u64 = 44100;
+
+const DMG_WRITE: u8 = 0xB3;
+const WAIT: u8 = 0x61;
+const SHORT_WAIT: u8 = 0x70; // Waits from 1 to 16 samples
+const END_OF_DATA: u8 = 0x66;
+
+/// Logs the writes to the APU registers into a VGM 1.70 file, for the
+/// Game Boy DMG chip. The sizes in the header are only correct once the
+/// recorder is finished or dropped
+pub struct VgmRecorder {
+    writer: BufWriter<File>,
+    data_size: u32,
+    error: Option<io::Error>,
+    finished: bool,
+
+    start: u64,   // The cycle the recording started at
+    samples: u64, // The timestamp of the last command
+}
+
+impl VgmRecorder {
+    pub fn create(path: &Path, cycle: u64) -> io::Result<VgmRecorder> {
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        let mut header = [0; HEADER_SIZE as usize];
+        header[0x00..0x04].copy_from_slice(b"Vgm ");
+        header[0x08..0x0C].copy_from_slice(&VERSION.to_le_bytes());
+        header[0x34..0x38].copy_from_slice(&(HEADER_SIZE - 0x34).to_le_bytes()); // Data offset
+        header[0x80..0x84].copy_from_slice(&CLOCK_RATE.to_le_bytes());
+        writer.write_all(&header)?;
+
+        Ok(VgmRecorder {
+            writer,
+            data_size: 0,
+            error: None,
+            finished: false,
+
+            start: cycle,
+            samples: 0,
+        })
+    }
+
+    /// Logs a write to a register between 0xFF10 and 0xFF3F
+    pub fn write_register(&mut self, cycle: u64, address: u16, value: u8) {
+        self.wait_until(cycle);
+        self.write_command(&[DMG_WRITE, (address - 0xFF10) as u8, value]);
+    }
+
+    /// Writes the final sizes in the header, after the time elapsed
+    /// since the last write
+    pub fn finish(mut self, cycle: u64) -> io::Result<()> {
+        self.wait_until(cycle);
+        self.finalize()
+    }
+
+    fn wait_until(&mut self, cycle: u64) {
+        let samples = (cycle - self.start) * VGM_SAMPLE_RATE / CLOCK_RATE as u64;
+
+        while self.samples < samples {
+            let wait = (samples - self.samples).min(u16::MAX as u64);
+            if wait <= 16 {
+                self.write_command(&[SHORT_WAIT + wait as u8 - 1]);
+            } else {
+                let [low, high] = (wait as u16).to_le_bytes();
+                self.write_command(&[WAIT, low, high]);
+            }
+            self.samples += wait;
+        }
+    }
+
+    // The first error is kept, and returned by `finish`
+    fn write_command(&mut self, command: &[u8]) {
+        if self.error.is_some() {
+            return
+        }
+
+        match self.writer.write_all(command) {
+            Ok(()) => self.data_size += command.len() as u32,
+            Err(error) => self.error = Some(error),
+        }
+    }
+
+    fn finalize(&mut self) -> io::Result<()> {
+        self.write_command(&[END_OF_DATA]);
+        self.finished = true;
+
+        if let Some(error) = self.error.take() {
+            return Err(error)
+        }
+
+        self.writer.seek(SeekFrom::Start(0x04))?;
+        self.writer.write_all(&(HEADER_SIZE + self.data_size - 4).to_le_bytes())?;
+        self.writer.seek(SeekFrom::Start(0x18))?;
+        self.writer.write_all(&(self.samples as u32).to_le_bytes())?;
+        self.writer.flush()
+    }
+}
+
+impl Drop for VgmRecorder {
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = self.finalize();
+        }
+    }
+}
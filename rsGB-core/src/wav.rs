@@ -0,0 +1,92 @@
+use std::{fs::File, io::{self, BufWriter, Seek, SeekFrom, Write}, path::Path};
+
+const HEADER_SIZE: u32 = 44;
+const CHANNELS: u16 = 2;
+const BITS_PER_SAMPLE: u16 = 16;
+
+/// Writes stereo samples to a 16-bit PCM WAV file. The sizes in the
+/// header are only correct once the recorder is finished or dropped
+pub struct WavRecorder {
+    writer: BufWriter<File>,
+    data_size: u32,
+    error: Option<io::Error>,
+    finished: bool,
+}
+
+impl WavRecorder {
+    pub fn create(path: &Path, sample_rate: u32) -> io::Result<WavRecorder> {
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        let block_align = CHANNELS * BITS_PER_SAMPLE / 8;
+
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&(HEADER_SIZE - 8).to_le_bytes())?;
+        writer.write_all(b"WAVE")?;
+
+        writer.write_all(b"fmt ")?;
+        writer.write_all(&16u32.to_le_bytes())?;
+        writer.write_all(&1u16.to_le_bytes())?; // PCM
+        writer.write_all(&CHANNELS.to_le_bytes())?;
+        writer.write_all(&sample_rate.to_le_bytes())?;
+        writer.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+        writer.write_all(&block_align.to_le_bytes())?;
+        writer.write_all(&BITS_PER_SAMPLE.to_le_bytes())?;
+
+        writer.write_all(b"data")?;
+        writer.write_all(&0u32.to_le_bytes())?;
+
+        Ok(WavRecorder {
+            writer,
+            data_size: 0,
+            error: None,
+            finished: false,
+        })
+    }
+
+    /// The first error is kept, and returned by `finish`
+    pub fn write_sample(&mut self, (left, right): (f32, f32)) {
+        if self.error.is_some() {
+            return
+        }
+
+        let mut bytes = [0; 4];
+        bytes[..2].copy_from_slice(&to_pcm(left).to_le_bytes());
+        bytes[2..].copy_from_slice(&to_pcm(right).to_le_bytes());
+
+        match self.writer.write_all(&bytes) {
+            Ok(()) => self.data_size += bytes.len() as u32,
+            Err(error) => self.error = Some(error),
+        }
+    }
+
+    /// Writes the final sizes in the header
+    pub fn finish(mut self) -> io::Result<()> {
+        self.finalize()
+    }
+
+    fn finalize(&mut self) -> io::Result<()> {
+        self.finished = true;
+
+        if let Some(error) = self.error.take() {
+            return Err(error)
+        }
+
+        self.writer.seek(SeekFrom::Start(4))?;
+        self.writer.write_all(&(HEADER_SIZE - 8 + self.data_size).to_le_bytes())?;
+        self.writer.seek(SeekFrom::Start(40))?;
+        self.writer.write_all(&self.data_size.to_le_bytes())?;
+        self.writer.flush()
+    }
+}
+
+impl Drop for WavRecorder {
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = self.finalize();
+        }
+    }
+}
+
+fn to_pcm(sample: f32) -> i16 {
+    (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16
+}
@@ -0,0 +1,224 @@
+mod archive_tests {
+    use std::{fs, io::Write, path::{Path, PathBuf}};
+
+    use flate2::{Compression, Crc, GzBuilder, write::DeflateEncoder};
+    use rsgb_core::{ColorMode, Gameboy, archived_roms, settings::Settings};
+
+    const ACID2: &str = "../test_roms/others/dmg-acid2.gb";
+    // With 8 KiB of RAM and a battery
+    const MBC5_ROM: &str = "../test_roms/mooneye/acceptance/oam_dma/sources-GS.gb";
+
+    fn render(path: &Path, rom_name: Option<&str>) -> Vec<u32> {
+        let mut gb = Gameboy::new(ColorMode::ARGB, |_| {});
+
+        let settings = Settings::default();
+        gb.load_archived_cartridge(path, rom_name, &settings).unwrap();
+
+        let mut framebuffer = [0; 0x5A00];
+        for _ in 0..60 {
+            gb.next_frame(&mut framebuffer, &settings);
+        }
+        framebuffer.to_vec()
+    }
+
+    // A text file stored as is, then the ROM compressed with deflate
+    fn write_zip(name: &str) -> PathBuf {
+        let rom = fs::read(ACID2).unwrap();
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&rom).unwrap();
+        let deflated = encoder.finish().unwrap();
+
+        let mut zip = Vec::new();
+        let mut directory = Vec::new();
+        let files = [
+            ("readme.txt", b"A test ROM".to_vec(), 0u16, b"A test ROM".as_slice()),
+            ("dmg-acid2.gb", deflated, 8, &rom),
+        ];
+
+        for (file_name, data, method, original) in &files {
+            let mut crc = Crc::new();
+            crc.update(original);
+
+            let offset = zip.len() as u32;
+            let mut fields = Vec::new();
+            fields.extend(20u16.to_le_bytes()); // Version
+            fields.extend(0u16.to_le_bytes()); // Flags
+            fields.extend(method.to_le_bytes());
+            fields.extend([0; 4]); // Time and date
+            fields.extend(crc.sum().to_le_bytes());
+            fields.extend((data.len() as u32).to_le_bytes());
+            fields.extend((original.len() as u32).to_le_bytes());
+            fields.extend((file_name.len() as u16).to_le_bytes());
+            fields.extend(0u16.to_le_bytes()); // Extra field
+
+            zip.extend(0x04034B50u32.to_le_bytes());
+            zip.extend(&fields);
+            zip.extend(file_name.as_bytes());
+            zip.extend(data);
+
+            directory.extend(0x02014B50u32.to_le_bytes());
+            directory.extend(20u16.to_le_bytes()); // Made by
+            directory.extend(&fields);
+            directory.extend([0; 6]); // Comment, disk and internal attributes
+            directory.extend([0; 4]); // External attributes
+            directory.extend(offset.to_le_bytes());
+            directory.extend(file_name.as_bytes());
+        }
+
+        let directory_offset = zip.len() as u32;
+        zip.extend(&directory);
+        zip.extend(0x06054B50u32.to_le_bytes());
+        zip.extend([0; 4]); // Disks
+        zip.extend((files.len() as u16).to_le_bytes());
+        zip.extend((files.len() as u16).to_le_bytes());
+        zip.extend((directory.len() as u32).to_le_bytes());
+        zip.extend(directory_offset.to_le_bytes());
+        zip.extend(0u16.to_le_bytes()); // Comment
+
+        let path = std::env::temp_dir().join(name);
+        fs::write(&path, zip).unwrap();
+        path
+    }
+
+    #[test]
+    fn plain_rom_is_its_only_rom() {
+        assert_eq!(archived_roms(Path::new(ACID2)).unwrap(), ["dmg-acid2.gb"]);
+    }
+
+    #[test]
+    fn zip_rom_is_loaded() {
+        let path = write_zip("rsgb_roms.zip");
+        let roms = archived_roms(&path).unwrap();
+        let image = render(&path, None);
+        let _ = fs::remove_file(&path);
+
+        assert_eq!(roms, ["dmg-acid2.gb"]);
+        assert!(image == render(Path::new(ACID2), None));
+    }
+
+    #[test]
+    fn gzip_rom_is_loaded() {
+        let path = std::env::temp_dir().join("rsgb_rom.gz");
+        let mut encoder = GzBuilder::new()
+            .filename("dmg-acid2.gb")
+            .write(fs::File::create(&path).unwrap(), Compression::default());
+        encoder.write_all(&fs::read(ACID2).unwrap()).unwrap();
+        encoder.finish().unwrap();
+
+        let roms = archived_roms(&path).unwrap();
+        let image = render(&path, None);
+        let _ = fs::remove_file(&path);
+
+        assert_eq!(roms, ["dmg-acid2.gb"]);
+        assert!(image == render(Path::new(ACID2), None));
+    }
+
+    #[test]
+    fn seven_zip_lzma_rom_is_loaded() {
+        let path = Path::new("../test_roms/archives/dmg-acid2.7z");
+
+        assert_eq!(archived_roms(path).unwrap(), ["dmg-acid2.gb"]);
+        assert!(render(path, None) == render(Path::new(ACID2), None));
+    }
+
+    // The ROMs are compressed together with LZMA2, and so is the header
+    #[test]
+    fn seven_zip_rom_is_chosen() {
+        let path = Path::new("../test_roms/archives/roms.7z");
+
+        assert_eq!(archived_roms(path).unwrap(), ["roms/2-causes.gb", "roms/dmg-acid2.gb"]);
+        assert!(render(path, Some("roms/dmg-acid2.gb")) == render(Path::new(ACID2), None));
+        assert!(render(path, None) != render(path, Some("roms/dmg-acid2.gb")));
+    }
+
+    #[test]
+    fn corrupted_archives_are_rejected() {
+        let mut gb = Gameboy::new(ColorMode::ARGB, |_| {});
+        let settings = Settings::default();
+
+        for archive in ["../test_roms/archives/roms.7z", "../test_roms/archives/dmg-acid2.7z"] {
+            let mut data = fs::read(archive).unwrap();
+            // Past the signature header
+            for byte in &mut data[40..60] {
+                *byte ^= 0x55;
+            }
+
+            let path = std::env::temp_dir().join("rsgb_corrupted.7z");
+            fs::write(&path, data).unwrap();
+            let result = gb.load_archived_cartridge(&path, None, &settings);
+            let _ = fs::remove_file(&path);
+
+            assert!(result.is_err());
+        }
+
+        let path = write_zip("rsgb_missing.zip");
+        let result = gb.load_archived_cartridge(&path, Some("missing.gb"), &settings);
+        let _ = fs::remove_file(&path);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn oversized_rom_is_rejected() {
+        let path = std::env::temp_dir().join("rsgb_oversized.gz");
+        let mut encoder = GzBuilder::new()
+            .filename("huge.gb")
+            .write(fs::File::create(&path).unwrap(), Compression::default());
+        encoder.write_all(&vec![0; 9 << 20]).unwrap();
+        encoder.finish().unwrap();
+
+        let mut gb = Gameboy::new(ColorMode::ARGB, |_| {});
+        let result = gb.load_cartridge(&path, &Settings::default());
+        let _ = fs::remove_file(&path);
+
+        assert!(result.is_err());
+    }
+
+    // A ROM with a byte of its header replaced, and the header checksum updated
+    fn write_patched_rom(rom: &str, address: usize, value: u8, name: &str) -> PathBuf {
+        let mut rom = fs::read(rom).unwrap();
+        rom[address] = value;
+        rom[0x14D] = rom[0x134..=0x14C].iter()
+            .fold(0u8, |checksum, &byte| checksum.wrapping_sub(byte).wrapping_sub(1));
+
+        let path = std::env::temp_dir().join(name);
+        fs::write(&path, rom).unwrap();
+        path
+    }
+
+    #[test]
+    fn unsupported_mbc_is_rejected() {
+        // Pocket Camera
+        let path = write_patched_rom(ACID2, 0x147, 0xFC, "rsgb_camera.gb");
+
+        let mut gb = Gameboy::new(ColorMode::ARGB, |_| {});
+        let result = gb.load_cartridge(&path, &Settings::default());
+        let _ = fs::remove_file(&path);
+
+        assert!(result.is_err_and(|error| error.to_string().contains("FC")));
+    }
+
+    #[test]
+    fn unknown_ram_size_is_rejected() {
+        let path = write_patched_rom(MBC5_ROM, 0x149, 0x07, "rsgb_ram_size.gb");
+
+        let mut gb = Gameboy::new(ColorMode::ARGB, |_| {});
+        let result = gb.load_cartridge(&path, &Settings::default());
+        let _ = fs::remove_file(&path);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn truncated_save_is_rejected() {
+        let save_folder = std::env::temp_dir().join("rsgb_truncated_save");
+        fs::create_dir_all(&save_folder).unwrap();
+        fs::write(save_folder.join("sources-GS.sav"), [0; 100]).unwrap();
+
+        let mut gb = Gameboy::new(ColorMode::ARGB, |_| {});
+        let result = gb.load_cartridge(Path::new(MBC5_ROM), &Settings::new(save_folder.clone()));
+        let _ = fs::remove_dir_all(&save_folder);
+
+        assert!(result.is_err());
+        assert!(!gb.cartridge_loaded());
+    }
+}
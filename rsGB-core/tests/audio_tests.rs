@@ -0,0 +1,263 @@
+mod common;
+
+mod audio_tests {
+    use std::{path::PathBuf, sync::{Arc, Mutex}};
+
+    use rsgb_core::{Gameboy, settings::{AudioFilter, Settings}};
+
+    use crate::common::write_gbs;
+
+    const CLOCK_RATE: f64 = 4_194_304.0;
+
+    struct Recording {
+        samples: Vec<(f32, f32)>,
+        channel_samples: Vec<[f32; 4]>,
+        seconds: f64,
+    }
+
+    // Records the audio and the separate channels for 5 seconds of emulation
+    fn record(rom: &str, sample_rate: u32, settings: &Settings) -> Recording {
+        let samples = Arc::new(Mutex::new(Vec::new()));
+        let channel_samples = Arc::new(Mutex::new(Vec::new()));
+
+        let sender = samples.clone();
+        let mut gb = Gameboy::new(rsgb_core::ColorMode::ARGB, move |sample| {
+            sender.lock().unwrap().push(sample);
+        });
+        gb.set_sample_rate(sample_rate);
+
+        let sender = channel_samples.clone();
+        gb.set_channel_callback(move |sample| {
+            sender.lock().unwrap().push(sample);
+        });
+
+        gb.load_cartridge(&PathBuf::from(rom), settings).unwrap();
+
+        let mut framebuffer = [0; 0x5A00];
+        while (gb.cycles() as f64) < CLOCK_RATE * 5.0 {
+            gb.next_frame(&mut framebuffer, settings);
+        }
+
+        Recording {
+            samples: samples.lock().unwrap().clone(),
+            channel_samples: channel_samples.lock().unwrap().clone(),
+            seconds: gb.cycles() as f64 / CLOCK_RATE,
+        }
+    }
+
+    fn with_filter(filter: AudioFilter) -> Settings {
+        let mut settings = Settings::default();
+        settings.set_audio_filter(filter);
+        settings
+    }
+
+    #[test]
+    fn sample_rate_is_respected() {
+        let rom = "../test_roms/blargg/dmg_sound.gb";
+
+        for sample_rate in [44100, 48000, 22050] {
+            let recording = record(rom, sample_rate, &Settings::default());
+            let samples = recording.samples;
+
+            let expected = sample_rate as f64 * recording.seconds;
+            assert!((samples.len() as f64 - expected).abs() < expected * 0.01,
+                "{} samples instead of {expected} at {sample_rate} Hz", samples.len());
+
+            assert!(samples.iter().all(|(left, right)| left.is_finite() && right.is_finite()));
+            assert_eq!(recording.channel_samples.len(), samples.len());
+        }
+    }
+
+    // The boot ROM leaves the DAC of CH1 on, its offset
+    // must be removed by the high-pass filter
+    #[test]
+    fn high_pass_removes_dc_offset() {
+        let rom = "../test_roms/others/dmg-acid2.gb";
+
+        for filter in [AudioFilter::DMG, AudioFilter::CGB] {
+            let samples = record(rom, 48000, &with_filter(filter)).samples;
+            let (left, right) = *samples.last().unwrap();
+            assert!(left.abs() < 0.001 && right.abs() < 0.001, "{filter:?} output: {left}, {right}");
+        }
+
+        let samples = record(rom, 48000, &with_filter(AudioFilter::Raw)).samples;
+        let (left, _) = *samples.last().unwrap();
+        assert!(left.abs() > 0.1, "{left}");
+    }
+
+    #[test]
+    fn noise_channel_is_clocked() {
+        let samples = Arc::new(Mutex::new(Vec::new()));
+        let sender = samples.clone();
+        let mut gb = Gameboy::new(rsgb_core::ColorMode::ARGB, |_| {});
+        gb.set_sample_rate(48000);
+        gb.set_channel_callback(move |sample| {
+            sender.lock().unwrap().push(sample[3]);
+        });
+
+        // INIT plays a note on CH4, at full volume
+        let path = write_gbs("rsgb_noise.gbs", 1, 1, 0x0410, [0, 0], &[
+            0x3E, 0x80, 0xE0, 0x26, // NR52: APU on
+            0x3E, 0xF0, 0xE0, 0x21, // NR42: volume 15, no envelope
+            0x3E, 0x11, 0xE0, 0x22, // NR43: shift 1, divider 1
+            0x3E, 0x80, 0xE0, 0x23, // NR44: trigger
+            0xC9,                   // RET
+        ]);
+        gb.load_gbs(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        let settings = with_filter(AudioFilter::Raw);
+        let mut framebuffer = [0; 0x5A00];
+        for _ in 0..60 {
+            gb.next_frame(&mut framebuffer, &settings);
+        }
+
+        // The LFSR output is random, the level goes up and down all the time
+        let samples = samples.lock().unwrap();
+        let changes = samples.windows(2).filter(|pair| (pair[0] - pair[1]).abs() > 0.5).count();
+        assert!(changes > samples.len() / 10, "{changes} changes in {} samples", samples.len());
+    }
+
+    #[test]
+    fn muted_channels_are_not_mixed() {
+        let rom = "../test_roms/blargg/dmg_sound.gb";
+
+        let mut settings = with_filter(AudioFilter::Raw);
+        for channel in 0..4 {
+            settings.set_channel_muted(channel, true);
+        }
+
+        let recording = record(rom, 48000, &settings);
+        assert!(recording.samples.iter().all(|&sample| sample == (0.0, 0.0)));
+
+        // The separate outputs aren't affected
+        assert!(recording.channel_samples.iter().any(|sample| sample.iter().any(|&level| level != 0.0)));
+
+        // Soloing a muted channel doesn't make it heard
+        settings.set_channel_solo(0, true);
+        let recording = record(rom, 48000, &settings);
+        assert!(recording.samples.iter().all(|&sample| sample == (0.0, 0.0)));
+    }
+
+    #[test]
+    fn recording_writes_wav_file() {
+        let path = std::env::temp_dir().join("rsgb_recording.wav");
+
+        let count = Arc::new(Mutex::new(0u32));
+        let sender = count.clone();
+        let mut gb = Gameboy::new(rsgb_core::ColorMode::ARGB, move |_| {
+            *sender.lock().unwrap() += 1;
+        });
+        gb.set_sample_rate(48000);
+
+        let settings = Settings::default();
+        gb.load_cartridge(&PathBuf::from("../test_roms/blargg/dmg_sound.gb"), &settings).unwrap();
+        gb.start_recording(&path).unwrap();
+
+        let mut framebuffer = [0; 0x5A00];
+        for _ in 0..60 {
+            gb.next_frame(&mut framebuffer, &settings);
+        }
+        gb.stop_recording().unwrap();
+        assert!(!gb.is_recording());
+
+        let wav = std::fs::read(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        let read_u32 = |offset: usize| u32::from_le_bytes(wav[offset..offset + 4].try_into().unwrap());
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(read_u32(24), 48000);
+        assert_eq!(read_u32(40), *count.lock().unwrap() * 4);
+        assert_eq!(wav.len(), 44 + read_u32(40) as usize);
+    }
+
+    #[test]
+    fn vgm_log_records_register_writes() {
+        let path = std::env::temp_dir().join("rsgb_log.vgm");
+
+        let mut gb = Gameboy::new(rsgb_core::ColorMode::ARGB, |_| {});
+        let settings = Settings::default();
+        gb.load_cartridge(&PathBuf::from("../test_roms/blargg/dmg_sound.gb"), &settings).unwrap();
+        gb.start_vgm_log(&path).unwrap();
+        let start = gb.cycles();
+
+        let mut framebuffer = [0; 0x5A00];
+        for _ in 0..60 {
+            gb.next_frame(&mut framebuffer, &settings);
+        }
+        gb.stop_vgm_log().unwrap();
+        assert!(!gb.is_vgm_logging());
+
+        let vgm = std::fs::read(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        let read_u32 = |offset: usize| u32::from_le_bytes(vgm[offset..offset + 4].try_into().unwrap());
+        assert_eq!(&vgm[0..4], b"Vgm ");
+        assert_eq!(read_u32(0x04) as usize, vgm.len() - 4);
+        assert_eq!(read_u32(0x08), 0x170);
+        assert_eq!(read_u32(0x80), 4_194_304);
+
+        // The waits add up to the time elapsed
+        let mut offset = 0x34 + read_u32(0x34) as usize;
+        let (mut writes, mut samples) = (0, 0);
+        loop {
+            match vgm[offset] {
+                0xB3 => {
+                    assert!(vgm[offset + 1] < 0x30);
+                    writes += 1;
+                    offset += 3;
+                }
+                0x61 => {
+                    samples += u16::from_le_bytes([vgm[offset + 1], vgm[offset + 2]]) as u32;
+                    offset += 3;
+                }
+                command @ 0x70..=0x7F => {
+                    samples += (command & 0xF) as u32 + 1;
+                    offset += 1;
+                }
+                0x66 => break,
+                command => panic!("Unexpected command {command:02X}"),
+            }
+        }
+        assert_eq!(offset, vgm.len() - 1);
+        assert_eq!(samples, read_u32(0x18));
+        assert_eq!(samples as u64, (gb.cycles() - start) * 44100 / 4_194_304);
+        assert!(writes > 30, "{writes} writes");
+    }
+
+    // The APU keeps counting its own cycles when a state is loaded
+    #[test]
+    fn vgm_log_starts_after_a_loaded_state() {
+        let path = std::env::temp_dir().join("rsgb_loaded_state.vgm");
+        let rom = PathBuf::from("../test_roms/blargg/dmg_sound.gb");
+        let settings = Settings::default();
+        let mut framebuffer = [0; 0x5A00];
+
+        let mut gb = Gameboy::new(rsgb_core::ColorMode::ARGB, |_| {});
+        gb.load_cartridge(&rom, &settings).unwrap();
+        for _ in 0..600 {
+            gb.next_frame(&mut framebuffer, &settings);
+        }
+        let state = gb.save_state();
+
+        let mut gb = Gameboy::new(rsgb_core::ColorMode::ARGB, |_| {});
+        gb.load_cartridge(&rom, &settings).unwrap();
+        for _ in 0..10 {
+            gb.next_frame(&mut framebuffer, &settings);
+        }
+        gb.load_state(&state).unwrap();
+        gb.start_vgm_log(&path).unwrap();
+        let start = gb.cycles();
+
+        for _ in 0..60 {
+            gb.next_frame(&mut framebuffer, &settings);
+        }
+        gb.stop_vgm_log().unwrap();
+
+        let vgm = std::fs::read(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        let samples = u32::from_le_bytes(vgm[0x18..0x1C].try_into().unwrap());
+        assert_eq!(samples as u64, (gb.cycles() - start) * 44100 / 4_194_304);
+    }
+}
@@ -0,0 +1,70 @@
+mod blargg_tests {
+    use std::path::{Path, PathBuf};
+
+    use rsgb_core::{Gameboy, settings::Settings};
+
+    // 60 seconds of emulated time
+    const CYCLE_BUDGET: u64 = 4_194_304 * 60;
+
+    // Blargg's tests write their status at 0xA000 once this signature is written
+    const SIGNATURE: [u8; 3] = [0xDE, 0xB0, 0x61];
+    const RUNNING: u8 = 0x80;
+
+    mod oam_bug {
+        use std::path::Path;
+
+        #[test_each::blob(glob = "test_roms/blargg/oam_bug_singles/[!7]-*.gb", name(segments = 1))]
+        fn run_test(_content: &[u8], path: &Path) {
+            super::run_blargg(path);
+        }
+
+        // Its dumps take more than the 8 KiB of cartridge RAM. The ROM writes its
+        // output past them into the WRAM, and crashes before reporting its result
+        #[test]
+        #[ignore = "the output of this ROM overflows the cartridge RAM"]
+        fn run_test_7_timing_effect() {
+            super::run_blargg(Path::new("../test_roms/blargg/oam_bug_singles/7-timing_effect.gb"));
+        }
+    }
+
+    fn run_blargg(path: &Path) {
+        let mut gb = Gameboy::new(rsgb_core::ColorMode::ARGB, |_| {});
+
+        // The saves are kept out of the test folder, and removed
+        // beforehand so that a previous result can't be read back
+        let save_folder = std::env::temp_dir().join("rsgb_blargg");
+        std::fs::create_dir_all(&save_folder).unwrap();
+
+        let rom_path = PathBuf::from(path);
+        let mut save_path = save_folder.join(rom_path.file_name().unwrap());
+        save_path.set_extension("sav");
+        let _ = std::fs::remove_file(&save_path);
+
+        let settings = Settings::new(save_folder);
+        gb.load_cartridge(&rom_path, &settings).unwrap();
+
+        let mut framebuffer = [0; 0x5A00];
+
+        while gb.cycles() < CYCLE_BUDGET {
+            gb.next_frame(&mut framebuffer, &settings);
+
+            let debug_info = gb.debug();
+            let signature = [debug_info.read(0xA001), debug_info.read(0xA002), debug_info.read(0xA003)];
+
+            if signature == SIGNATURE && debug_info.read(0xA000) != RUNNING {
+                break
+            }
+        }
+
+        let debug_info = gb.debug();
+        let mut output = String::new();
+        let mut address = 0xA004;
+        while debug_info.read(address) != 0 && address < 0xC000 {
+            output.push(debug_info.read(address) as char);
+            address += 1;
+        }
+
+        assert!(gb.cycles() < CYCLE_BUDGET, "Cycle budget exhausted: {output}");
+        assert_eq!(debug_info.read(0xA000), 0, "{output}");
+    }
+}
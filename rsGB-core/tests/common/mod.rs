@@ -0,0 +1,22 @@
+use std::path::PathBuf;
+
+// The code is loaded at 0x0400, where INIT starts, and PLAY is
+// one of its routines. The file is written in the temporary folder
+pub fn write_gbs(name: &str, track_count: u8, first_track: u8, play: u16, timer: [u8; 2], code: &[u8]) -> PathBuf {
+    let mut data = vec![0; 0x70];
+    data[0..4].copy_from_slice(b"GBS\x01");
+    data[0x04] = track_count;
+    data[0x05] = first_track;
+    data[0x06..0x08].copy_from_slice(&0x0400u16.to_le_bytes()); // Load
+    data[0x08..0x0A].copy_from_slice(&0x0400u16.to_le_bytes()); // Init
+    data[0x0A..0x0C].copy_from_slice(&play.to_le_bytes());
+    data[0x0C..0x0E].copy_from_slice(&0xFFFEu16.to_le_bytes()); // SP
+    data[0x0E..0x10].copy_from_slice(&timer); // Modulo and control
+    data[0x10..0x14].copy_from_slice(b"Test");
+    data[0x30..0x36].copy_from_slice(b"Author");
+    data.extend(code);
+
+    let path = std::env::temp_dir().join(name);
+    std::fs::write(&path, data).unwrap();
+    path
+}
@@ -0,0 +1,104 @@
+mod common;
+
+mod gbs_tests {
+    use std::path::PathBuf;
+
+    use rsgb_core::{Gameboy, settings::Settings};
+
+    use crate::common;
+
+    // INIT stores the track number at 0xA001,
+    // PLAY counts its calls at 0xA000
+    fn write_gbs(name: &str, timer_modulo: u8, timer_control: u8) -> PathBuf {
+        common::write_gbs(name, 3, 2, 0x0404, [timer_modulo, timer_control], &[
+            0xEA, 0x01, 0xA0,       // LD (0xA001),A
+            0xC9,                   // RET
+            0x21, 0x00, 0xA0,       // LD HL,0xA000
+            0x34,                   // INC (HL)
+            0xC9,                   // RET
+        ])
+    }
+
+    fn run_frames(gb: &mut Gameboy, frames: usize) {
+        let settings = Settings::default();
+        let mut framebuffer = [0; 0x5A00];
+        for _ in 0..frames {
+            gb.next_frame(&mut framebuffer, &settings);
+        }
+    }
+
+    #[test]
+    fn gbs_header_is_parsed() {
+        let path = write_gbs("rsgb_header.gbs", 0, 0);
+        let mut gb = Gameboy::new(rsgb_core::ColorMode::ARGB, |_| {});
+        let info = gb.load_gbs(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(info.title, "Test");
+        assert_eq!(info.author, "Author");
+        assert_eq!(info.copyright, "");
+        assert_eq!(info.track_count, 3);
+        assert_eq!(info.first_track, 1);
+        assert_eq!(gb.gbs_info().unwrap().title, "Test");
+    }
+
+    #[test]
+    fn invalid_gbs_is_rejected() {
+        let path = std::env::temp_dir().join("rsgb_invalid.gbs");
+        std::fs::write(&path, [0; 0x100]).unwrap();
+
+        let mut gb = Gameboy::new(rsgb_core::ColorMode::ARGB, |_| {});
+        assert!(gb.load_gbs(&path).is_err());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn play_is_called_on_vblank() {
+        let path = write_gbs("rsgb_vblank.gbs", 0, 0);
+        let mut gb = Gameboy::new(rsgb_core::ColorMode::ARGB, |_| {});
+        gb.load_gbs(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        run_frames(&mut gb, 60);
+        assert_eq!(gb.debug().read(0xA001), 1);
+
+        let calls = gb.debug().read(0xA000);
+        assert!((59..=60).contains(&calls), "{calls} calls");
+    }
+
+    // The timer overflows every 64 ticks at 4096 Hz
+    #[test]
+    fn play_is_called_on_timer() {
+        let path = write_gbs("rsgb_timer.gbs", 0xC0, 0x04);
+        let mut gb = Gameboy::new(rsgb_core::ColorMode::ARGB, |_| {});
+        gb.load_gbs(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        run_frames(&mut gb, 60);
+        let expected = gb.cycles() as f64 / 4_194_304.0 * 64.0;
+        let calls = gb.debug().read(0xA000) as f64;
+        assert!((calls - expected).abs() <= 1.0, "{calls} calls instead of {expected}");
+    }
+
+    #[test]
+    fn track_selection_restarts_init() {
+        let path = write_gbs("rsgb_tracks.gbs", 0, 0);
+        let mut gb = Gameboy::new(rsgb_core::ColorMode::ARGB, |_| {});
+        gb.load_gbs(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        run_frames(&mut gb, 2);
+        gb.select_track(2).unwrap();
+        run_frames(&mut gb, 2);
+        assert_eq!(gb.debug().read(0xA001), 2);
+
+        // The console is power cycled, PLAY counts its calls from 0 again
+        let calls = gb.debug().read(0xA000);
+        assert!((1..=2).contains(&calls), "{calls} calls");
+
+        // Out of range
+        gb.select_track(3).unwrap();
+        run_frames(&mut gb, 2);
+        assert_eq!(gb.debug().read(0xA001), 2);
+    }
+}
@@ -1,10 +1,13 @@
 mod mooneye_tests {
-    use std::collections::HashMap;
+    use std::{collections::HashMap, path::{Path, PathBuf}};
 
-    mod acceptance {
-        use std::{path::{Path, PathBuf}, time::{Duration, Instant}};
+    use rsgb_core::{Gameboy, settings::Settings};
+
+    // 30 seconds of emulated time, no mooneye test should take that long
+    const CYCLE_BUDGET: u64 = 4_194_304 * 30;
 
-        use rsgb_core::{Gameboy, settings::Settings};
+    mod acceptance {
+        use std::path::Path;
 
         const SKIP_LIST: [&str; 9] = [
             "boot_div2-S",
@@ -20,33 +23,61 @@ mod mooneye_tests {
 
         #[test_each::blob(glob = "test_roms/mooneye/acceptance/**/*.gb", name(segments = 1))]
         fn run_test(_content: &[u8], path: &Path) {
-            let mut gb = Gameboy::new(rsgb_core::ColorMode::ARGB, |_| {});
+            super::run_mooneye(path, &SKIP_LIST, false);
+        }
+    }
+
+    mod emulator_only {
+        use std::path::Path;
+
+        #[test_each::blob(glob = "test_roms/mooneye/emulator-only/**/*.gb", name(segments = 2))]
+        fn run_test(_content: &[u8], path: &Path) {
+            super::run_mooneye(path, &[], false);
+        }
+    }
 
-            let settings = Settings::default();
-            let rom_path = PathBuf::from(path);
+    // The misc and madness ROMs are left out, they only target the AGB, CGB and MGB models
 
-            if SKIP_LIST.contains(&rom_path.file_stem().unwrap().to_str().unwrap()) {
-                return
-            }
+    mod scanline_renderer {
+        use std::path::Path;
 
-            gb.load_cartridge(&rom_path, &settings);
+        #[test_each::blob(glob = "test_roms/mooneye/acceptance/ppu/*.gb", name(segments = 1))]
+        fn run_ppu_test(_content: &[u8], path: &Path) {
+            super::run_mooneye(path, &[], true);
+        }
+
+        #[test_each::blob(glob = "test_roms/mooneye/acceptance/*timing*.gb", name(segments = 1))]
+        fn run_timing_test(_content: &[u8], path: &Path) {
+            super::run_mooneye(path, &[], true);
+        }
+    }
+
+    fn run_mooneye(path: &Path, skip_list: &[&str], scanline_renderer: bool) {
+        let mut gb = Gameboy::new(rsgb_core::ColorMode::ARGB, |_| {});
 
-            let timeout = Duration::from_secs(20);
-            let start_time = Instant::now();
+        let mut settings = Settings::default();
+        settings.set_software_breakpoints(true);
+        settings.set_scanline_renderer(scanline_renderer);
 
-            let mut framebuffer = [0; 0x5A00];
+        let rom_path = PathBuf::from(path);
 
-            while start_time.elapsed() < timeout && !gb.debug().current_instruction().contains("JR FE") { // Infinite loop of jumping in place
-                gb.next_frame(&mut framebuffer, &settings);
-            }
-            
-            let debug_info = gb.debug();
-            let registers = debug_info.registers();
-            assert!(start_time.elapsed() < timeout);
-            assert!(super::successful_test(&registers));
+        if skip_list.contains(&rom_path.file_stem().unwrap().to_str().unwrap()) {
+            return
         }
+
+        gb.load_cartridge(&rom_path, &settings).unwrap();
+
+        let mut framebuffer = [0; 0x5A00];
+
+        while gb.cycles() < CYCLE_BUDGET && !gb.breakpoint_hit() {
+            gb.next_frame(&mut framebuffer, &settings);
+        }
+
+        let debug_info = gb.debug();
+        let registers = debug_info.registers();
+        assert!(gb.breakpoint_hit(), "Cycle budget exhausted before reaching the breakpoint");
+        assert!(successful_test(&registers));
     }
-    
 
     fn successful_test(registers: &HashMap<&str, u16>) -> bool {
         return (registers["b"] == 3) &
@@ -56,4 +87,4 @@ mod mooneye_tests {
             (registers["h"] == 21) &
             (registers["l"] == 34)
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,147 @@
+mod renderer_tests {
+    use std::path::PathBuf;
+
+    use rsgb_core::{ColorMode, FrameBlender, Gameboy, settings::{FrameBlending, PalettePreset, Settings}};
+
+    const FRAMES: usize = 60;
+
+    fn render(rom: &str, scanline_renderer: bool, color_mode: ColorMode) -> Vec<u32> {
+        let mut gb = Gameboy::new(color_mode, |_| {});
+
+        let mut settings = Settings::default();
+        settings.set_scanline_renderer(scanline_renderer);
+
+        gb.load_cartridge(&PathBuf::from(rom), &settings).unwrap();
+
+        let mut framebuffer = [0; 0x5A00];
+        for _ in 0..FRAMES {
+            gb.next_frame(&mut framebuffer, &settings);
+        }
+        framebuffer.to_vec()
+    }
+
+    #[test]
+    fn scanline_renderer_matches_fifo() {
+        let rom = "../test_roms/others/dmg-acid2.gb";
+        assert!(render(rom, false, ColorMode::ARGB) == render(rom, true, ColorMode::ARGB));
+    }
+
+    #[test]
+    fn palette_is_applied_at_runtime() {
+        let mut gb = Gameboy::new(rsgb_core::ColorMode::ARGB, |_| {});
+
+        let mut settings = Settings::default();
+        gb.load_cartridge(&PathBuf::from("../test_roms/others/dmg-acid2.gb"), &settings).unwrap();
+
+        let mut framebuffer = [0; 0x5A00];
+        for _ in 0..FRAMES {
+            gb.next_frame(&mut framebuffer, &settings);
+        }
+        let gray = framebuffer;
+
+        settings.set_palette(PalettePreset::DmgGreen.palette());
+        gb.next_frame(&mut framebuffer, &settings);
+
+        // Each shade is replaced by its color
+        let grays = PalettePreset::Grayscale.palette().bg.map(|rgb| 0xFF000000 | rgb);
+        let greens = PalettePreset::DmgGreen.palette().bg.map(|rgb| 0xFF000000 | rgb);
+        for (pixel, gray) in framebuffer.iter().zip(gray) {
+            let shade = grays.iter().position(|&color| color == gray).unwrap();
+            assert_eq!(*pixel, greens[shade]);
+        }
+    }
+
+    #[test]
+    fn color_modes_encode_the_same_image() {
+        let rom = "../test_roms/others/dmg-acid2.gb";
+        let argb = render(rom, false, ColorMode::ARGB);
+        let grays = PalettePreset::Grayscale.palette().bg.map(|rgb| 0xFF000000 | rgb);
+
+        let to_bytes = |framebuffer: Vec<u32>| -> Vec<u8> {
+            framebuffer.iter().flat_map(|pixel| pixel.to_ne_bytes()).collect()
+        };
+        let bgra = render(rom, false, ColorMode::BGRA);
+        let abgr = render(rom, false, ColorMode::ABGR);
+        let rgb565 = to_bytes(render(rom, false, ColorMode::RGB565));
+        let rgb24 = to_bytes(render(rom, false, ColorMode::RGB24));
+        let indexed = to_bytes(render(rom, false, ColorMode::Indexed));
+
+        for (i, pixel) in argb.iter().enumerate() {
+            let [_, r, g, b] = pixel.to_be_bytes();
+
+            assert_eq!(bgra[i], u32::from_be_bytes([b, g, r, 0xFF]));
+            assert_eq!(abgr[i], u32::from_be_bytes([0xFF, b, g, r]));
+
+            let expected = (r as u16 >> 3) << 11 | (g as u16 >> 2) << 5 | b as u16 >> 3;
+            assert_eq!(u16::from_ne_bytes([rgb565[2 * i], rgb565[2 * i + 1]]), expected);
+
+            assert_eq!(rgb24[3 * i..3 * i + 3], [r, g, b]);
+
+            let shade = grays.iter().position(|color| color == pixel).unwrap();
+            assert_eq!(indexed[i] & 0b11, shade as u8);
+        }
+    }
+
+    #[test]
+    fn blending_keeps_still_images() {
+        let rom = "../test_roms/others/dmg-acid2.gb";
+        let expected = render(rom, false, ColorMode::ARGB);
+
+        for blending in [FrameBlending::Mix, FrameBlending::Ghosting(0.8)] {
+            let mut gb = Gameboy::new(ColorMode::ARGB, |_| {});
+
+            let mut settings = Settings::default();
+            settings.set_frame_blending(blending);
+            gb.load_cartridge(&PathBuf::from(rom), &settings).unwrap();
+
+            // The image is still long enough for the ghosting to fade out
+            let mut framebuffer = [0; 0x5A00];
+            for _ in 0..FRAMES * 2 {
+                gb.next_frame(&mut framebuffer, &settings);
+            }
+            assert!(framebuffer.to_vec() == expected, "{blending:?}");
+        }
+    }
+
+    #[test]
+    fn flickering_pixels_are_blended() {
+        let (black, white) = (0xFF000000, 0xFFFFFFFF);
+        let frame = |color: u32| vec![color; 0x5A00];
+
+        let mut blender = FrameBlender::new();
+        let mut framebuffer = frame(black);
+        blender.apply(FrameBlending::Mix, ColorMode::ARGB, &mut framebuffer);
+        assert_eq!(framebuffer[0], black);
+
+        for _ in 0..4 {
+            let mut framebuffer = frame(white);
+            blender.apply(FrameBlending::Mix, ColorMode::ARGB, &mut framebuffer);
+            assert_eq!(framebuffer[0], 0xFF808080);
+
+            let mut framebuffer = frame(black);
+            blender.apply(FrameBlending::Mix, ColorMode::ARGB, &mut framebuffer);
+            assert_eq!(framebuffer[0], 0xFF808080);
+        }
+
+        // The ghost of a white frame fades out
+        let mut blender = FrameBlender::new();
+        let mut framebuffer = frame(white);
+        blender.apply(FrameBlending::Ghosting(0.5), ColorMode::ARGB, &mut framebuffer);
+
+        let mut levels = Vec::new();
+        for _ in 0..10 {
+            let mut framebuffer = frame(black);
+            blender.apply(FrameBlending::Ghosting(0.5), ColorMode::ARGB, &mut framebuffer);
+            levels.push(framebuffer[0] & 0xFF);
+        }
+        assert!(levels.windows(2).all(|pair| pair[1] < pair[0] || pair[1] == 0), "{levels:?}");
+        assert_eq!(levels[9], 0);
+
+        // Only the formats with a byte per channel are blended
+        let mut blender = FrameBlender::new();
+        blender.apply(FrameBlending::Mix, ColorMode::Indexed, &mut frame(0));
+        let mut framebuffer = frame(0x03030303);
+        blender.apply(FrameBlending::Mix, ColorMode::Indexed, &mut framebuffer);
+        assert_eq!(framebuffer[0], 0x03030303);
+    }
+}
@@ -0,0 +1,69 @@
+mod reset_tests {
+    use std::path::PathBuf;
+
+    use rsgb_core::{ColorMode, Gameboy, settings::Settings};
+
+    // Switches between the ROM banks of its MBC1 for each test
+    const CPU_INSTRS: &str = "../test_roms/blargg/cpu_instrs.gb";
+
+    fn run_frames(gb: &mut Gameboy, frames: usize) -> Vec<u32> {
+        let settings = Settings::default();
+        let mut framebuffer = [0; 0x5A00];
+        for _ in 0..frames {
+            gb.next_frame(&mut framebuffer, &settings);
+        }
+        framebuffer.to_vec()
+    }
+
+    #[test]
+    fn reset_restarts_the_game() {
+        let mut gb = Gameboy::new(ColorMode::ARGB, |_| {});
+        gb.load_cartridge(&PathBuf::from(CPU_INSTRS), &Settings::default()).unwrap();
+
+        let first_run = run_frames(&mut gb, 120);
+        let cycles = gb.cycles();
+        run_frames(&mut gb, 60);
+
+        gb.reset().unwrap();
+        assert_eq!(gb.cycles(), 0);
+        assert!(gb.cartridge_loaded());
+
+        assert!(run_frames(&mut gb, 120) == first_run);
+        assert_eq!(gb.cycles(), cycles);
+    }
+
+    #[test]
+    fn paused_console_only_advances_frame_by_frame() {
+        let mut gb = Gameboy::new(ColorMode::ARGB, |_| {});
+        let settings = Settings::default();
+        gb.load_cartridge(&PathBuf::from(CPU_INSTRS), &settings).unwrap();
+        run_frames(&mut gb, 10);
+
+        gb.set_paused(true);
+        assert!(gb.is_paused());
+
+        let cycles = gb.cycles();
+        run_frames(&mut gb, 10);
+        assert_eq!(gb.cycles(), cycles);
+
+        // One frame lasts 70224 cycles
+        let mut framebuffer = [0; 0x5A00];
+        gb.advance_frame(&mut framebuffer, &settings);
+        assert!(gb.cycles().abs_diff(cycles + 70224) < 100);
+
+        gb.set_paused(false);
+        run_frames(&mut gb, 1);
+        assert!(gb.cycles().abs_diff(cycles + 2 * 70224) < 100);
+    }
+
+    #[test]
+    fn ejected_cartridge_is_removed() {
+        let mut gb = Gameboy::new(ColorMode::ARGB, |_| {});
+        gb.load_cartridge(&PathBuf::from(CPU_INSTRS), &Settings::default()).unwrap();
+        run_frames(&mut gb, 10);
+
+        gb.eject().unwrap();
+        assert!(!gb.cartridge_loaded());
+        assert_eq!(gb.cycles(), 0);
+    }
+}
@@ -0,0 +1,123 @@
+mod state_tests {
+    use std::path::PathBuf;
+
+    use rsgb_core::{ColorMode, Gameboy, settings::Settings};
+
+    // Switches between the ROM banks of its MBC1 for each test
+    const CPU_INSTRS: &str = "../test_roms/blargg/cpu_instrs.gb";
+    const ACID2: &str = "../test_roms/others/dmg-acid2.gb";
+
+    fn load(rom: &str) -> Gameboy {
+        let mut gb = Gameboy::new(ColorMode::ARGB, |_| {});
+        gb.load_cartridge(&PathBuf::from(rom), &Settings::default()).unwrap();
+        gb
+    }
+
+    fn run_frames(gb: &mut Gameboy, frames: usize) -> Vec<u32> {
+        let settings = Settings::default();
+        let mut framebuffer = [0; 0x5A00];
+        for _ in 0..frames {
+            gb.next_frame(&mut framebuffer, &settings);
+        }
+        framebuffer.to_vec()
+    }
+
+    #[test]
+    fn state_is_restored() {
+        let mut gb = load(CPU_INSTRS);
+        run_frames(&mut gb, 90);
+        let state = gb.save_state();
+
+        let image = run_frames(&mut gb, 120);
+        let cycles = gb.cycles();
+
+        gb.load_state(&state).unwrap();
+        assert!(run_frames(&mut gb, 120) == image);
+        assert_eq!(gb.cycles(), cycles);
+
+        // In another console with the same game
+        let mut other = load(CPU_INSTRS);
+        other.load_state(&state).unwrap();
+        assert!(run_frames(&mut other, 120) == image);
+    }
+
+    #[test]
+    fn state_is_restored_mid_frame() {
+        let mut gb = load("../test_roms/mooneye/acceptance/timer/div_write.gb");
+
+        // The PPU is drawing the screen when the CPU stops on LD B,B
+        let mut settings = Settings::default();
+        settings.set_software_breakpoints(true);
+        let mut framebuffer = [0; 0x5A00];
+        for _ in 0..600 {
+            gb.next_frame(&mut framebuffer, &settings);
+            if gb.breakpoint_hit() {
+                break
+            }
+        }
+        assert!(gb.breakpoint_hit());
+        let state = gb.save_state();
+
+        let image = run_frames(&mut gb, 30);
+        gb.load_state(&state).unwrap();
+        assert!(run_frames(&mut gb, 30) == image);
+    }
+
+    #[test]
+    fn invalid_states_are_rejected() {
+        let mut gb = load(CPU_INSTRS);
+        run_frames(&mut gb, 30);
+        let state = gb.save_state();
+
+        let mut other_game = load(ACID2);
+        assert!(other_game.load_state(&state).is_err());
+
+        // The console is left as it was
+        let mut reference = load(CPU_INSTRS);
+        reference.load_state(&state).unwrap();
+        let image = run_frames(&mut reference, 60);
+
+        let mut gb = load(CPU_INSTRS);
+        gb.load_state(&state).unwrap();
+        assert!(gb.load_state(&state[..state.len() - 1]).is_err());
+        assert!(gb.load_state(&[state.as_slice(), &[0]].concat()).is_err());
+        assert!(gb.load_state(b"RGBS").is_err());
+
+        // The dot of the PPU in its line is out of range, it's saved before
+        // 5 flags of the PPU and the 9 bytes of the console timing
+        let mut corrupted = state.clone();
+        let line_ticks = corrupted.len() - 18;
+        corrupted[line_ticks..line_ticks + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(gb.load_state(&corrupted).is_err());
+        assert!(run_frames(&mut gb, 60) == image);
+    }
+
+    #[test]
+    fn inconsistent_states_are_rejected() {
+        let mut gb = load(ACID2);
+        run_frames(&mut gb, 30);
+        let state = gb.save_state();
+
+        // The timer and the APU are saved after the time of the console and its
+        // 5 events. The ROM has no MBC, so the PPU follows, starting with its fetcher
+        let now = gb.cycles().to_le_bytes();
+        let scheduler = state.windows(8).position(|bytes| bytes == now).unwrap();
+        let (timer_synced, apu_synced, fetcher) = (scheduler + 48, scheduler + 56, scheduler + 64);
+        let ppu_synced = state.len() - 9;
+
+        // A device updated past the time of the console
+        for synced in [timer_synced, apu_synced, ppu_synced] {
+            let mut corrupted = state.clone();
+            corrupted[synced..synced + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+            assert!(gb.load_state(&corrupted).is_err());
+        }
+
+        // The fetcher fetching a sprite, without one
+        let mut corrupted = state.clone();
+        corrupted[fetcher + 12] = 1;
+        corrupted[fetcher + 15] = 0;
+        assert!(gb.load_state(&corrupted).is_err());
+
+        gb.load_state(&state).unwrap();
+    }
+}
@@ -32,7 +32,10 @@ fn main() {
             let _ = audio_sender.try_push(sample);
         }
     );
-    gameboy.load_cartridge(&rom_path, &Settings::default());
+    if let Err(error) = gameboy.load_cartridge(&rom_path, &Settings::default()) {
+        eprintln!("Could not load {}: {error}", rom_path.display());
+        return;
+    }
 
     // Preparation of the audio stream
     let mut previous_audio = (0.0, 0.0);
@@ -40,6 +43,7 @@ fn main() {
     let host = cpal::default_host();
     let device = host.default_output_device().expect("No output device detected");
     let config = device.default_output_config().unwrap();
+    gameboy.set_sample_rate(config.sample_rate().0);
 
     let stream = device.build_output_stream(
         &config.config(), 
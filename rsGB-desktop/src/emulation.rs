@@ -1,23 +1,29 @@
-use std::{path::PathBuf, time::{Duration, Instant}};
+use std::{error::Error, io, path::Path, time::{Duration, Instant}};
 
-use bytemuck::cast_slice;
+use bytemuck::{cast_slice, cast_slice_mut};
 // 3rd party crates
 use cpal::{Stream, traits::{DeviceTrait, HostTrait, StreamTrait}};
 use eframe::egui::{self, ColorImage};
 use ringbuf::traits::{Consumer, Producer, Split};
 
 // local crate import
-use rsgb_core::{ColorMode, DebugInfo, Gameboy, InputState};
+use rsgb_core::{ColorMode, DebugInfo, Gameboy, GbsInfo, InputState};
 
-use crate::settings::{AppSettings, FRAME_SIZE, XRES, YRES};
+use crate::{filters::VideoFilter, gamepad::Gamepads, settings::{AppSettings, FRAME_SIZE, XRES, YRES}};
 
 pub struct EmulationState {
     gameboy: Gameboy,
 
     framebuffer: [u32; FRAME_SIZE],
+    filtered: Vec<u32>, // The output of the video filter
     frame_texture: egui::TextureHandle,
 
     _audio_stream: Stream,
+
+    // The track played when a GBS file is loaded
+    track: u8,
+    // Runs one frame while paused
+    frame_advance: bool,
     
     counter: u32,
     instant: Instant,
@@ -27,8 +33,8 @@ impl EmulationState {
     pub fn new(ctx: &egui::Context) -> EmulationState {
         let (mut audio_sender, mut audio_receiver) = ringbuf::StaticRb::<(f32, f32), 8192>::default().split();
 
-        let  gameboy = Gameboy::new( 
-            ColorMode::ARGB, 
+        let mut gameboy = Gameboy::new( 
+            ColorMode::ABGR, 
             move |sample| { 
                 let _ = audio_sender.try_push(sample);
             }
@@ -49,6 +55,7 @@ impl EmulationState {
         let host = cpal::default_host();
         let device = host.default_output_device().expect("No output device detected");
         let config = device.default_output_config().unwrap();
+        gameboy.set_sample_rate(config.sample_rate().0);
 
         let _audio_stream = device.build_output_stream(
             &config.config(), 
@@ -71,24 +78,129 @@ impl EmulationState {
             gameboy,
 
             framebuffer,
+            filtered: Vec::new(),
             frame_texture,
 
             _audio_stream,
 
+            track: 0,
+            frame_advance: false,
+
             counter: 0,
             instant: Instant::now(),
         }
     }
 
-    pub fn load_cartridge(&mut self, rom_path: &PathBuf, settings: &AppSettings) {
-        self.gameboy.load_cartridge(rom_path, settings.emu_settings());
+    /// Loads a ROM, or the one with this name if it's in an archive
+    pub fn load_cartridge(&mut self, rom_path: &Path, rom_name: Option<&str>, settings: &AppSettings) -> Result<(), Box<dyn Error>> {
+        self.gameboy.load_archived_cartridge(rom_path, rom_name, settings.emu_settings())
+    }
+
+    pub fn load_gbs(&mut self, gbs_path: &Path) -> Result<(), Box<dyn Error>> {
+        let info = self.gameboy.load_gbs(gbs_path)?;
+        self.track = info.first_track;
+        Ok(())
+    }
+
+    fn select_track(&mut self, track: u8) {
+        self.finish_vgm_log();
+        if let Err(error) = self.gameboy.select_track(track) {
+            eprintln!("Could not select the track: {error}");
+        }
+        self.track = track;
     }
 
     pub fn cartridge_loaded(&self) -> bool {
         self.gameboy.cartridge_loaded()
     }
 
-    pub fn render(&mut self, ctx: &egui::Context, settings: &AppSettings) {
+    pub fn is_paused(&self) -> bool {
+        self.gameboy.is_paused()
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.gameboy.set_paused(paused);
+    }
+
+    pub fn advance_frame(&mut self) {
+        self.frame_advance = true;
+    }
+
+    /// Restarts the game, the GBS files keep their track
+    pub fn reset(&mut self) {
+        self.finish_vgm_log();
+        if let Err(error) = self.gameboy.reset() {
+            eprintln!("Could not reset the console: {error}");
+        }
+    }
+
+    /// Removes the game, the audio stream is kept for the next one
+    pub fn stop(&mut self) {
+        if let Err(error) = self.gameboy.stop_recording() {
+            eprintln!("Could not finish the audio recording: {error}");
+        }
+        self.finish_vgm_log();
+        if let Err(error) = self.gameboy.eject() {
+            eprintln!("Could not eject the cartridge: {error}");
+        }
+        self.gameboy.set_paused(false);
+        self.frame_advance = false;
+        self.track = 0;
+    }
+
+    /// The battery save of the game, None for GBS files
+    pub fn save_path(&self) -> Option<&Path> {
+        self.gameboy.save_path()
+    }
+
+    pub fn save_state(&self) -> Vec<u8> {
+        self.gameboy.save_state()
+    }
+
+    /// The last frame drawn, its pixels in RGBA order
+    pub fn screen(&self) -> Vec<u8> {
+        cast_slice(&self.framebuffer).to_vec()
+    }
+
+    /// Loads a state with the screen saved with it, which is shown until the next frame
+    pub fn load_state(&mut self, state: &[u8], screen: &[u8]) -> Result<(), Box<dyn Error>> {
+        self.gameboy.load_state(state)?;
+        cast_slice_mut(&mut self.framebuffer).copy_from_slice(screen);
+        Ok(())
+    }
+
+    pub fn start_recording(&mut self, path: &Path) -> io::Result<()> {
+        self.gameboy.start_recording(path)
+    }
+
+    pub fn stop_recording(&mut self) -> io::Result<()> {
+        self.gameboy.stop_recording()
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.gameboy.is_recording()
+    }
+
+    pub fn start_vgm_log(&mut self, path: &Path) -> io::Result<()> {
+        self.gameboy.start_vgm_log(path)
+    }
+
+    pub fn stop_vgm_log(&mut self) -> io::Result<()> {
+        self.gameboy.stop_vgm_log()
+    }
+
+    pub fn is_vgm_logging(&self) -> bool {
+        self.gameboy.is_vgm_logging()
+    }
+
+    // The log ends with the game, its errors are reported apart
+    fn finish_vgm_log(&mut self) {
+        if let Err(error) = self.stop_vgm_log() {
+            eprintln!("Could not finish the VGM log: {error}");
+        }
+    }
+
+    pub fn render(&mut self, ctx: &egui::Context, settings: &AppSettings, gamepads: Option<&Gamepads>) {
         let mut input = InputState::default();
 
         ctx.input(|i | {
@@ -96,11 +208,26 @@ impl EmulationState {
                 input.update(*button, i.key_down(*key));
             }
         });
+        if let Some(gamepads) = gamepads {
+            gamepads.apply_input(settings, &mut input);
+        }
 
         self.gameboy.apply_input(input);
-        self.gameboy.next_frame(&mut self.framebuffer, settings.emu_settings());
+        if std::mem::take(&mut self.frame_advance) {
+            self.gameboy.advance_frame(&mut self.framebuffer, settings.emu_settings());
+        } else {
+            self.gameboy.next_frame(&mut self.framebuffer, settings.emu_settings());
+        }
 
-        let color_image = ColorImage::from_rgba_unmultiplied([XRES, YRES], cast_slice(&self.framebuffer));
+        // The bytes of the ABGR pixels are already in RGBA order
+        let filter = settings.video_filter;
+        let color_image = if filter == VideoFilter::None {
+            ColorImage::from_rgba_unmultiplied([XRES, YRES], cast_slice(&self.framebuffer))
+        } else {
+            filter.apply(&self.framebuffer, &mut self.filtered);
+            let scale = filter.scale();
+            ColorImage::from_rgba_unmultiplied([XRES * scale, YRES * scale], cast_slice(&self.filtered))
+        };
 
         self.frame_texture.set(color_image, egui::TextureOptions::NEAREST);
 
@@ -112,6 +239,11 @@ impl EmulationState {
             self.counter = 0;
         }
 
+        if let Some(info) = self.gameboy.gbs_info().cloned() {
+            self.render_player(ctx, &info);
+            return
+        }
+
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.centered_and_justified(|ui| {
                 let available_width = ui.available_width();
@@ -120,7 +252,8 @@ impl EmulationState {
                 let available_height = ui.available_height();
                 let y_scale = (available_height / YRES as f32).floor();
 
-                let scale = x_scale.min(y_scale);
+                // The filtered frame is larger than the screen
+                let scale = x_scale.min(y_scale) / filter.scale() as f32;
 
                 let image_widget = egui::Image::new(&self.frame_texture)
                     .fit_to_original_size(scale);
@@ -129,6 +262,40 @@ impl EmulationState {
         });
     }
 
+    // GBS files have nothing to display, only the track selection
+    fn render_player(&mut self, ctx: &egui::Context, info: &GbsInfo) {
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.vertical_centered(|ui| {
+                ui.heading(&info.title);
+                ui.label(&info.author);
+                ui.label(&info.copyright);
+                ui.separator();
+
+                ui.horizontal(|ui| {
+                    if ui.add_enabled(self.track > 0, egui::Button::new("⏮")).clicked() {
+                        self.select_track(self.track - 1);
+                    }
+
+                    let mut track = self.track as u32 + 1;
+                    let slider = egui::Slider::new(&mut track, 1..=info.track_count as u32)
+                        .prefix("Track ")
+                        .suffix(format!(" / {}", info.track_count));
+                    if ui.add(slider).changed() {
+                        self.select_track(track as u8 - 1);
+                    }
+
+                    if ui.add_enabled(self.track + 1 < info.track_count, egui::Button::new("⏭")).clicked() {
+                        self.select_track(self.track + 1);
+                    }
+
+                    if ui.button("Restart").clicked() {
+                        self.select_track(self.track);
+                    }
+                });
+            });
+        });
+    }
+
     pub fn debug_info<'a>(&'a self) -> DebugInfo<'a> {
         self.gameboy.debug()
     }
@@ -0,0 +1,220 @@
+use crate::settings::{XRES, YRES};
+
+/// The pixel-art filters applied to the frame before it's uploaded. The
+/// pixels are ABGR, so their bytes are in RGBA order
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VideoFilter {
+    None,
+    Scale2x,
+    Scale3x,
+    Hq2x,
+    XbrLite,
+    LcdGrid,
+}
+
+impl VideoFilter {
+    pub const ALL: [VideoFilter; 6] = [
+        VideoFilter::None,
+        VideoFilter::Scale2x,
+        VideoFilter::Scale3x,
+        VideoFilter::Hq2x,
+        VideoFilter::XbrLite,
+        VideoFilter::LcdGrid,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            VideoFilter::None => "None",
+            VideoFilter::Scale2x => "Scale2x",
+            VideoFilter::Scale3x => "Scale3x",
+            VideoFilter::Hq2x => "hq2x",
+            VideoFilter::XbrLite => "xBR Lite",
+            VideoFilter::LcdGrid => "LCD Grid",
+        }
+    }
+
+    /// The size of the output relative to the screen
+    pub fn scale(self) -> usize {
+        match self {
+            VideoFilter::None => 1,
+            VideoFilter::Scale2x | VideoFilter::Hq2x | VideoFilter::XbrLite => 2,
+            VideoFilter::Scale3x | VideoFilter::LcdGrid => 3,
+        }
+    }
+
+    pub fn apply(self, frame: &[u32], output: &mut Vec<u32>) {
+        let scale = self.scale();
+        output.resize(XRES * scale * YRES * scale, 0);
+
+        let frame = Frame::new(frame, matches!(self, VideoFilter::Hq2x | VideoFilter::XbrLite));
+        for y in 0..YRES {
+            for x in 0..XRES {
+                let mut block = [0; 9];
+                match self {
+                    VideoFilter::None => block[0] = frame.get(x, y, 0, 0),
+                    VideoFilter::Scale2x => scale2x(&frame, x, y, &mut block),
+                    VideoFilter::Scale3x => scale3x(&frame, x, y, &mut block),
+                    VideoFilter::Hq2x => hq2x(&frame, x, y, &mut block),
+                    VideoFilter::XbrLite => xbr_lite(&frame, x, y, &mut block),
+                    VideoFilter::LcdGrid => lcd_grid(&frame, x, y, &mut block),
+                }
+
+                // The block holds the output pixels line by line
+                for (i, pixel) in block[..scale * scale].iter().enumerate() {
+                    let (out_x, out_y) = (x * scale + i % scale, y * scale + i / scale);
+                    output[out_y * XRES * scale + out_x] = *pixel;
+                }
+            }
+        }
+    }
+}
+
+struct Frame<'a> {
+    pixels: &'a [u32],
+    yuv: Vec<(i32, i32, i32)>, // Only computed for the filters comparing colors
+}
+
+impl Frame<'_> {
+    fn new(pixels: &[u32], with_yuv: bool) -> Frame<'_> {
+        let yuv = if with_yuv {
+            pixels.iter().map(|&pixel| yuv(pixel)).collect()
+        } else {
+            Vec::new()
+        };
+        Frame { pixels, yuv }
+    }
+
+    // The pixels outside of the screen repeat the edges
+    fn index(&self, x: usize, y: usize, dx: isize, dy: isize) -> usize {
+        let x = (x as isize + dx).clamp(0, XRES as isize - 1) as usize;
+        let y = (y as isize + dy).clamp(0, YRES as isize - 1) as usize;
+        y * XRES + x
+    }
+
+    fn get(&self, x: usize, y: usize, dx: isize, dy: isize) -> u32 {
+        self.pixels[self.index(x, y, dx, dy)]
+    }
+
+    // The thresholds of hq2x
+    fn similar(&self, a: usize, b: usize) -> bool {
+        let ((y1, u1, v1), (y2, u2, v2)) = (self.yuv[a], self.yuv[b]);
+        (y1 - y2).abs() <= 48 && (u1 - u2).abs() <= 7 && (v1 - v2).abs() <= 6
+    }
+
+    // The weights of xBR
+    fn distance(&self, a: usize, b: usize) -> i32 {
+        let ((y1, u1, v1), (y2, u2, v2)) = (self.yuv[a], self.yuv[b]);
+        48 * (y1 - y2).abs() + 7 * (u1 - u2).abs() + 6 * (v1 - v2).abs()
+    }
+}
+
+// AdvMAME2x: the corners take the color of their two neighbours when they match
+fn scale2x(frame: &Frame, x: usize, y: usize, block: &mut [u32; 9]) {
+    let p = frame.get(x, y, 0, 0);
+    let (a, b, c, d) = (frame.get(x, y, 0, -1), frame.get(x, y, 1, 0), frame.get(x, y, -1, 0), frame.get(x, y, 0, 1));
+
+    block[0] = if c == a && c != d && a != b { a } else { p };
+    block[1] = if a == b && a != c && b != d { b } else { p };
+    block[2] = if d == c && d != b && c != a { c } else { p };
+    block[3] = if b == d && b != a && d != c { d } else { p };
+}
+
+// AdvMAME3x, with the neighbours named from A to I
+fn scale3x(frame: &Frame, x: usize, y: usize, block: &mut [u32; 9]) {
+    let get = |dx, dy| frame.get(x, y, dx, dy);
+    let (a, b, c) = (get(-1, -1), get(0, -1), get(1, -1));
+    let (d, e, f) = (get(-1, 0), get(0, 0), get(1, 0));
+    let (g, h, i) = (get(-1, 1), get(0, 1), get(1, 1));
+
+    if b == h || d == f {
+        block.fill(e);
+        return
+    }
+
+    block[0] = if d == b { d } else { e };
+    block[1] = if (d == b && e != c) || (b == f && e != a) { b } else { e };
+    block[2] = if b == f { f } else { e };
+    block[3] = if (d == b && e != g) || (d == h && e != a) { d } else { e };
+    block[4] = e;
+    block[5] = if (b == f && e != i) || (h == f && e != c) { f } else { e };
+    block[6] = if d == h { d } else { e };
+    block[7] = if (d == h && e != i) || (h == f && e != g) { h } else { e };
+    block[8] = if h == f { f } else { e };
+}
+
+// Like hq2x, the colors are compared in YUV, and the corners along
+// an edge are interpolated instead of copied
+fn hq2x(frame: &Frame, x: usize, y: usize, block: &mut [u32; 9]) {
+    let p = frame.index(x, y, 0, 0);
+
+    for (corner, (dx, dy)) in [(-1, -1), (1, -1), (-1, 1), (1, 1)].into_iter().enumerate() {
+        let (horizontal, vertical) = (frame.index(x, y, dx, 0), frame.index(x, y, 0, dy));
+
+        block[corner] = if frame.similar(horizontal, vertical) && !frame.similar(p, horizontal) {
+            let pixels = frame.pixels;
+            mix(&[(pixels[p], 2), (pixels[horizontal], 1), (pixels[vertical], 1)])
+        } else {
+            frame.pixels[p]
+        };
+    }
+}
+
+// A single pass of xBR at level 1: an edge crossing the corner is detected by
+// comparing the gradients along both diagonals, and the corner is blended
+// with the closest neighbour
+fn xbr_lite(frame: &Frame, x: usize, y: usize, block: &mut [u32; 9]) {
+    let e = frame.index(x, y, 0, 0);
+    let distance = |a, b| frame.distance(a, b);
+
+    for (corner, (sx, sy)) in [(-1, -1), (1, -1), (-1, 1), (1, 1)].into_iter().enumerate() {
+        // The neighbourhood is mirrored so that the corner is always at the bottom right
+        let get = |dx: isize, dy: isize| frame.index(x, y, dx * sx, dy * sy);
+        let (b, c) = (get(0, -1), get(1, -1));
+        let (d, f) = (get(-1, 0), get(1, 0));
+        let (g, h, i) = (get(-1, 1), get(0, 1), get(1, 1));
+        let (f4, i4, h5, i5) = (get(2, 0), get(2, 1), get(0, 2), get(1, 2));
+
+        let edge = distance(e, c) + distance(e, g) + distance(i, f4) + distance(i, h5) + 4 * distance(h, f);
+        let across = distance(h, d) + distance(h, i5) + distance(f, i4) + distance(f, b) + 4 * distance(e, i);
+
+        block[corner] = if edge < across {
+            let closest = if distance(e, f) <= distance(e, h) { f } else { h };
+            mix(&[(frame.pixels[e], 1), (frame.pixels[closest], 1)])
+        } else {
+            frame.pixels[e]
+        };
+    }
+}
+
+// Each pixel is a dot, separated from the next ones by darker lines
+fn lcd_grid(frame: &Frame, x: usize, y: usize, block: &mut [u32; 9]) {
+    let p = frame.get(x, y, 0, 0);
+    let line = mix(&[(p, 3), (0xFF000000, 1)]);
+
+    *block = [
+        p, p, line,
+        p, p, line,
+        line, line, line,
+    ];
+}
+
+// The weighted average of the colors
+fn mix(colors: &[(u32, u32)]) -> u32 {
+    let total: u32 = colors.iter().map(|(_, weight)| weight).sum();
+
+    let mut channels = [0; 4];
+    for (color, weight) in colors {
+        for (channel, value) in channels.iter_mut().zip(color.to_ne_bytes()) {
+            *channel += value as u32 * weight;
+        }
+    }
+    u32::from_ne_bytes(channels.map(|channel| (channel / total) as u8))
+}
+
+fn yuv(color: u32) -> (i32, i32, i32) {
+    let [r, g, b, _] = color.to_ne_bytes().map(|channel| channel as i32);
+    let y = (r * 299 + g * 587 + b * 114) / 1000;
+    let u = (b - y) * 493 / 1000;
+    let v = (r - y) * 877 / 1000;
+    (y, u, v)
+}
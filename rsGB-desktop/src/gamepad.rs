@@ -0,0 +1,110 @@
+use std::{collections::HashMap, error::Error};
+
+use gilrs::{Axis, Button as PadButton, EventType, Gilrs};
+use rsgb_core::{Button, InputState};
+
+use crate::settings::AppSettings;
+
+// The buttons a controller can be bound with, named like in the settings file
+const PAD_BUTTONS: [PadButton; 19] = [
+    PadButton::South, PadButton::East, PadButton::North, PadButton::West,
+    PadButton::C, PadButton::Z,
+    PadButton::LeftTrigger, PadButton::LeftTrigger2, PadButton::RightTrigger, PadButton::RightTrigger2,
+    PadButton::Select, PadButton::Start, PadButton::Mode,
+    PadButton::LeftThumb, PadButton::RightThumb,
+    PadButton::DPadUp, PadButton::DPadDown, PadButton::DPadLeft, PadButton::DPadRight,
+];
+
+/// The game controllers, read through gilrs. They can be plugged
+/// and unplugged while the emulator runs
+pub struct Gamepads {
+    gilrs: Gilrs,
+
+    // The buttons pressed since the last update, and the name of their controller
+    pressed: Vec<(String, PadButton)>,
+}
+
+impl Gamepads {
+    pub fn new() -> Result<Gamepads, Box<dyn Error>> {
+        Ok(Gamepads {
+            gilrs: Gilrs::new()?,
+
+            pressed: Vec::new(),
+        })
+    }
+
+    /// Reads the buttons pressed since the last update. The state of
+    /// the controllers, and the ones plugged, are updated along
+    pub fn update(&mut self) {
+        self.pressed.clear();
+
+        while let Some(event) = self.gilrs.next_event() {
+            if let EventType::ButtonPressed(button, _) = event.event
+                && button != PadButton::Unknown {
+                let name = self.gilrs.gamepad(event.id).name().to_string();
+                self.pressed.push((name, button));
+            }
+        }
+    }
+
+    /// The names of the controllers plugged, without duplicates
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.gilrs.gamepads().map(|(_, gamepad)| gamepad.name().to_string()).collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    pub fn pressed(&self) -> &[(String, PadButton)] {
+        &self.pressed
+    }
+
+    /// Adds the buttons held on all the controllers to the input
+    pub fn apply_input(&self, settings: &AppSettings, input: &mut InputState) {
+        let threshold = settings.gamepad_deadzone();
+
+        for (_, gamepad) in self.gilrs.gamepads() {
+            for (&pad_button, &button) in settings.gamepad_map(gamepad.name()) {
+                if gamepad.is_pressed(pad_button) {
+                    input.update(button, true);
+                }
+            }
+
+            // The left stick always moves the D-pad, outside of the deadzone.
+            // Its Y axis goes up
+            let (x, y) = (gamepad.value(Axis::LeftStickX), gamepad.value(Axis::LeftStickY));
+            for (button, pushed) in [
+                (Button::LEFT, x < -threshold),
+                (Button::RIGHT, x > threshold),
+                (Button::UP, y > threshold),
+                (Button::DOWN, y < -threshold),
+            ] {
+                if pushed {
+                    input.update(button, true);
+                }
+            }
+        }
+    }
+}
+
+/// The bindings of the controllers without their own
+pub fn default_gamepad_map() -> HashMap<PadButton, Button> {
+    HashMap::from([
+        (PadButton::South, Button::A),
+        (PadButton::East, Button::B),
+        (PadButton::Start, Button::START),
+        (PadButton::Select, Button::SELECT),
+        (PadButton::DPadUp, Button::UP),
+        (PadButton::DPadDown, Button::DOWN),
+        (PadButton::DPadLeft, Button::LEFT),
+        (PadButton::DPadRight, Button::RIGHT),
+    ])
+}
+
+pub fn pad_button_name(button: PadButton) -> String {
+    format!("{button:?}")
+}
+
+pub fn pad_button_from_name(name: &str) -> Option<PadButton> {
+    PAD_BUTTONS.into_iter().find(|&button| pad_button_name(button) == name)
+}
@@ -1,77 +1,230 @@
+use std::path::{Path, PathBuf};
+
 // third party crates imports
 use eframe::egui;
 use rfd::FileDialog;
-use rsgb_core::settings::SpeedOption;
+use rsgb_core::settings::{AudioFilter, FrameBlending, SpeedOption};
 
 // child modules
 mod settings;
 mod emulation;
 mod debugger;
+mod filters;
+mod gamepad;
+mod save_states;
 
 use crate::{
     emulation::EmulationState, 
-    settings::AppSettings,
+    filters::VideoFilter,
+    gamepad::Gamepads,
     debugger::Debugger,
+    save_states::SaveStates,
 };
 
+pub use settings::AppSettings;
+
 
 pub struct MyEguiApp {
     emulation_state: EmulationState,
     debugger: Debugger,
+    save_states: SaveStates,
+    gamepads: Option<Gamepads>, // None when the controllers can't be read
     
     app_settings: AppSettings,
 
     display_debugger: bool,
     display_settings: bool,
+
+    // Recording started with the first game loaded
+    record_audio: Option<PathBuf>,
+    // Opened on the first update, from the command line
+    open_at_start: Option<PathBuf>,
+    // An archive with several ROMs, until one of them is chosen
+    archive_choice: Option<(PathBuf, Vec<String>)>,
+    // The game loaded, and its ROM in an archive, read again on a hard reset
+    current_game: Option<(PathBuf, Option<String>)>,
 }
 
 impl MyEguiApp {
-    pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
+    pub fn new(cc: &eframe::CreationContext<'_>, app_settings: AppSettings) -> Self {
         // Customize egui here with cc.egui_ctx.set_fonts and cc.egui_ctx.set_visuals.
         // Restore app state using cc.storage (requires the "persistence" feature).
         // Use the cc.gl (a glow::Context) to create graphics shaders and buffers that you can use
         // for e.g. egui::PaintCallback.
 
+        let gamepads = Gamepads::new()
+            .map_err(|error| eprintln!("Could not open the game controllers: {error}"))
+            .ok();
+
         MyEguiApp { 
             emulation_state: EmulationState::new(&cc.egui_ctx),
             debugger: Debugger::new(cc),
+            save_states: SaveStates::new(),
+            gamepads,
 
-            app_settings: AppSettings::new(),
+            app_settings,
 
             display_debugger: false,
             display_settings: false,
+
+            record_audio: None,
+            open_at_start: None,
+            archive_choice: None,
+            current_game: None,
         }
     }
+
+    /// Opens a game as soon as the window is shown
+    pub fn with_rom(mut self, path: PathBuf) -> Self {
+        self.open_at_start = Some(path);
+        self
+    }
+
+    fn open_file(&mut self, file: PathBuf) {
+        if !file.is_file() {
+            eprintln!("Could not open {}: the file doesn't exist", file.display());
+            self.app_settings.remove_recent_file(&file);
+            return
+        }
+
+        if is_gbs(&file) {
+            self.load_game(file, None);
+            return
+        }
+
+        match rsgb_core::archived_roms(&file) {
+            Ok(roms) if roms.len() > 1 => self.archive_choice = Some((file, roms)),
+            Ok(_) => self.load_game(file, None),
+            Err(error) => eprintln!("Could not open {}: {error}", file.display()),
+        }
+    }
+
+    /// Loads a game, or the ROM with this name in an archive
+    fn load_game(&mut self, file: PathBuf, rom_name: Option<&str>) {
+        if self.emulation_state.cartridge_loaded() {
+            // If another game was already loaded
+            self.emulation_state.stop();
+        }
+
+        self.app_settings.set_current_game(&file);
+        let result = if is_gbs(&file) {
+            self.emulation_state.load_gbs(&file)
+        } else {
+            self.emulation_state.load_cartridge(&file, rom_name, &self.app_settings)
+        };
+        if let Err(error) = result {
+            eprintln!("Could not load {}: {error}", file.display());
+            return
+        }
+        self.current_game = Some((file.clone(), rom_name.map(str::to_string)));
+        self.app_settings.add_recent_file(&file);
+        self.app_settings.save();
+
+        if let Some(path) = self.record_audio.take()
+            && let Err(error) = self.emulation_state.start_recording(&path) {
+            eprintln!("Could not record the audio to {}: {error}", path.display());
+        }
+    }
+
+    /// Records the audio to a WAV file once a game is loaded
+    pub fn with_audio_recording(mut self, path: PathBuf) -> Self {
+        self.record_audio = Some(path);
+        self
+    }
 }
 
 impl eframe::App for MyEguiApp {
    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {        
         ctx.request_repaint();
 
+        if let Some(gamepads) = &mut self.gamepads {
+            gamepads.update();
+        }
+
+        if let Some(file) = self.open_at_start.take() {
+            self.open_file(file);
+        }
+
+        // Only the first game dropped is opened
+        let dropped = ctx.input(|i| {
+            i.raw.dropped_files.iter()
+                .filter_map(|file| file.path.clone())
+                .find(|path| is_game(path) || is_gbs(path))
+        });
+        if let Some(file) = dropped {
+            self.open_file(file);
+        }
+
+        self.save_states.update(ctx, &mut self.emulation_state);
+
+        // Saved with the other settings on exit
+        if let Some(rect) = ctx.input(|i| i.viewport().inner_rect) {
+            self.app_settings.set_window_size(rect.width(), rect.height());
+        }
+
         egui::TopBottomPanel::top("Buttons").show(ctx, |ui| {
             ui.horizontal(|ui| {
                 ui.menu_button("File", |ui| {
                     if ui.button("Open").clicked() {
                         let file = FileDialog::new()
-                            .add_filter("GameBoy / GameBoy Color games", &["gb", "gbc"])
+                            .add_filter("GameBoy / GameBoy Color games", &["gb", "gbc", "zip", "7z", "gz"])
+                            .add_filter("GameBoy Sound files", &["gbs"])
                             .pick_file();
 
                         if let Some(file) = file {
-                            if self.emulation_state.cartridge_loaded() {
-                                // If another game was already loaded
-                                self.emulation_state = EmulationState::new(ctx);
-                            }
-                            self.emulation_state.load_cartridge(&file, &self.app_settings);
+                            self.open_file(file);
                         }
                     }
+
+                    ui.menu_button("Open Recent", |ui| {
+                        if self.app_settings.recent_files().is_empty() {
+                            ui.label("No recent files");
+                        }
+
+                        let mut opened = None;
+                        for file in self.app_settings.recent_files() {
+                            let name = file.file_name().unwrap_or_default().to_string_lossy();
+                            if ui.button(name).on_hover_text(file.display().to_string()).clicked() {
+                                opened = Some(file.clone());
+                            }
+                        }
+
+                        ui.separator();
+                        if ui.button("Clear Recent Files").clicked() {
+                            self.app_settings.clear_recent_files();
+                        }
+
+                        if let Some(file) = opened {
+                            self.open_file(file);
+                        }
+                    });
                 });
 
                 ui.menu_button("Emulation", |ui| {
                     ui.add_enabled_ui(self.emulation_state.cartridge_loaded(), |ui| {
+                        let paused = self.emulation_state.is_paused();
+                        if ui.button(if paused { "Resume" } else { "Pause" }).clicked() {
+                            self.emulation_state.set_paused(!paused);
+                        }
+                        if ui.add_enabled(paused, egui::Button::new("Frame Advance")).clicked() {
+                            self.emulation_state.advance_frame();
+                        }
+
+                        if ui.button("Reset").on_hover_text("Power cycles the console with the same game").clicked() {
+                            self.emulation_state.reset();
+                        }
+                        if ui.button("Hard Reset").on_hover_text("Reads the game from the disk again").clicked()
+                            && let Some((file, rom_name)) = self.current_game.clone() {
+                            self.load_game(file, rom_name.as_deref());
+                        }
+
                         if ui.button("Stop").clicked() {
-                            self.emulation_state = EmulationState::new(ctx);
+                            self.emulation_state.stop();
+                            self.current_game = None;
                         }
 
+                        ui.separator();
+
                         ui.menu_button("Speed", |ui| {
                             ui.selectable_value(&mut self.app_settings.emu_settings.speed, SpeedOption::Normal, "1x");
                             ui.selectable_value(&mut self.app_settings.emu_settings.speed, SpeedOption::X2, "2x");
@@ -79,6 +232,84 @@ impl eframe::App for MyEguiApp {
                             ui.selectable_value(&mut self.app_settings.emu_settings.speed, SpeedOption::X4, "4x");
                         });
 
+                        ui.menu_button("Video Filter", |ui| {
+                            for filter in VideoFilter::ALL {
+                                ui.selectable_value(&mut self.app_settings.video_filter, filter, filter.name());
+                            }
+                        });
+
+                        ui.menu_button("Frame Blending", |ui| {
+                            let mut blending = self.app_settings.emu_settings.frame_blending;
+
+                            ui.selectable_value(&mut blending, FrameBlending::Off, "Off");
+                            ui.selectable_value(&mut blending, FrameBlending::Mix, "Mix Frames");
+
+                            let ghosting = matches!(blending, FrameBlending::Ghosting(_));
+                            if ui.selectable_label(ghosting, "LCD Ghosting").clicked() && !ghosting {
+                                blending = FrameBlending::Ghosting(0.5);
+                            }
+                            if let FrameBlending::Ghosting(persistence) = &mut blending {
+                                ui.add(egui::Slider::new(persistence, 0.1..=0.9).text("Persistence"));
+                            }
+
+                            // Remembered for this game only
+                            if blending != self.app_settings.emu_settings.frame_blending {
+                                self.app_settings.set_frame_blending(blending);
+                            }
+                        });
+
+                        ui.menu_button("Audio Filter", |ui| {
+                            ui.selectable_value(&mut self.app_settings.emu_settings.audio_filter, AudioFilter::DMG, "DMG");
+                            ui.selectable_value(&mut self.app_settings.emu_settings.audio_filter, AudioFilter::CGB, "MGB/CGB");
+                            ui.selectable_value(&mut self.app_settings.emu_settings.audio_filter, AudioFilter::Raw, "Raw");
+                        });
+
+                        ui.menu_button("Audio Channels", |ui| {
+                            egui::Grid::new("audio_channels").show(ui, |ui| {
+                                let settings = &mut self.app_settings.emu_settings;
+                                for channel in 0..4 {
+                                    ui.label(format!("CH{}", channel + 1));
+                                    ui.checkbox(&mut settings.muted_channels[channel], "Mute");
+                                    ui.checkbox(&mut settings.solo_channels[channel], "Solo");
+                                    ui.end_row();
+                                }
+                            });
+                        });
+
+                        if self.emulation_state.is_recording() {
+                            if ui.button("Stop Audio Recording").clicked()
+                                && let Err(error) = self.emulation_state.stop_recording() {
+                                eprintln!("Could not finish the audio recording: {error}");
+                            }
+                        } else if ui.button("Record Audio...").clicked() {
+                            let file = FileDialog::new()
+                                .add_filter("WAV audio", &["wav"])
+                                .set_file_name("recording.wav")
+                                .save_file();
+
+                            if let Some(file) = file
+                                && let Err(error) = self.emulation_state.start_recording(&file) {
+                                eprintln!("Could not record the audio to {}: {error}", file.display());
+                            }
+                        }
+
+                        if self.emulation_state.is_vgm_logging() {
+                            if ui.button("Stop VGM Log").clicked()
+                                && let Err(error) = self.emulation_state.stop_vgm_log() {
+                                eprintln!("Could not finish the VGM log: {error}");
+                            }
+                        } else if ui.button("Log VGM...").clicked() {
+                            let file = FileDialog::new()
+                                .add_filter("VGM music", &["vgm"])
+                                .set_file_name("music.vgm")
+                                .save_file();
+
+                            if let Some(file) = file
+                                && let Err(error) = self.emulation_state.start_vgm_log(&file) {
+                                eprintln!("Could not log the music to {}: {error}", file.display());
+                            }
+                        }
+
                         ui.separator();
 
                         if ui.button("Debugger").clicked() {
@@ -87,6 +318,10 @@ impl eframe::App for MyEguiApp {
                     })
                 });
 
+                ui.menu_button("States", |ui| {
+                    self.save_states.menu(ui, &mut self.emulation_state);
+                });
+
                 if ui.button("Settings").clicked() {
                     self.display_settings = true;
                 }
@@ -99,7 +334,10 @@ impl eframe::App for MyEguiApp {
                         .with_always_on_top()                       
                         .with_title("Settings"),
                     |ctx, _class| {
-                        self.display_settings = self.app_settings.render(ctx);
+                        self.display_settings = self.app_settings.render(ctx, self.gamepads.as_ref());
+                        if !self.display_settings {
+                            self.app_settings.save();
+                        }
                     }
                 );
             }
@@ -119,8 +357,52 @@ impl eframe::App for MyEguiApp {
             }
         });
 
+        if let Some((archive, roms)) = &self.archive_choice {
+            let mut chosen = None;
+            let mut open = true;
+            egui::Window::new("Choose a ROM")
+                .open(&mut open)
+                .collapsible(false)
+                .resizable(false)
+                .anchor(egui::Align2::CENTER_CENTER, (0.0, 0.0))
+                .show(ctx, |ui| {
+                    ui.label(archive.file_name().unwrap_or_default().to_string_lossy());
+                    ui.separator();
+                    for rom in roms {
+                        if ui.button(rom).clicked() {
+                            chosen = Some(rom.clone());
+                        }
+                    }
+                });
+
+            if let Some(rom) = chosen {
+                let (archive, _) = self.archive_choice.take().unwrap();
+                self.load_game(archive, Some(&rom));
+            } else if !open {
+                self.archive_choice = None;
+            }
+        }
+
+        self.save_states.render(ctx, &mut self.emulation_state);
+
         if self.emulation_state.cartridge_loaded() {
-            self.emulation_state.render(ctx, &self.app_settings);
+            self.emulation_state.render(ctx, &self.app_settings, self.gamepads.as_ref());
         }
    }
-}
\ No newline at end of file
+
+   fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        self.app_settings.save();
+   }
+}
+
+fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    path.extension().is_some_and(|ext| extensions.iter().any(|e| ext.eq_ignore_ascii_case(e)))
+}
+
+fn is_game(path: &Path) -> bool {
+    has_extension(path, &["gb", "gbc", "zip", "7z", "gz"])
+}
+
+fn is_gbs(path: &Path) -> bool {
+    has_extension(path, &["gbs"])
+}
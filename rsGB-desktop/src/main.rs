@@ -1,17 +1,57 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+use std::path::PathBuf;
+
 use eframe::{NativeOptions, egui};
 
-use rsgb_desktop::MyEguiApp;
+use rsgb_desktop::{AppSettings, MyEguiApp};
+
+const USAGE: &str = "Usage: rsgb_desktop [--record-audio <file.wav>] [rom]";
 
 fn main() {    
+    // The recording starts with the first game loaded
+    let mut args = std::env::args().skip(1);
+    let mut record_audio = None;
+    let mut rom = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--record-audio" => match args.next() {
+                Some(path) => record_audio = Some(PathBuf::from(path)),
+                None => usage_error("--record-audio needs a file"),
+            },
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return
+            }
+            option if option.starts_with('-') => usage_error(&format!("Unknown option {option}")),
+            _ => rom = Some(PathBuf::from(arg)),
+        }
+    }
+
+    let app_settings = AppSettings::load();
+    let [width, height] = app_settings.window_size();
+
     let options = NativeOptions {
         viewport: egui::ViewportBuilder::default()
             .with_title("rsGB")
-            .with_inner_size((900.0, 675.0)),
+            .with_inner_size((width, height)),
         ..Default::default()
     };
     let _ = eframe::run_native("My egui App", 
         options, 
-        Box::new(|cc| Ok(Box::new(MyEguiApp::new(cc)))));
-}
\ No newline at end of file
+        Box::new(|cc| {
+            let mut app = MyEguiApp::new(cc, app_settings);
+            if let Some(path) = record_audio {
+                app = app.with_audio_recording(path);
+            }
+            if let Some(path) = rom {
+                app = app.with_rom(path);
+            }
+            Ok(Box::new(app))
+        }));
+}
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("{message}\n{USAGE}");
+    std::process::exit(2)
+}
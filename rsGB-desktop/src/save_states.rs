@@ -0,0 +1,285 @@
+use std::{fs, io, path::{Path, PathBuf}, time::{Duration, SystemTime, UNIX_EPOCH}};
+
+use eframe::egui::{self, ColorImage, Key, KeyboardShortcut, Modifiers};
+
+use crate::{emulation::EmulationState, settings::{FRAME_SIZE, XRES, YRES}};
+
+pub const SLOT_COUNT: usize = 8;
+
+const MAGIC: &[u8; 8] = b"RSGBSLOT";
+const SCREEN_LEN: usize = FRAME_SIZE * 4;
+
+// F1 to F8 load the slots, and save them with Shift
+const SLOT_KEYS: [Key; SLOT_COUNT] = [Key::F1, Key::F2, Key::F3, Key::F4, Key::F5, Key::F6, Key::F7, Key::F8];
+const UNDO_LOAD: KeyboardShortcut = KeyboardShortcut::new(Modifiers::NONE, Key::F9);
+
+fn save_shortcut(slot: usize) -> KeyboardShortcut {
+    KeyboardShortcut::new(Modifiers::SHIFT, SLOT_KEYS[slot])
+}
+
+fn load_shortcut(slot: usize) -> KeyboardShortcut {
+    KeyboardShortcut::new(Modifiers::NONE, SLOT_KEYS[slot])
+}
+
+/// A save state file, with the screen at the time it was saved
+struct SlotFile {
+    timestamp: SystemTime,
+    screen: Vec<u8>, // The pixels in RGBA order
+    state: Vec<u8>,
+}
+
+impl SlotFile {
+    fn read(path: &Path) -> io::Result<SlotFile> {
+        let data = fs::read(path)?;
+
+        let header_len = MAGIC.len() + 8;
+        if !data.starts_with(MAGIC) || data.len() < header_len + SCREEN_LEN {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a save state slot"))
+        }
+
+        let seconds = u64::from_le_bytes(data[MAGIC.len()..header_len].try_into().unwrap());
+        let timestamp = UNIX_EPOCH.checked_add(Duration::from_secs(seconds))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid save state slot date"))?;
+        let (screen, state) = data[header_len..].split_at(SCREEN_LEN);
+
+        Ok(SlotFile {
+            timestamp,
+            screen: screen.to_vec(),
+            state: state.to_vec(),
+        })
+    }
+
+    fn write(&self, path: &Path) -> io::Result<()> {
+        let seconds = self.timestamp.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+
+        let mut data = MAGIC.to_vec();
+        data.extend(seconds.to_le_bytes());
+        data.extend(&self.screen);
+        data.extend(&self.state);
+        fs::write(path, data)
+    }
+}
+
+// What the slots window shows of a slot
+struct SlotInfo {
+    timestamp: SystemTime,
+    thumbnail: egui::TextureHandle,
+}
+
+impl SlotInfo {
+    fn new(ctx: &egui::Context, slot: usize, file: &SlotFile) -> SlotInfo {
+        let image = ColorImage::from_rgba_unmultiplied([XRES, YRES], &file.screen);
+        SlotInfo {
+            timestamp: file.timestamp,
+            thumbnail: ctx.load_texture(format!("save_slot_{slot}"), image, egui::TextureOptions::LINEAR),
+        }
+    }
+}
+
+/// The numbered save states of the game loaded. They are stored next to
+/// its battery save, as `.ss1` to `.ss8` files
+pub struct SaveStates {
+    save_path: Option<PathBuf>, // The battery save of the game the slots were read for
+    slots: Vec<Option<SlotInfo>>,
+
+    // The state and the screen replaced by the last load
+    undo: Option<(Vec<u8>, Vec<u8>)>,
+
+    pub display_window: bool,
+}
+
+impl SaveStates {
+    pub fn new() -> SaveStates {
+        SaveStates {
+            save_path: None,
+            slots: Vec::new(),
+
+            undo: None,
+
+            display_window: false,
+        }
+    }
+
+    /// Reads the slots again when another game is loaded, and handles the shortcuts
+    pub fn update(&mut self, ctx: &egui::Context, emulation: &mut EmulationState) {
+        let save_path = emulation.save_path().map(Path::to_path_buf);
+        if save_path != self.save_path {
+            self.save_path = save_path;
+            self.undo = None;
+            self.slots = (0..SLOT_COUNT)
+                .map(|slot| {
+                    let file = SlotFile::read(&self.slot_path(slot)?).ok()?;
+                    Some(SlotInfo::new(ctx, slot, &file))
+                })
+                .collect();
+        }
+
+        if self.save_path.is_none() {
+            return
+        }
+
+        for slot in 0..SLOT_COUNT {
+            // Shift+F1 would be taken for F1 otherwise
+            if ctx.input_mut(|i| i.consume_shortcut(&save_shortcut(slot))) {
+                self.save(ctx, emulation, slot);
+            } else if ctx.input_mut(|i| i.consume_shortcut(&load_shortcut(slot))) {
+                self.load(emulation, slot);
+            }
+        }
+        if ctx.input_mut(|i| i.consume_shortcut(&UNDO_LOAD)) {
+            self.undo_load(emulation);
+        }
+    }
+
+    fn slot_path(&self, slot: usize) -> Option<PathBuf> {
+        Some(self.save_path.as_ref()?.with_extension(format!("ss{}", slot + 1)))
+    }
+
+    fn save(&mut self, ctx: &egui::Context, emulation: &EmulationState, slot: usize) {
+        let Some(path) = self.slot_path(slot) else { return };
+
+        let file = SlotFile {
+            timestamp: SystemTime::now(),
+            screen: emulation.screen(),
+            state: emulation.save_state(),
+        };
+        if let Err(error) = file.write(&path) {
+            eprintln!("Could not save the state to {}: {error}", path.display());
+            return
+        }
+        self.slots[slot] = Some(SlotInfo::new(ctx, slot, &file));
+    }
+
+    fn load(&mut self, emulation: &mut EmulationState, slot: usize) {
+        let Some(path) = self.slot_path(slot) else { return };
+        if self.slots[slot].is_none() {
+            return
+        }
+
+        let file = match SlotFile::read(&path) {
+            Ok(file) => file,
+            Err(error) => {
+                eprintln!("Could not read the state from {}: {error}", path.display());
+                return
+            }
+        };
+
+        let previous = (emulation.save_state(), emulation.screen());
+        match emulation.load_state(&file.state, &file.screen) {
+            Ok(()) => self.undo = Some(previous),
+            Err(error) => eprintln!("Could not load the state from {}: {error}", path.display()),
+        }
+    }
+
+    fn undo_load(&mut self, emulation: &mut EmulationState) {
+        if let Some((state, screen)) = self.undo.take()
+            && let Err(error) = emulation.load_state(&state, &screen) {
+            eprintln!("Could not undo the state load: {error}");
+        }
+    }
+
+    pub fn menu(&mut self, ui: &mut egui::Ui, emulation: &mut EmulationState) {
+        ui.add_enabled_ui(self.save_path.is_some(), |ui| {
+            ui.menu_button("Save State", |ui| {
+                for slot in 0..SLOT_COUNT {
+                    let button = egui::Button::new(self.slot_label(slot))
+                        .shortcut_text(ui.ctx().format_shortcut(&save_shortcut(slot)));
+                    if ui.add(button).clicked() {
+                        self.save(ui.ctx(), emulation, slot);
+                    }
+                }
+            });
+
+            ui.menu_button("Load State", |ui| {
+                for slot in 0..SLOT_COUNT {
+                    let button = egui::Button::new(self.slot_label(slot))
+                        .shortcut_text(ui.ctx().format_shortcut(&load_shortcut(slot)));
+                    if ui.add_enabled(self.slots[slot].is_some(), button).clicked() {
+                        self.load(emulation, slot);
+                    }
+                }
+            });
+
+            let undo = egui::Button::new("Undo Load State")
+                .shortcut_text(ui.ctx().format_shortcut(&UNDO_LOAD));
+            if ui.add_enabled(self.undo.is_some(), undo).clicked() {
+                self.undo_load(emulation);
+            }
+
+            if ui.button("Save States...").clicked() {
+                self.display_window = true;
+            }
+        });
+    }
+
+    fn slot_label(&self, slot: usize) -> String {
+        match &self.slots[slot] {
+            Some(info) => format!("Slot {}: {}", slot + 1, saved_ago(info.timestamp)),
+            None => format!("Slot {}: Empty", slot + 1),
+        }
+    }
+
+    /// The slots with their thumbnails, 4 by row
+    pub fn render(&mut self, ctx: &egui::Context, emulation: &mut EmulationState) {
+        if !self.display_window || self.save_path.is_none() {
+            return
+        }
+
+        let mut open = true;
+        let mut action = None;
+        egui::Window::new("Save States")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                egui::Grid::new("save_slots").spacing((12.0, 12.0)).show(ui, |ui| {
+                    for slot in 0..SLOT_COUNT {
+                        ui.vertical(|ui| {
+                            let size = egui::vec2(XRES as f32, YRES as f32);
+                            match &self.slots[slot] {
+                                Some(info) => {
+                                    ui.add(egui::Image::new(&info.thumbnail).fit_to_exact_size(size));
+                                }
+                                None => {
+                                    let (rect, _) = ui.allocate_exact_size(size, egui::Sense::hover());
+                                    ui.painter().rect_filled(rect, 0.0, ui.visuals().extreme_bg_color);
+                                }
+                            }
+                            ui.label(self.slot_label(slot));
+
+                            ui.horizontal(|ui| {
+                                if ui.button("Save").clicked() {
+                                    action = Some((slot, true));
+                                }
+                                if ui.add_enabled(self.slots[slot].is_some(), egui::Button::new("Load")).clicked() {
+                                    action = Some((slot, false));
+                                }
+                            });
+                        });
+
+                        if slot % 4 == 3 {
+                            ui.end_row();
+                        }
+                    }
+                });
+            });
+
+        match action {
+            Some((slot, true)) => self.save(ctx, emulation, slot),
+            Some((slot, false)) => self.load(emulation, slot),
+            None => (),
+        }
+        self.display_window = open;
+    }
+}
+
+// The timestamps are shown relative to now, in the largest unit
+fn saved_ago(timestamp: SystemTime) -> String {
+    let seconds = timestamp.elapsed().unwrap_or_default().as_secs();
+    let (count, unit) = match seconds {
+        0..60 => return "just now".to_string(),
+        60..3600 => (seconds / 60, "minute"),
+        3600..86400 => (seconds / 3600, "hour"),
+        _ => (seconds / 86400, "day"),
+    };
+    format!("{count} {unit}{} ago", if count > 1 { "s" } else { "" })
+}
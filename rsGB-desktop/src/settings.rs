@@ -1,28 +1,57 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, path::{Path, PathBuf}};
 
 use eframe::egui::{self, Key};
+use gilrs::Button as PadButton;
 
 use rsgb_core::{
     Button,
-    settings::Settings,
+    settings::{FrameBlending, Settings},
 };
 
 mod bindings;
+mod config;
 mod save_location;
+mod palette;
+
+use crate::{filters::VideoFilter, gamepad::{Gamepads, default_gamepad_map}};
 
 use bindings::bindings_widget;
 use save_location::save_location_widget;
+use palette::palette_widget;
 
 pub const XRES: usize = 160;
 pub const YRES: usize = 144;
 
+const MAX_RECENT_FILES: usize = 10;
+
 pub const FRAME_SIZE: usize = XRES as usize * YRES as usize;
 
 pub struct AppSettings {
     pub(crate) emu_settings: Settings,
     key_map: HashMap<Key, Button>,
 
+    // The bindings of each controller, by name. The others use the default ones
+    gamepad_maps: HashMap<String, HashMap<PadButton, Button>>,
+    default_gamepad_map: HashMap<PadButton, Button>,
+    gamepad_deadzone: f32, // The fraction of the stick range ignored
+    pub(crate) video_filter: VideoFilter,
+    window_size: [f32; 2],
+
+    recent_files: Vec<PathBuf>, // The last opened first
+
+    // The frame blending chosen for each game, by ROM file name
+    game_blending: HashMap<String, FrameBlending>,
+    current_game: Option<String>,
+
     awaiting_input: Option<Button>,
+    awaiting_gamepad: Option<Button>,
+    selected_gamepad: Option<String>,
+}
+
+impl Default for AppSettings {
+    fn default() -> AppSettings {
+        AppSettings::new()
+    }
 }
 
 impl AppSettings {
@@ -42,7 +71,21 @@ impl AppSettings {
             emu_settings: Settings::default(),
             key_map,
 
+            gamepad_maps: HashMap::new(),
+            default_gamepad_map: default_gamepad_map(),
+            gamepad_deadzone: 0.3,
+
+            video_filter: VideoFilter::None,
+            window_size: [900.0, 675.0],
+
+            recent_files: Vec::new(),
+
+            game_blending: HashMap::new(),
+            current_game: None,
+
             awaiting_input: None,
+            awaiting_gamepad: None,
+            selected_gamepad: None,
         }
     }
 
@@ -50,11 +93,73 @@ impl AppSettings {
         &self.emu_settings
     }
 
+    pub fn recent_files(&self) -> &[PathBuf] {
+        &self.recent_files
+    }
+
+    /// Moves the file to the top of the recent files
+    pub fn add_recent_file(&mut self, path: &Path) {
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+
+        self.recent_files.retain(|file| *file != path);
+        self.recent_files.insert(0, path);
+        self.recent_files.truncate(MAX_RECENT_FILES);
+    }
+
+    pub fn remove_recent_file(&mut self, path: &Path) {
+        self.recent_files.retain(|file| file != path);
+    }
+
+    pub fn clear_recent_files(&mut self) {
+        self.recent_files.clear();
+    }
+
+    /// Restores the settings chosen for this game
+    pub fn set_current_game(&mut self, rom_path: &Path) {
+        let game = rom_path.file_name().unwrap_or_default().to_string_lossy().to_string();
+
+        self.emu_settings.frame_blending = self.game_blending.get(&game)
+            .copied()
+            .unwrap_or(FrameBlending::Off);
+        self.current_game = Some(game);
+    }
+
+    /// The frame blending is remembered for the current game
+    pub fn set_frame_blending(&mut self, frame_blending: FrameBlending) {
+        self.emu_settings.frame_blending = frame_blending;
+
+        if let Some(game) = &self.current_game {
+            self.game_blending.insert(game.clone(), frame_blending);
+        }
+    }
+
+    pub fn window_size(&self) -> [f32; 2] {
+        self.window_size
+    }
+
+    pub fn set_window_size(&mut self, width: f32, height: f32) {
+        self.window_size = [width, height];
+    }
+
     pub fn key_map(&self) -> &HashMap<Key, Button> {
         &self.key_map
     }
 
-    pub fn render(&mut self, ctx: &egui::Context) -> bool {
+    pub fn gamepad_map(&self, controller: &str) -> &HashMap<PadButton, Button> {
+        self.gamepad_maps.get(controller).unwrap_or(&self.default_gamepad_map)
+    }
+
+    // Starts from the default bindings the first time
+    fn gamepad_map_mut(&mut self, controller: &str) -> &mut HashMap<PadButton, Button> {
+        self.gamepad_maps.entry(controller.to_string())
+            .or_insert_with(|| self.default_gamepad_map.clone())
+    }
+
+    pub fn gamepad_deadzone(&self) -> f32 {
+        self.gamepad_deadzone
+    }
+
+    pub fn render(&mut self, ctx: &egui::Context, gamepads: Option<&Gamepads>) -> bool {
         let mut stay_open = true;
 
         egui::CentralPanel::default().show(ctx, |ui| {
@@ -68,10 +173,12 @@ impl AppSettings {
                 .min_col_width(200.0)
                 .max_col_width(200.0)
                 .show(ui, |ui| {
-                    bindings_widget(self, ui);
+                    bindings_widget(self, gamepads, ui);
 
                     save_location_widget(self, ui);
 
+                    palette_widget(self, ui);
+
                     ui.end_row();
                     
                 });
@@ -1,14 +1,35 @@
 use eframe::egui;
 use rsgb_core::Button;
 
-pub fn bindings_widget(settings: &mut super::AppSettings, ui: &mut egui::Ui) {
+use crate::gamepad::Gamepads;
+
+pub fn bindings_widget(settings: &mut super::AppSettings, gamepads: Option<&Gamepads>, ui: &mut egui::Ui) {
     ui.vertical(|ui| {
         ui.label("Button binding");
 
+        // The controller bindings shown are the ones of the selected controller
+        let names = gamepads.map(Gamepads::names).unwrap_or_default();
+        if settings.selected_gamepad.as_ref().is_none_or(|name| !names.contains(name)) {
+            settings.selected_gamepad = names.first().cloned();
+            settings.awaiting_gamepad = None;
+        }
+
+        if let Some(selected) = settings.selected_gamepad.clone() {
+            egui::ComboBox::from_id_salt("gamepad")
+                .selected_text(&selected)
+                .show_ui(ui, |ui| {
+                    for name in names {
+                        ui.selectable_value(&mut settings.selected_gamepad, Some(name.clone()), name);
+                    }
+                });
+        } else {
+            ui.label("No controller connected");
+        }
+
         let buttons = [Button::A, Button::B, Button::DOWN, Button::LEFT, Button::RIGHT, Button::SELECT, Button::START, Button::UP];
                 
         egui::Grid::new("bindings")
-            .num_columns(2)
+            .num_columns(3)
             .show(ui, |ui| {
                 for button in buttons {
                     ui.label(format!("{:?}", button));
@@ -44,6 +65,10 @@ pub fn bindings_widget(settings: &mut super::AppSettings, ui: &mut egui::Ui) {
                             settings.awaiting_input = Some(button);
                         }
                     }
+
+                    if let Some(gamepad) = settings.selected_gamepad.clone() {
+                        gamepad_binding(settings, &gamepad, gamepads, button, ui);
+                    }
                     ui.end_row();
                 }
             });
@@ -51,5 +76,44 @@ pub fn bindings_widget(settings: &mut super::AppSettings, ui: &mut egui::Ui) {
         if settings.awaiting_input.is_some() {
             ui.label(egui::RichText::new("Press a key to bind it...").color(egui::Color32::YELLOW));
         }
+        if settings.awaiting_gamepad.is_some() {
+            ui.label(egui::RichText::new("Press a controller button to bind it...").color(egui::Color32::YELLOW));
+        }
+
+        ui.add(egui::Slider::new(&mut settings.gamepad_deadzone, 0.05..=0.95).text("Stick deadzone"));
     });
-}
\ No newline at end of file
+}
+
+// Works like the keys, with the buttons pressed on the selected controller
+fn gamepad_binding(settings: &mut super::AppSettings, gamepad: &str, gamepads: Option<&Gamepads>, button: Button, ui: &mut egui::Ui) {
+    let bound_button = settings.gamepad_map(gamepad).iter()
+        .find(|(_, v)| **v == button)
+        .map(|(&k, _)| k);
+
+    if settings.awaiting_gamepad == Some(button) {
+        if ui.button("Wait for button...").clicked() {
+            settings.awaiting_gamepad = None; // Cancel if clicked again
+        }
+
+        let pressed = gamepads.and_then(|gamepads| {
+            gamepads.pressed().iter().find(|(name, _)| name == gamepad).map(|&(_, pad_button)| pad_button)
+        });
+
+        if let Some(pad_button) = pressed {
+            let map = settings.gamepad_map_mut(gamepad);
+            map.retain(|_, &mut v| v != button);
+            map.insert(pad_button, button);
+
+            settings.awaiting_gamepad = None;
+        }
+    } else {
+        let btn_text = match bound_button {
+            Some(b) => format!("{:?}", b),
+            None => "Unbound".to_string(),
+        };
+
+        if ui.button(btn_text).clicked() {
+            settings.awaiting_gamepad = Some(button);
+        }
+    }
+}
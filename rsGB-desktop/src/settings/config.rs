@@ -0,0 +1,259 @@
+use std::{collections::HashMap, fs, io, path::PathBuf};
+
+use eframe::egui::Key;
+use rsgb_core::{
+    Button,
+    settings::{AudioFilter, DmgPalette, FrameBlending, SaveLocation, SpeedOption},
+};
+use toml_edit::{Array, DocumentMut, Item, Table, Value, value};
+
+use crate::{filters::VideoFilter, gamepad::{pad_button_from_name, pad_button_name}};
+
+use super::AppSettings;
+
+const CONFIG_FILE: &str = "settings.toml";
+
+const BUTTON_NAMES: [(&str, Button); 8] = [
+    ("A", Button::A),
+    ("B", Button::B),
+    ("START", Button::START),
+    ("SELECT", Button::SELECT),
+    ("UP", Button::UP),
+    ("DOWN", Button::DOWN),
+    ("LEFT", Button::LEFT),
+    ("RIGHT", Button::RIGHT),
+];
+
+impl AppSettings {
+    /// Loads the settings saved in the config directory. The missing or
+    /// invalid values keep their default, and the unknown ones are ignored
+    pub fn load() -> AppSettings {
+        let mut settings = AppSettings::new();
+
+        let Some(path) = config_path() else {
+            return settings
+        };
+
+        match fs::read_to_string(&path) {
+            Ok(text) => match text.parse::<DocumentMut>() {
+                Ok(document) => settings.apply_config(&document),
+                Err(error) => eprintln!("Could not parse {}: {error}", path.display()),
+            },
+            Err(error) if error.kind() == io::ErrorKind::NotFound => (),
+            Err(error) => eprintln!("Could not read {}: {error}", path.display()),
+        }
+        settings
+    }
+
+    /// Writes the settings in the config directory
+    pub fn save(&self) {
+        let Some(path) = config_path() else {
+            return
+        };
+
+        let result = path.parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| fs::write(&path, self.to_config()));
+
+        if let Err(error) = result {
+            eprintln!("Could not save the settings to {}: {error}", path.display());
+        }
+    }
+
+    fn apply_config(&mut self, document: &DocumentMut) {
+        let settings = &mut self.emu_settings;
+
+        if let Some(speed) = document.get("speed").and_then(Item::as_integer) {
+            settings.speed = match speed {
+                2 => SpeedOption::X2,
+                3 => SpeedOption::X3,
+                4 => SpeedOption::X4,
+                _ => SpeedOption::Normal,
+            };
+        }
+
+        if let Some(folder) = document.get("save_folder").and_then(Item::as_str) {
+            settings.save_location = SaveLocation::SaveFolder(PathBuf::from(folder));
+        }
+
+        if let Some(name) = document.get("audio_filter").and_then(Item::as_str) {
+            match name {
+                "DMG" => settings.audio_filter = AudioFilter::DMG,
+                "CGB" => settings.audio_filter = AudioFilter::CGB,
+                "Raw" => settings.audio_filter = AudioFilter::Raw,
+                _ => (),
+            }
+        }
+
+        for (key, channels) in [("muted_channels", &mut settings.muted_channels), ("solo_channels", &mut settings.solo_channels)] {
+            if let Some(values) = document.get(key).and_then(Item::as_array) {
+                for (channel, value) in channels.iter_mut().zip(values) {
+                    *channel = value.as_bool().unwrap_or(*channel);
+                }
+            }
+        }
+
+        if let Some(palette) = document.get("palette").and_then(Item::as_table_like) {
+            let DmgPalette { bg, obj0, obj1 } = &mut settings.palette;
+
+            for (key, colors) in [("bg", bg), ("obj0", obj0), ("obj1", obj1)] {
+                if let Some(values) = palette.get(key).and_then(Item::as_array) {
+                    for (color, value) in colors.iter_mut().zip(values) {
+                        if let Some(rgb) = value.as_integer() && (0..=0xFFFFFF).contains(&rgb) {
+                            *color = rgb as u32;
+                        }
+                    }
+                }
+            }
+        }
+
+        if let Some(name) = document.get("video_filter").and_then(Item::as_str)
+            && let Some(filter) = VideoFilter::ALL.into_iter().find(|filter| filter.name() == name) {
+            self.video_filter = filter;
+        }
+
+        if let Some(files) = document.get("recent_files").and_then(Item::as_array) {
+            self.recent_files = files.iter()
+                .filter_map(|file| file.as_str())
+                .map(PathBuf::from)
+                .take(super::MAX_RECENT_FILES)
+                .collect();
+        }
+
+        if let Some(size) = document.get("window_size").and_then(Item::as_array)
+            && let [Some(width), Some(height)] = [size.get(0), size.get(1)].map(|value| value.and_then(|v| v.as_float()))
+            && width > 0.0 && height > 0.0 {
+            self.window_size = [width as f32, height as f32];
+        }
+
+        // A key bound in the file replaces the default one of its button
+        if let Some(keys) = document.get("keys").and_then(Item::as_table_like) {
+            for (name, item) in keys.iter() {
+                let button = BUTTON_NAMES.iter().find(|(button_name, _)| *button_name == name);
+
+                if let Some(&(_, button)) = button
+                    && let Some(key) = item.as_str().and_then(Key::from_name) {
+                    self.key_map.retain(|&k, &mut b| k != key && b != button);
+                    self.key_map.insert(key, button);
+                }
+            }
+        }
+
+        if let Some(deadzone) = document.get("gamepad_deadzone").and_then(Item::as_float) {
+            self.gamepad_deadzone = (deadzone as f32).clamp(0.0, 1.0);
+        }
+
+        // The bindings of a controller replace all the default ones
+        if let Some(gamepads) = document.get("gamepads").and_then(Item::as_table_like) {
+            for (gamepad, item) in gamepads.iter() {
+                let Some(bindings) = item.as_table_like() else {
+                    continue
+                };
+
+                let mut map = HashMap::new();
+                for (name, item) in bindings.iter() {
+                    let button = BUTTON_NAMES.iter().find(|(button_name, _)| *button_name == name);
+
+                    if let Some(&(_, button)) = button
+                        && let Some(pad_button) = item.as_str().and_then(pad_button_from_name) {
+                        map.retain(|_, &mut b| b != button);
+                        map.insert(pad_button, button);
+                    }
+                }
+                self.gamepad_maps.insert(gamepad.to_string(), map);
+            }
+        }
+
+        // Either "Off", "Mix" or the persistence of the ghosting
+        if let Some(games) = document.get("frame_blending").and_then(Item::as_table_like) {
+            for (game, item) in games.iter() {
+                let blending = match (item.as_str(), item.as_float()) {
+                    (Some("Off"), _) => FrameBlending::Off,
+                    (Some("Mix"), _) => FrameBlending::Mix,
+                    (_, Some(persistence)) => FrameBlending::Ghosting((persistence as f32).clamp(0.0, 1.0)),
+                    _ => continue,
+                };
+                self.game_blending.insert(game.to_string(), blending);
+            }
+        }
+    }
+
+    fn to_config(&self) -> String {
+        let settings = &self.emu_settings;
+        let mut document = DocumentMut::new();
+
+        document["speed"] = value(settings.speed as u8 as i64);
+        if let SaveLocation::SaveFolder(folder) = &settings.save_location {
+            document["save_folder"] = value(folder.to_string_lossy().as_ref());
+        }
+
+        let audio_filter = match settings.audio_filter {
+            AudioFilter::DMG => "DMG",
+            AudioFilter::CGB => "CGB",
+            AudioFilter::Raw => "Raw",
+        };
+        document["audio_filter"] = value(audio_filter);
+        document["muted_channels"] = value(Array::from_iter(settings.muted_channels));
+        document["solo_channels"] = value(Array::from_iter(settings.solo_channels));
+
+        document["video_filter"] = value(self.video_filter.name());
+        let recent_files = self.recent_files.iter().map(|file| file.to_string_lossy().into_owned());
+        document["recent_files"] = value(Array::from_iter(recent_files));
+
+        document["gamepad_deadzone"] = value(float(self.gamepad_deadzone));
+        document["window_size"] = value(Array::from_iter(self.window_size.map(float)));
+
+        let mut palette = Table::new();
+        for (key, colors) in [("bg", settings.palette.bg), ("obj0", settings.palette.obj0), ("obj1", settings.palette.obj1)] {
+            // The colors are parsed from their text to be written in hexadecimal
+            let colors = colors.map(|color| format!("0x{color:06X}").parse::<Value>().unwrap());
+            palette[key] = value(Array::from_iter(colors));
+        }
+        document["palette"] = Item::Table(palette);
+
+        let mut keys = Table::new();
+        for (name, button) in BUTTON_NAMES {
+            if let Some((key, _)) = self.key_map.iter().find(|(_, b)| **b == button) {
+                keys[name] = value(key.name());
+            }
+        }
+        document["keys"] = Item::Table(keys);
+
+        // Only the table of each controller has a header
+        let mut gamepads = Table::new();
+        gamepads.set_implicit(true);
+        for (gamepad, map) in &self.gamepad_maps {
+            let mut bindings = Table::new();
+            for (name, button) in BUTTON_NAMES {
+                if let Some((pad_button, _)) = map.iter().find(|(_, b)| **b == button) {
+                    bindings[name] = value(pad_button_name(*pad_button));
+                }
+            }
+            gamepads[gamepad.as_str()] = Item::Table(bindings);
+        }
+        gamepads.sort_values();
+        document["gamepads"] = Item::Table(gamepads);
+
+        let mut games = Table::new();
+        for (game, blending) in &self.game_blending {
+            games[game.as_str()] = match blending {
+                FrameBlending::Off => value("Off"),
+                FrameBlending::Mix => value("Mix"),
+                FrameBlending::Ghosting(persistence) => value(float(*persistence)),
+            };
+        }
+        games.sort_values();
+        document["frame_blending"] = Item::Table(games);
+
+        document.to_string()
+    }
+}
+
+// The f32 values are written with their shortest representation
+fn float(value: f32) -> f64 {
+    value.to_string().parse().unwrap_or_default()
+}
+
+fn config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("rsGB").join(CONFIG_FILE))
+}
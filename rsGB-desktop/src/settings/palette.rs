@@ -0,0 +1,39 @@
+use eframe::egui;
+use rsgb_core::settings::PalettePreset;
+
+use crate::settings::AppSettings;
+
+pub fn palette_widget(settings: &mut AppSettings, ui: &mut egui::Ui) {
+    let palette = &mut settings.emu_settings.palette;
+
+    ui.vertical(|ui| {
+        ui.label("Color Palette");
+
+        let selected = palette.preset().map_or("Custom", |preset| preset.name());
+        egui::ComboBox::from_id_salt("palette_preset")
+            .selected_text(selected)
+            .show_ui(ui, |ui| {
+                for preset in PalettePreset::ALL {
+                    if ui.selectable_label(palette.preset() == Some(preset), preset.name()).clicked() {
+                        *palette = preset.palette();
+                    }
+                }
+            });
+
+        // Editing any color makes the palette custom
+        egui::Grid::new("palette_colors")
+            .num_columns(5)
+            .show(ui, |ui| {
+                for (name, colors) in [("BG", &mut palette.bg), ("OBJ0", &mut palette.obj0), ("OBJ1", &mut palette.obj1)] {
+                    ui.label(name);
+                    for color in colors.iter_mut() {
+                        let mut rgb = [(*color >> 16) as u8, (*color >> 8) as u8, *color as u8];
+                        if ui.color_edit_button_srgb(&mut rgb).changed() {
+                            *color = u32::from_be_bytes([0, rgb[0], rgb[1], rgb[2]]);
+                        }
+                    }
+                    ui.end_row();
+                }
+            });
+    });
+}